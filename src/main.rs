@@ -6,10 +6,16 @@ use std::collections::HashMap;
 use util::per_window::PerWindow;
 
 use crate::experimental::Antistatic;
-use crate::util::helpers::{record_into_buffer, recreate_swapchain};
+use crate::util::helpers::{record_into_buffer, recreate_swapchain, DynamicRendering};
+use crate::util::chain::describe;
+use crate::util::config::Config;
+use crate::util::interface::shader_interface;
 use crate::util::logging::{ConsoleLogger, UnwrapLog};
 use crate::util::per_window::WindowBuilder;
 use crate::util::swapchain::PerSwapchain;
+use crate::util::upload::TransferQueue;
+use crate::util::scale;
+use crate::util::capture::{describe_capture, Capture, CaptureDesc, CaptureFormat};
 use ash::vk::{Handle, PFN_vkAllocateMemory};
 use ash::Instance;
 use ash::{ext, vk};
@@ -46,7 +52,9 @@ use rand::Rng;
 #[cfg(target_os = "linux")]
 use drm_fourcc::{DrmFormat, DrmFourcc, DrmModifier};
 #[cfg(target_os = "linux")]
-use pipewire::properties::properties;
+use crate::platform::linux::capture::{self, FrameData, Negotiated};
+#[cfg(target_os = "linux")]
+use crate::platform::linux::session::{CaptureEvent, CaptureNode, CaptureSession};
 
 const APPLICATION_TITLE: &str = "EMBER";
 const WINDOW_COUNT: usize = 1;
@@ -75,23 +83,29 @@ const OPTIONAL_EXTENSIONS: [&CStr; 6] = [
 ];
 const REQUIRED_DEVICE_EXTENSIONS: [&CStr; 1] = [
     khr::swapchain::NAME,];
-const OPTIONAL_DEVICE_EXTENSIONS: [&CStr; 8] = [
+const OPTIONAL_DEVICE_EXTENSIONS: [&CStr; 12] = [
     ext::device_address_binding_report::NAME,
     khr::external_memory_fd::NAME,
+    khr::external_semaphore_fd::NAME,
     khr::external_memory::NAME,
     ext::external_memory_dma_buf::NAME,
+    ext::queue_family_foreign::NAME,
 
     ext::image_drm_format_modifier::NAME,
 
     khr::bind_memory2::NAME,
     khr::sampler_ycbcr_conversion::NAME,
     khr::image_format_list::NAME,
+
+    khr::synchronization2::NAME,
+    khr::dynamic_rendering::NAME,
 ];
 
 static OPT_EXT_LOCK: Mutex<Vec<&CStr>> = Mutex::new(vec![]);
 
 
 const SCREENSHARE: bool = false;
+const CONFIG_PATH: &str = "ember.conf";
 const MV_SIZE: (usize, usize) = (312,372);
 const MV_BUF_SIZE: usize = MV_SIZE.0 * MV_SIZE.1;   //116064
 
@@ -126,6 +140,7 @@ fn main() -> Result<(),Box<dyn Error>>
     unsafe { env::set_var("COLORTERM","truecolor"); }
     log::set_logger(&LOGGER)?;
     log::set_max_level(LevelFilter::Trace);
+    let args = Args::parse();
 
     #[cfg(target_os = "linux")]
     unsafe {
@@ -332,11 +347,14 @@ fn main() -> Result<(),Box<dyn Error>>
         queue_families.iter().enumerate().filter_map(|(usize,&properties)| {
             properties.queue_flags.contains(vk::QueueFlags::GRAPHICS).then_some(usize)
         }).next().unwrap()} as u32;
-    let device_queue_create_info = vk::DeviceQueueCreateInfo {
-        queue_family_index,
-        queue_count: 1,
-        p_queue_priorities: &1f32,
-        ..Default::default()};
+    //uploads go through a transfer-only family if there is one, see util::upload
+    let transfer_family = unsafe { TransferQueue::dedicated_family(phys_device) };
+    let device_queue_create_infos: Vec<vk::DeviceQueueCreateInfo> = [Some(queue_family_index), transfer_family].into_iter().flatten()
+        .map(|queue_family_index| vk::DeviceQueueCreateInfo {
+            queue_family_index,
+            queue_count: 1,
+            p_queue_priorities: &1f32,
+            ..Default::default()}).collect();
     let mut address_debug_info: Option<vk::PhysicalDeviceAddressBindingReportFeaturesEXT> = None;
     if !opt_device_ext_lock.contains(&ext::device_address_binding_report::NAME) {
         address_debug_info = Some(
@@ -346,20 +364,73 @@ fn main() -> Result<(),Box<dyn Error>>
 
     let device_features = phys_device_features.clone().sampler_anisotropy(true);
 
-    let device_create_info = vk::DeviceCreateInfo {
-        p_next: if let Some(mut address_debug_info) = address_debug_info {
-            ptr::from_ref(&mut address_debug_info).cast() } else { ptr::null() },
-        queue_create_info_count: 1,
-        p_queue_create_infos: &device_queue_create_info,
+    //the extension alone isn't enough, the feature has to be there (and get enabled) too
+    let synchronization2 = !opt_device_ext_lock.contains(&khr::synchronization2::NAME) && unsafe {
+        let mut sync2_features = vk::PhysicalDeviceSynchronization2Features::default();
+        let mut features2 = vk::PhysicalDeviceFeatures2::default().push_next(&mut sync2_features);
+        INSTANCE.get_physical_device_features2(phys_device, &mut features2);
+        sync2_features.synchronization2 == vk::TRUE };
+    if !synchronization2 { warn!("synchronization2 is unavailable, the render graph falls back to plain pipeline barriers") }
+    let mut sync2_features = vk::PhysicalDeviceSynchronization2Features {
+        synchronization2: vk::TRUE,
+        ..Default::default()};
+
+    //for sampling YUV captures as RGB, see util::capture
+    let ycbcr_conversion = !opt_device_ext_lock.contains(&khr::sampler_ycbcr_conversion::NAME) && unsafe {
+        let mut ycbcr_features = vk::PhysicalDeviceSamplerYcbcrConversionFeatures::default();
+        let mut features2 = vk::PhysicalDeviceFeatures2::default().push_next(&mut ycbcr_features);
+        INSTANCE.get_physical_device_features2(phys_device, &mut features2);
+        ycbcr_features.sampler_ycbcr_conversion == vk::TRUE };
+    if !ycbcr_conversion { warn!("sampler YCbCr conversion is unavailable, YUV captures won't be offered") }
+    let mut ycbcr_features = vk::PhysicalDeviceSamplerYcbcrConversionFeatures {
+        sampler_ycbcr_conversion: vk::TRUE,
+        ..Default::default()};
+
+    //core since 1.3, the extension is for drivers older than that. same feature struct either way.
+    let dynamic_rendering_core = phys_device_properties.api_version >= vk::API_VERSION_1_3;
+    let dynamic_rendering = (dynamic_rendering_core || !opt_device_ext_lock.contains(&khr::dynamic_rendering::NAME)) && unsafe {
+        let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeatures::default();
+        let mut features2 = vk::PhysicalDeviceFeatures2::default().push_next(&mut dynamic_rendering_features);
+        INSTANCE.get_physical_device_features2(phys_device, &mut features2);
+        dynamic_rendering_features.dynamic_rendering == vk::TRUE };
+    if !dynamic_rendering { warn!("dynamic rendering is unavailable, falling back to render passes and framebuffers") }
+    let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeatures {
+        dynamic_rendering: vk::TRUE,
+        ..Default::default()};
+
+    let mut device_create_info = vk::DeviceCreateInfo {
+        queue_create_info_count: device_queue_create_infos.len() as u32,
+        p_queue_create_infos: device_queue_create_infos.as_ptr(),
         enabled_extension_count: phys_device_extensions.len() as u32,
         pp_enabled_extension_names: phys_device_extensions.as_ptr(),
         p_enabled_features: &device_features,
         ..Default::default()};
+    if let Some(address_debug_info) = address_debug_info.as_mut() {
+        device_create_info = device_create_info.push_next(address_debug_info) }
+    if synchronization2 {
+        device_create_info = device_create_info.push_next(&mut sync2_features) }
+    if dynamic_rendering {
+        device_create_info = device_create_info.push_next(&mut dynamic_rendering_features) }
+    if ycbcr_conversion {
+        device_create_info = device_create_info.push_next(&mut ycbcr_features) }
     info!("Creating logical device over physical device {}",phys_device_properties.device_name_as_c_str()?.to_str()?.bright_purple());
     let device = unsafe { INSTANCE.create_device(phys_device, &device_create_info, None).log() };
     let queue = unsafe { device.get_device_queue(queue_family_index, 0) };
-
-
+    let transfer = TransferQueue {
+        queue: transfer_family.map_or(queue, |family| unsafe { device.get_device_queue(family, 0) }),
+        family: transfer_family.unwrap_or(queue_family_index),
+        graphics_family: queue_family_index };
+
+
+    //having the extension doesn't mean the driver takes sync fds, the capture's acquire fences come as one
+    let sync_fd_import = unsafe {
+        let info = vk::PhysicalDeviceExternalSemaphoreInfo::default()
+            .handle_type(vk::ExternalSemaphoreHandleTypeFlags::SYNC_FD);
+        let mut properties = vk::ExternalSemaphoreProperties::default();
+        INSTANCE.get_physical_device_external_semaphore_properties(phys_device, &info, &mut properties);
+        properties.external_semaphore_features.contains(vk::ExternalSemaphoreFeatureFlags::IMPORTABLE) };
+    if !sync_fd_import && !opt_device_ext_lock.contains(&khr::external_semaphore_fd::NAME) {
+        warn!("sync fds can't be imported as semaphores, not synchronizing captures with the compositor") }
 
     let extension_holder = {
         let opt_ext_lock = OPT_EXT_LOCK.lock()?;
@@ -382,6 +453,8 @@ fn main() -> Result<(),Box<dyn Error>>
                 ext::acquire_drm_display::Instance::new(&ENTRY,&INSTANCE)),
             extmem_fd: (!opt_ext_lock.contains(&khr::external_memory_fd::NAME)).then(||
                 khr::external_memory_fd::Device::new(&INSTANCE,&device)),
+            extsem_fd: (sync_fd_import && !opt_device_ext_lock.contains(&khr::external_semaphore_fd::NAME)).then(||
+                khr::external_semaphore_fd::Device::new(&INSTANCE,&device)),
             extmem_caps: (!opt_ext_lock.contains(&khr::external_memory_capabilities::NAME)).then(||
                 khr::external_memory_capabilities::Instance::new(&ENTRY,&INSTANCE)),
             image_drm_format_modifier: (!opt_ext_lock.contains(&ext::image_drm_format_modifier::NAME)).then(||
@@ -391,8 +464,13 @@ fn main() -> Result<(),Box<dyn Error>>
                 khr::bind_memory2::Device::new(&INSTANCE,&device)),
             get_physical_device_properties2: (!opt_ext_lock.contains(&khr::get_physical_device_properties2::NAME)).then(||
                 khr::get_physical_device_properties2::Instance::new(&ENTRY, &INSTANCE)),
-            sampler_ycbcr_conversion: (!opt_ext_lock.contains(&khr::sampler_ycbcr_conversion::NAME)).then(||
+            sampler_ycbcr_conversion: ycbcr_conversion.then(||
                 khr::sampler_ycbcr_conversion::Device::new(&INSTANCE,&device)),
+            synchronization2: synchronization2.then(||
+                khr::synchronization2::Device::new(&INSTANCE,&device)),
+            dynamic_rendering: dynamic_rendering.then(|| match dynamic_rendering_core {
+                true => DynamicRendering::Core,
+                false => DynamicRendering::Khr(khr::dynamic_rendering::Device::new(&INSTANCE,&device)) }),
         }};
 
     let command_pool_info = vk::CommandPoolCreateInfo {
@@ -418,266 +496,55 @@ fn main() -> Result<(),Box<dyn Error>>
 
 
 
-    let mut holder = SCHolder::default();
-    unsafe {
-        let fd = Arc::new(OnceLock::<i32>::new());
-        let fd_clone = fd.clone();
-        let fn_pw = || {
-                use pipewire;
-                use portal_screencast_waycap;
-                use pipewire::spa;
-
-                let (pw_sender, pw_recv) = pipewire::channel::channel::<()>();
-
-                let screencast = portal_screencast_waycap::ScreenCast::new()?
-                    .start(None)?;
-
-                let pipewire_fd = screencast.pipewire_fd() as i32;
-                let screencast_stream = screencast.streams().next().unwrap();
-                let stream_node = screencast_stream.pipewire_node();
-
-                let pw_loop = Arc::new(pipewire::main_loop::MainLoopBox::new(None)?);
-                let pw_loop_clone = pw_loop.clone();
-                let pw_context = pipewire::context::ContextBox::new(pw_loop.loop_(), None)?;
-                let pw_core = pw_context.connect_fd(OwnedFd::from_raw_fd(pipewire_fd), None)?;
-                let core_listener = pw_core
-                    .add_listener_local()
-                    .info(|i| log::debug!("VIDEO CORE:\n{i:#?}"))
-                    .error(|e, f, g, h| log::error!("{e},{f},{g},{h}"))
-                    .done(|d, _| log::debug!("DONE: {d}"))
-                    .register();
-                let mut stream = pipewire::stream::StreamBox::new(
-                    &*pw_core,
-                    "EMBER_CAPTURE",
-                    properties! {
-                    *pipewire::keys::MEDIA_TYPE => "Video",
-                    *pipewire::keys::MEDIA_CATEGORY => "Capture",
-                    *pipewire::keys::MEDIA_ROLE => "Screen",
-                })?;
-                let stream_listener = stream
-                    .add_local_listener::<()>()
-                    .state_changed(move |_, _, old, new| {
-                        info!("Video Stream State Changed: {old:?} -> {new:?}");
-                    })
-                    .param_changed(move |_, _, id, param| {
-                        let Some(param) = param else { return; };
-
-                        if id != spa::param::ParamType::Format.as_raw() { return; }
-
-                        let (media_type, media_subtype) =
-                            match spa::param::format_utils::parse_format(param) {
-                                Ok(v) => v,
-                                Err(_) => return,
-                            };
-
-                        if media_type != spa::param::format::MediaType::Video
-                            || media_subtype != spa::param::format::MediaSubtype::Raw
-                        { return; }
-                    })
-                    .process(move |stream, _| {
-                        match stream.dequeue_buffer() {
-                            None => debug!("out of buffers"),
-                            Some(mut buffer) => {
-                                let datas = buffer.datas_mut();
-                                if datas.is_empty() { return; }
-                                let data = &mut datas[0];
-                                let raw_data = data.as_raw();
-
-                                if data.type_() == spa::buffer::DataType::DmaBuf {
-                                    let _fd = raw_data.fd;
-                                    if _fd > 0 { fd_clone.set(_fd as i32).unwrap_or_default() }
-                                    //pw_sender.clone().send(()).unwrap();
-                                }
-                            }
-                        }
-                    })
-                    .register()?;
-
-
-                let pw_obj = spa::pod::object!(
-                spa::utils::SpaTypes::ObjectParamFormat,
-                spa::param::ParamType::EnumFormat,
-                spa::pod::property!(
-                    spa::param::format::FormatProperties::MediaType,
-                    Id,
-                    spa::param::format::MediaType::Video
-                ),
-                spa::pod::property!(
-                    spa::param::format::FormatProperties::MediaSubtype,
-                    Id,
-                    spa::param::format::MediaSubtype::Raw
-                ),
-                spa::pod::property!(
-                    spa::param::format::FormatProperties::VideoModifier,
-                    Long,
-                    0
-                ),
-                spa::pod::property!(
-                    spa::param::format::FormatProperties::VideoFormat,
-                    Choice,
-                    Enum,
-                    Id,
-                    spa::param::video::VideoFormat::NV12,
-                    spa::param::video::VideoFormat::I420,
-                    spa::param::video::VideoFormat::BGRA,
-                ),
-                spa::pod::property!(
-                    spa::param::format::FormatProperties::VideoSize,
-                    Choice,
-                    Range,
-                    Rectangle,
-                    spa::utils::Rectangle {
-                        width: 2560,
-                        height: 1440
-                    }, // Default
-                    spa::utils::Rectangle {
-                        width: 1,
-                        height: 1
-                    }, // Min
-                    spa::utils::Rectangle {
-                        width: 4096,
-                        height: 4096
-                    } // Max
-                ),
-                spa::pod::property!(
-                    spa::param::format::FormatProperties::VideoFramerate,
-                    Choice,
-                    Range,
-                    Fraction,
-                    spa::utils::Fraction { num: 240, denom: 1 }, // Default
-                    spa::utils::Fraction { num: 0, denom: 1 },   // Min
-                    spa::utils::Fraction { num: 244, denom: 1 }  // Max
-                ),
-            );
-
-                let video_spa_values: Vec<u8> = spa::pod::serialize::PodSerializer::serialize(
-                    std::io::Cursor::new(Vec::new()),
-                    &spa::pod::Value::Object(pw_obj),
-                )?.0.into_inner();
-
-                let mut video_params = [spa::pod::Pod::from_bytes(&video_spa_values).unwrap()];
-                stream.connect(
-                    spa::utils::Direction::Input,
-                    Some(stream_node),
-                    pipewire::stream::StreamFlags::AUTOCONNECT
-                        | pipewire::stream::StreamFlags::RT_PROCESS,
-                    &mut video_params)?;
-
-                let _recv = pw_recv.attach(pw_loop.loop_(), move |_| {
-                    debug!("Terminating video capture loop");
-                    pw_loop_clone.quit();
-                });
-
-                pw_loop.run();
-            Ok(())
+    let config = Config::load(CONFIG_PATH);
+    let capture_desc = describe_capture(&config);
+
+    //what the streams negotiated, one per source. see platform::linux::capture
+    let negotiated: Vec<Arc<Mutex<Negotiated>>> = (0..capture_desc.sources).map(|_| Arc::new(Mutex::new(Negotiated::default()))).collect();
+    let Args { nodes, reselect } = args;
+    let capturing = SCREENSHARE || !nodes.is_empty();
+    let mut session = None;
+    let sources = unsafe {
+        //only what the device can import gets offered, only shared memory without the extensions for it.
+        //  imported buffers get handed back and forth with the compositor, which takes the foreign queue family.
+        let dmabuf = !opt_device_ext_lock.contains(&ext::image_drm_format_modifier::NAME)
+            && !opt_device_ext_lock.contains(&khr::external_memory_fd::NAME)
+            && !opt_device_ext_lock.contains(&ext::external_memory_dma_buf::NAME)
+            && !opt_device_ext_lock.contains(&ext::queue_family_foreign::NAME);
+        if capturing && !dmabuf { warn!("Capture: DMA-BUFs can't be imported, the frames get copied out of shared memory") }
+        let offer = capture::offer(phys_device, extension_holder.sampler_ycbcr_conversion.is_some(), dmabuf);
+        let screenshare = capturing && !offer.is_empty();
+        //the images are made for whatever the streams settle on, which takes a format and a first buffer.
+        //  only the first source gets waited for, the others are placeholders until theirs come in.
+        let frame = match screenshare {
+            true => {
+                let session = session.insert(CaptureSession::new(offer, negotiated.clone(), reselect, nodes));
+                session.start()?;
+                loop {
+                    if let Some(frame) = negotiated[0].lock().unwrap().take() { break Some(frame) }
+                    //nothing's coming, it's the placeholder until the capture gets restarted
+                    if !session.running() {
+                        warn!("Capture: the stream ended before its first frame");
+                        break None }
+                    thread::sleep(Duration::from_millis(100));
+                } }
+            false => None
         };
-        if SCREENSHARE {
-            let _handle: thread::JoinHandle<Result<(), Box<dyn Error + Send + Sync>>> = thread::Builder::new()
-                .name("pipewire".to_owned())
-                .spawn(fn_pw)?;
-            while fd.get().is_none() {
-                thread::sleep(Duration::from_millis(100));
-            }
+        let mut sources = Vec::with_capacity(negotiated.len());
+        for idx in 0..negotiated.len() {
+            let format = frame.as_ref().filter(|_| idx == 0).map_or(CaptureFormat::default(), |frame| frame.format);
+            sources.push(Capture::new(&device, phys_device, queue, command_pool, extension_holder.extmem_fd.as_ref(),
+                                      extension_holder.extsem_fd.as_ref(), extension_holder.sampler_ycbcr_conversion.as_ref(), format)?);
         }
-
-        let mem_import_info = vk::ImportMemoryFdInfoKHR {
-            handle_type: vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT,
-            fd: *fd.get_or_init(||0),
-            ..Default::default()};
-        let mut mem_alloc_info = vk::MemoryAllocateInfo {
-            p_next: ptr::from_ref(&mem_import_info).cast(),
-            allocation_size: 1920*1200*4,   // todo! hardcoded shit
-            memory_type_index: 0,
-            ..Default::default()};
-
-        if !SCREENSHARE {
-            mem_alloc_info = vk::MemoryAllocateInfo {
-                p_next: ptr::null(),
-                allocation_size: 1920*1200*4,
-                memory_type_index: 0,
-                ..Default::default()};
-        }
-
-        let mem = device.allocate_memory(&mem_alloc_info, None)?;
-
-        let format_modifiers = vec![DrmFourcc::Abgr8888 as u64, DrmModifier::Linear.into()];
-        let drm_format_modifier_list = vk::ImageDrmFormatModifierListCreateInfoEXT {
-            drm_format_modifier_count: format_modifiers.len() as u32,
-            p_drm_format_modifiers: format_modifiers.as_ptr(),
-            ..Default::default() };
-        let ext_img_info = vk::ExternalMemoryImageCreateInfo {
-            p_next: ptr::from_ref(&drm_format_modifier_list).cast(),
-            handle_types: vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT,
-            ..Default::default()};
-        let mut img_info = vk::ImageCreateInfo {
-            p_next: ptr::from_ref(&ext_img_info).cast(),
-            flags: {
-                type Flags = vk::ImageCreateFlags;
-                Flags::default()
-            },
-            image_type: vk::ImageType::TYPE_2D,
-            format: vk::Format::B8G8R8A8_SRGB,
-            extent: vk::Extent3D { width: 1920, height: 1200, depth: 1 },
-            mip_levels: 1,
-            array_layers: 1,
-            samples: vk::SampleCountFlags::TYPE_1,
-            tiling: vk::ImageTiling::DRM_FORMAT_MODIFIER_EXT,
-            usage: {
-                type Flags = vk::ImageUsageFlags;
-                Flags::SAMPLED
-            },
-            sharing_mode: vk::SharingMode::EXCLUSIVE,
-            initial_layout: vk::ImageLayout::UNDEFINED,
-            ..Default::default()};
-
-        if !SCREENSHARE {
-            img_info.p_next = ptr::null();
-            img_info.tiling = vk::ImageTiling::LINEAR;
+        match frame.map(|frame| frame.data) {
+            Some(FrameData::DmaBuf { key, planes }) => if let Err(e) = sources[0].show(&device, key, planes) {
+                warn!("Capture: can't import the stream's buffer ({e})");
+                negotiated[0].lock().unwrap().release(key);
+            }
+            Some(FrameData::Shm { pixels, stride }) => sources[0].write(pixels, stride),
+            None => {}
         }
-        let img = device.create_image(&img_info, None)?;
-
-        device.bind_image_memory(img, mem, 0)?;
-
-        let view_info = vk::ImageViewCreateInfo {
-            flags: vk::ImageViewCreateFlags::default(),
-            image: img,
-            view_type: vk::ImageViewType::TYPE_2D,
-            format: img_info.format,
-            components: vk::ComponentMapping::default(),
-            subresource_range: vk::ImageSubresourceRange {
-                aspect_mask: vk::ImageAspectFlags::COLOR,
-                base_mip_level: 0,
-                level_count: 1,
-                base_array_layer: 0,
-                layer_count: 1,
-            },
-            ..Default::default()};
-        let view = device.create_image_view(&view_info, None)?;
-
-        let sampler_info = vk::SamplerCreateInfo {
-            flags: vk::SamplerCreateFlags::default(),
-            mag_filter: vk::Filter::LINEAR,
-            min_filter: vk::Filter::LINEAR,
-            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
-            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_BORDER,
-            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_BORDER,
-            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_BORDER,
-            mip_lod_bias: 0.0,
-            anisotropy_enable: vk::TRUE,
-            max_anisotropy: 4f32.min(INSTANCE.get_physical_device_properties(phys_device).limits.max_sampler_anisotropy),
-            compare_enable: vk::FALSE,
-            compare_op: vk::CompareOp::ALWAYS,
-            min_lod: 0.0,
-            max_lod: 0.0,
-            border_color: vk::BorderColor::FLOAT_OPAQUE_BLACK,
-            unnormalized_coordinates: vk::FALSE,
-            ..Default::default()};
-        let sampler = device.create_sampler(&sampler_info, None)?;
-
-        holder.mem = mem;
-        holder.img = img;
-        holder.view = view;
-        holder.sampler = sampler;
+        sources
     };
 
     info!("Using Device {}",format!("{:?}",phys_device_properties.device_name_as_c_str().unwrap()).bright_purple());
@@ -685,6 +552,7 @@ fn main() -> Result<(),Box<dyn Error>>
         device,
         physical_device: phys_device,
         queue,
+        transfer,
         command_pool,
 
         windows: HashMap::with_capacity(WINDOW_COUNT),
@@ -697,28 +565,26 @@ fn main() -> Result<(),Box<dyn Error>>
         resized: false,
         current_frame: 0,
 
-        screencast: Some(holder),
+        sources,
+        negotiated,
+        session,
+        capture: capture_desc,
         ctrl_vals: [[0.0,0.0,2.0],[0.0,0.0,0.0,],[0.0,0.0,0.0],[0.0,0.0,0.0]],
         mode: 0,
+        config,
     })
     {
         Ok(_) => Ok(()),
         Err(e) => Err(Box::new(e))
     }
 }
-#[derive(Default)]
-struct SCHolder {
-    mem: vk::DeviceMemory,
-    img: vk::Image,
-    view: vk::ImageView,
-    sampler: vk::Sampler,
-}
 pub(crate) struct App {
     #[allow(unused)]
     device: Device,
     //i *think* there's no way to retrieve the physical device handle from a logical device
     physical_device: vk::PhysicalDevice,
     queue: vk::Queue,
+    transfer: TransferQueue,
     command_pool: vk::CommandPool,
 
     windows: HashMap<WindowId,PerWindow>,
@@ -731,9 +597,17 @@ pub(crate) struct App {
     resized: bool,
     current_frame: usize,
 
-    screencast: Option<SCHolder>,
+    //the screencasts, by source. placeholders without a stream.
+    sources: Vec<Capture>,
+    //shared with the pipewire thread, which leaves a new format in there whenever a stream changes. by source too.
+    negotiated: Vec<Arc<Mutex<Negotiated>>>,
+    //the pipewire thread, if there's a screencast at all
+    session: Option<CaptureSession>,
+    //which window shows which source, see the [capture] section
+    capture: CaptureDesc,
     ctrl_vals: [[f32;3];4],
     mode: usize,
+    config: Config,
 }
 
 struct ExtensionHolder {
@@ -748,11 +622,15 @@ struct ExtensionHolder {
     linux_drm: Option<ext::acquire_drm_display::Instance>,
 
     extmem_fd: Option<khr::external_memory_fd::Device>,
+    extsem_fd: Option<khr::external_semaphore_fd::Device>,
     extmem_caps: Option<khr::external_memory_capabilities::Instance>,
     image_drm_format_modifier: Option<ext::image_drm_format_modifier::Device>,
     bind_memory2: Option<khr::bind_memory2::Device>,
     get_physical_device_properties2: Option<khr::get_physical_device_properties2::Instance>,
     sampler_ycbcr_conversion: Option<khr::sampler_ycbcr_conversion::Device>,
+    synchronization2: Option<khr::synchronization2::Device>,
+    //None means render passes
+    dynamic_rendering: Option<DynamicRendering>,
 }
 
 enum OSSurface {
@@ -762,6 +640,34 @@ enum OSSurface {
     XLIB(khr::xlib_surface::Instance),
 }
 
+//the command line
+struct Args {
+    //--capture-node <id|name>, once per source, captures straight from the pipewire daemon instead of the portal
+    nodes: Vec<CaptureNode>,
+    //--reselect-capture. the portal remembers what was shared the last time, unless it's told to ask again
+    reselect: bool,
+}
+
+impl Args {
+    //anything it doesn't know gets warned about and skipped
+    fn parse() -> Args {
+        let mut parsed = Args { nodes: Vec::new(), reselect: false };
+        let mut args = env::args().skip(1).peekable();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                //not another flag, that's a forgotten value
+                "--capture-node" => match args.next_if(|node| !node.starts_with("--")) {
+                    Some(node) => parsed.nodes.push(CaptureNode::parse(&node)),
+                    None => warn!("--capture-node needs a node id or name, capturing through the portal"),
+                },
+                "--reselect-capture" => parsed.reselect = true,
+                _ => warn!("unknown argument {arg:?}, ignoring it"),
+            }
+        }
+        parsed
+    }
+}
+
 
 unsafe fn cleanup(
     ext: &ExtensionHolder,
@@ -783,18 +689,26 @@ const FOV: f32 = 75.0;
 const NEAR: f32 = 0.01;
 const FAR: f32 = 20.0;
 
-#[repr(C)]
-struct UniformBufferObject {
-    model: glm::Mat4,
-    view: glm::Mat4,
-    proj: glm::Mat4,
-}
-
-#[repr(C)]
-struct MVBufferObject {
-    buffer_size: u64,
-    dimensions: glm::IVec2,
-    data: [u32; MV_BUF_SIZE],
+//everything the shaders see, laid out once. basic.vert/basic.frag get the GLSL side through #include <ember/interface.glsl>
+shader_interface! {
+    uniform(std140, set = 0, binding = 0) ubo: struct UniformBufferObject {
+        model: glm::Mat4,
+        view: glm::Mat4,
+        proj: glm::Mat4,
+    }
+    buffer(std430, set = 0, binding = 2) mv: struct MVBufferObject {
+        buffer_size: u64,
+        dimensions: glm::IVec2,
+        data: [u32; MV_BUF_SIZE],
+    }
+    push_constant(std430) pc: struct PushConstants {
+        rand: glm::Vec2,
+        //the capture's size in pixels, it changes with whatever the stream negotiates
+        capture: glm::Vec2,
+        aspect: f32,
+        t: f32,
+        id: i32,
+    }
 }
 
 
@@ -805,7 +719,7 @@ impl ApplicationHandler for App {
 
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
 
-        let mut builder = WindowBuilder::new(&self.ext,&self.device,self.physical_device,self.command_pool);
+        let mut builder = WindowBuilder::new(&self.ext,&self.device,self.physical_device,self.queue,self.transfer,self.command_pool,&self.config);
         builder.attributes = builder.attributes
             .with_title(APPLICATION_TITLE)
             .with_active(true)
@@ -820,7 +734,8 @@ impl ApplicationHandler for App {
 
         (0..window_count).for_each(|idx| {
             builder.attributes.title = format!("{}  #{}",APPLICATION_TITLE,idx+1);
-            let (window_id, mut per_window) = builder.build(event_loop, self.screencast.as_ref());
+            let source = self.capture.source(idx);
+            let (window_id, mut per_window) = builder.build(event_loop, &self.sources, source);
             per_window.id = idx as i32;
             /*
             let fp = unsafe { WindowsFFI::load_function_pointers() };
@@ -834,7 +749,8 @@ impl ApplicationHandler for App {
             debug!("THE LARGE AMOUNT OF WINDOWS IS INTENTIONAL.");
             info!("by the way, that above was on \"{}\" due to the color being highly visible, not because of it being debugging-related.","DEBUG".bright_cyan());
             builder.attributes.title = "yes, this is intentional".to_owned();
-            let (window_id, mut per_window) = builder.build(event_loop, self.screencast.as_ref());
+            let source = self.capture.source(window_count);
+            let (window_id, mut per_window) = builder.build(event_loop, &self.sources, source);
             per_window.id = window_count as i32;
             _ = self.windows.insert(window_id,per_window)
         }
@@ -852,8 +768,6 @@ impl ApplicationHandler for App {
             surface,
             ref swapchain,
             render_pass,
-            pipeline,
-            layout,
             command_buffers,
             ..
         } = per_window;
@@ -868,6 +782,11 @@ impl ApplicationHandler for App {
                             window.set_decorations(!window.is_decorated()) }
 
 
+                        KeyCode::F5 => {
+                            if !event.state.is_pressed() || event.repeat { return; }
+                            if let Some(Err(e)) = self.session.as_mut().map(|session| session.restart()) {
+                                error!("Capture: can't restart ({e})") }
+                        }
                         KeyCode::AltLeft => {
                             if !event.state.is_pressed() { return; }
                             self.mode = (self.mode + 1) % 4;
@@ -921,34 +840,21 @@ impl ApplicationHandler for App {
                     "Closing Window with {}",
                     format!("ID {}",unsafe {mem::transmute_copy::<_,isize>(&window_id) }).bright_purple());
 
-                let PerWindow {surface,layout,pipeline,render_pass,swapchain,vertex_buffer,vertex_buffer_mem,
-                    ubufs,ubufs_mem,descriptor_set_layout,descriptor_pool,mv_ubufs,mv_ubufs_mem,.. }
+                let PerWindow {surface,render_pass,mut chain,swapchain,vertex_buffer,vertex_buffer_mem,
+                    uploader,ubo,mv,scale,.. }
                     = self.windows.remove(&window_id).unwrap();
                 unsafe {
                     //VERY IMPORTANT! otherwise, we'd try cleaning up semaphores n stuff while they're still in use
                     self.device.device_wait_idle().unwrap();
 
-                    self.device.destroy_pipeline(pipeline,None);
-                    self.device.destroy_pipeline_layout(layout,None);
+                    chain.destroy(&self.device);
 
                     swapchain.cleanup(&self.device, &self.ext.swapchain);
 
-                    ubufs_mem.iter().for_each(|mem|{
-                        self.device.unmap_memory(*mem);
-                    });
-                    ubufs.iter().for_each(|buf|{
-                        self.device.destroy_buffer(*buf, None);
-                    });
-
-                    mv_ubufs_mem.iter().for_each(|mem|{
-                       self.device.unmap_memory(*mem);
-                    });
-                    mv_ubufs.iter().for_each(|buf|{
-                       self.device.destroy_buffer(*buf, None);
-                    });
-
-                    self.device.destroy_descriptor_pool(descriptor_pool, None);
-                    self.device.destroy_descriptor_set_layout(descriptor_set_layout,None);
+                    ubo.destroy(&self.device);
+                    mv.destroy(&self.device);
+                    uploader.destroy(&self.device);
+                    scale.destroy(&self.device);
 
                     self.device.destroy_buffer(vertex_buffer, None);
                     self.device.free_memory(vertex_buffer_mem, None);
@@ -970,6 +876,11 @@ impl ApplicationHandler for App {
                 let ext = &self.ext;
 
                 unsafe { device.wait_for_fences(&[swapchain.sync[self.current_frame].in_flight],true,u64::MAX).unwrap() };
+                //the frame's timestamps are in, so the render scale can catch up with how long it took
+                if let Some(factor) = unsafe { per_window.scale.adapt(device, self.current_frame) } { unsafe {
+                    device.device_wait_idle().unwrap();
+                    per_window.chain.set_scale(device, swapchain.extent, factor);
+                }}
 
                 let next = unsafe {
                     let mut result = ext.swapchain.acquire_next_image(swapchain.handle, u64::MAX, swapchain.sync[self.current_frame].swapchain.clone(), vk::Fence::null());
@@ -998,47 +909,53 @@ impl ApplicationHandler for App {
                     window,
                     surface,
                     swapchain,
-                    render_pass,
-                    pipeline,
-                    layout,
+                    chain,
                     command_buffers,
                     vertex_buffer,
-                    push_constant_range,
-                    ubufs,
-                    ubufs_map,
-                    mv_ubufs,
-                    mv_ubufs_map,
-                    descriptor_sets,
+                    uploader,
+                    ubo,
+                    mv,
+                    scale,
                     id,
                     ..
                 } = per_window;
 
                 unsafe { device.reset_fences(&[swapchain.sync[self.current_frame].in_flight]).unwrap() };
+                //what the frame staged last time around is done being copied
+                uploader.begin(self.current_frame);
 
-                let map = unsafe { ubufs_map[self.current_frame].cast::<UniformBufferObject>().as_mut().unwrap() };
                 let [[cx,cy,cz],[cu,cv,cw],[ox,oy,oz],[ou,ov,ow]] = self.ctrl_vals;
                 let aspect = (swapchain.extent.width as f32) / (swapchain.extent.height as f32);
-                map.model = glm::mat4(
+                let mut model = glm::mat4(
                     1.0,    0.0,    0.0,    0.0,
                     0.0,    1.0,    0.0,    0.0,
                     0.0,    0.0,    1.0,    0.0,
                     ox,    oy,    oz,    1.0);
-                map.model = glm::ext::rotate(&map.model, ou, glm::vec3(1.0,0.0,0.0));
-                map.model = glm::ext::rotate(&map.model, ov, glm::vec3(0.0,1.0,0.0));
-                map.model = glm::ext::rotate(&map.model, ow, glm::vec3(0.0,0.0,1.0));
-                map.view = glm::mat4(
+                model = glm::ext::rotate(&model, ou, glm::vec3(1.0,0.0,0.0));
+                model = glm::ext::rotate(&model, ov, glm::vec3(0.0,1.0,0.0));
+                model = glm::ext::rotate(&model, ow, glm::vec3(0.0,0.0,1.0));
+                let mut view = glm::mat4(
                     1.0,    0.0,    0.0,    0.0,
                     0.0,    1.0,    0.0,    0.0,
                     0.0,    0.0,    1.0,    0.0,
                     -cx,    -cy,    -cz,    1.0);
-                map.view = glm::ext::rotate(&map.view, cu, glm::vec3(1.0,0.0,0.0));
-                map.view = glm::ext::rotate(&map.view, cv, glm::vec3(0.0,1.0,0.0));
-                map.view = glm::ext::rotate(&map.view, cw, glm::vec3(0.0,0.0,1.0));
-                map.proj = glm::ext::perspective(FOV.to_radians(), aspect, NEAR, FAR);
-
-                let map = unsafe { mv_ubufs_map[self.current_frame].cast::<MVBufferObject>().as_mut().unwrap() };
-                map.buffer_size = MV_BUF_SIZE as u64;
-                map.dimensions = glm::IVec2::new(MV_SIZE.0 as i32, MV_SIZE.1 as i32);
+                view = glm::ext::rotate(&view, cu, glm::vec3(1.0,0.0,0.0));
+                view = glm::ext::rotate(&view, cv, glm::vec3(0.0,1.0,0.0));
+                view = glm::ext::rotate(&view, cw, glm::vec3(0.0,0.0,1.0));
+                //glm is made for OpenGL: y points up in clip space and depth goes from -1 to 1. vulkan wants y down and 0 to 1.
+                let clip = glm::mat4(
+                    1.0,    0.0,    0.0,    0.0,
+                    0.0,   -1.0,    0.0,    0.0,
+                    0.0,    0.0,    0.5,    0.0,
+                    0.0,    0.0,    0.5,    1.0);
+                let proj = clip * glm::ext::perspective(FOV.to_radians(), aspect, NEAR, FAR);
+                unsafe { ubo.write_value(0, &UniformBufferObject { model, view, proj }) };
+
+                //only what changed since the last frame gets copied over, see StagedBuffer::write
+                unsafe {
+                    mv.write_value(mem::offset_of!(MVBufferObject, buffer_size) as u64, &(MV_BUF_SIZE as u64));
+                    mv.write_value(mem::offset_of!(MVBufferObject, dimensions) as u64, &glm::IVec2::new(MV_SIZE.0 as i32, MV_SIZE.1 as i32));
+                }
                 let mut buffer = Vec::<u32>::with_capacity(MV_BUF_SIZE);
                 /*
                 let pid = self.kwin.1;
@@ -1065,19 +982,40 @@ impl ApplicationHandler for App {
                 }
                 addr = ptr::null_mut();
                 unsafe { ret = libc::ptrace(libc::PTRACE_DETACH, pid, addr, data_ptr) }; */
-                map.data = unsafe { buffer.as_ptr().cast::<[u32;MV_BUF_SIZE]>().read() };
+                //nothing fills it for now, so the data stays zeroed instead of getting read out of an empty Vec
+                if buffer.len() == MV_BUF_SIZE {
+                    let data = unsafe { slice::from_raw_parts(buffer.as_ptr().cast::<u8>(), size_of_val(buffer.as_slice())) };
+                    mv.write(mem::offset_of!(MVBufferObject, data) as u64, data);
+                }
+                let uploaded = unsafe { uploader.flush(device, self.physical_device, self.current_frame, &mut [ubo, mv]) };
+                //shared memory frames go in with the frame's own command buffer, see Capture::upload
+                for (source, capture) in self.sources.iter_mut().enumerate() {
+                    if chain.reads(source) { unsafe { capture.upload(device, uploader, self.current_frame) } } }
 
                 unsafe { device.reset_command_buffer(command_buffers[self.current_frame],Default::default()).unwrap() };
-                unsafe { record_into_buffer(device, window, *pipeline, *render_pass, swapchain.framebuffers[next as usize],
-                                            swapchain.extent, command_buffers[self.current_frame], self.current_frame, *vertex_buffer, *layout, *push_constant_range,
-                                            self.screencast.as_ref().unwrap().img, descriptor_sets.clone(), *id) };
+                unsafe { record_into_buffer(device, window, chain, swapchain.framebuffers.get(next as usize).copied().unwrap_or_default(),
+                                            swapchain.extent, command_buffers[self.current_frame], self.current_frame, *vertex_buffer,
+                                            swapchain.images[next as usize], swapchain.views[next as usize], uploader, scale, *id) };
 
                 window.pre_present_notify();
 
+                //whatever the compositor still has to write into the captures' buffers, see Capture::acquire
+                let captured: Vec<vk::Semaphore> = self.sources.iter_mut().enumerate().filter(|(source, _)| chain.reads(*source))
+                    .filter_map(|(source, capture)| {
+                        let semaphore = swapchain.sync[self.current_frame].capture[source];
+                        unsafe { capture.acquire(semaphore) }.then_some(semaphore) })
+                    .collect();
+
+                //the uploads and the capture are read by the shaders, the swapchain image is only written to at the very end
+                let shaders = vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER;
+                let (wait_semaphores, wait_stages): (Vec<vk::Semaphore>, Vec<vk::PipelineStageFlags>) =
+                    [Some((swapchain.sync[self.current_frame].swapchain, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)),
+                     uploaded.map(|uploaded| (uploaded, shaders))].into_iter().flatten()
+                        .chain(captured.into_iter().map(|captured| (captured, shaders))).unzip();
                 let submit_info = vk::SubmitInfo {
-                    wait_semaphore_count: 1,
-                    p_wait_semaphores: ptr::from_ref(&swapchain.sync[self.current_frame].swapchain),
-                    p_wait_dst_stage_mask: ptr::from_ref(&vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT),
+                    wait_semaphore_count: wait_semaphores.len() as u32,
+                    p_wait_semaphores: wait_semaphores.as_ptr(),
+                    p_wait_dst_stage_mask: wait_stages.as_ptr(),
                     command_buffer_count: 1,
                     p_command_buffers: ptr::from_ref(&command_buffers[self.current_frame]),
                     signal_semaphore_count: 1,
@@ -1122,6 +1060,109 @@ impl ApplicationHandler for App {
     }
 
     fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        //the pass list, shaders and specialization constants are baked into the chain, so changing them means building a new one.
+        //a pass whose shader sources changed gets rebuilt on its own. a broken shader shows its errors in the window until it's fixed.
+        //a changed [scale] resets the window's render scale, see util::scale
+        let reloaded = self.config.reload();
+        let descs = reloaded.then(|| describe(&self.config));
+        let scale = reloaded.then(|| scale::describe(&self.config));
+        let mut idle = false;
+        for per_window in self.windows.values_mut() {
+            let chain = &mut per_window.chain;
+            let outdated = descs.as_ref().filter(|descs| !chain.describes(descs));
+            let changed = chain.changed();
+            let rescaled = scale.filter(|scale| per_window.scale.desc != *scale);
+            if outdated.is_none() && changed.is_empty() && rescaled.is_none() { continue }
+            unsafe {
+                if !idle { self.device.device_wait_idle().unwrap(); idle = true }
+                let factor = rescaled.map(|scale| per_window.scale.configure(scale));
+                match outdated {
+                    Some(descs) => {
+                        info!("Pass config changed, rebuilding the render chain");
+                        if let Some(factor) = factor { chain.scale = factor }
+                        chain.rebuild(&self.device, per_window.swapchain.extent, descs.clone()) }
+                    None => {
+                        changed.into_iter().for_each(|idx| chain.rebuild_pass(&self.device, per_window.swapchain.extent, idx));
+                        if let Some(factor) = factor { chain.set_scale(&self.device, per_window.swapchain.extent, factor) } }
+                }
+            }
+        }
+
+        //what the pipewire thread has to say. once a stream's over it's the placeholder again, see Negotiated::reset.
+        for event in self.session.iter().flat_map(|session| session.events()) {
+            match event {
+                CaptureEvent::State { source, state } => debug!("Capture: stream {source} is {state:?}"),
+                CaptureEvent::Error(e) => error!("Capture: {e}"),
+                CaptureEvent::Ended { source } => warn!("Capture: stream {source} ended, F5 picks another one"),
+            }
+        }
+
+        //the windows can show another source whenever the config says so. how many there are is fixed at startup though.
+        if reloaded {
+            let capture = describe_capture(&self.config);
+            if capture.sources != self.sources.len() {
+                warn!("Capture: {} sources instead of {} takes a restart", capture.sources, self.sources.len()) }
+            for per_window in self.windows.values_mut() {
+                let source = Some(capture.source(per_window.id as usize)).filter(|source| *source < self.sources.len()).unwrap_or(0);
+                if source == per_window.source { continue }
+                info!("Window #{} shows capture source {source} now", per_window.id + 1);
+                unsafe {
+                    let fences: Vec<vk::Fence> = per_window.swapchain.sync.iter().map(|sync| sync.in_flight).collect();
+                    self.device.wait_for_fences(&fences, true, u64::MAX).unwrap();
+                    per_window.chain.set_source(&self.device, per_window.swapchain.extent, source);
+                }
+                per_window.source = source;
+            }
+            self.capture = capture;
+        }
+
+        //the newest buffer each stream finished, see platform::linux::capture. the windows showing it switch over once
+        //  they're done with the frame sampling the one before, which then goes back to pipewire.
+        //a changed format or size means new images for everything, and buffers pipewire took away lose theirs.
+        for (source, capture) in self.sources.iter_mut().enumerate() {
+            let negotiated = &self.negotiated[source];
+            let (frame, removed) = {
+                let mut negotiated = negotiated.lock().unwrap();
+                (negotiated.take(), negotiated.removed()) };
+            if frame.is_none() && removed.is_empty() { continue }
+            unsafe {
+                let shown = capture.current;
+                let reformat = frame.as_ref().is_some_and(|frame| capture.wants(frame.format));
+                if !removed.is_empty() || reformat {
+                    self.device.device_wait_idle().unwrap();
+                    removed.into_iter().for_each(|key| capture.forget(&self.device, key));
+                }
+                if let Some(frame) = frame {
+                    if reformat {
+                        info!("Capture {source} is now {}x{} {:?}", frame.format.extent.width, frame.format.extent.height, frame.format.format);
+                        if let Err(e) = capture.reformat(&self.device, frame.format) {
+                            warn!("Capture: can't make images of the renegotiated format ({e}), ignoring it until the stream changes again") }
+                    }
+                    match frame.data {
+                        //there are no images for it, it goes right back
+                        FrameData::DmaBuf { key, .. } if frame.format != capture.format => negotiated.lock().unwrap().release(key),
+                        FrameData::DmaBuf { key, planes } => if let Err(e) = capture.show(&self.device, key, planes) {
+                            //it comes around again with its planes, to try again with
+                            if capture.reject(key) { warn!("Capture: can't import the stream's buffer ({e})") }
+                            negotiated.lock().unwrap().reject(key);
+                        }
+                        FrameData::Shm { pixels, stride } => if frame.format == capture.format { capture.write(pixels, stride) },
+                    }
+                }
+                //descriptors can't change under a frame that's still being rendered. shared memory frames all go into the
+                //  same image, nothing to change there.
+                let sampled = capture.sampled();
+                let windows = self.windows.values_mut().filter(|per_window| per_window.chain.reads(source));
+                for per_window in windows.filter(|_| reformat || capture.current != shown) {
+                    let fences: Vec<vk::Fence> = per_window.swapchain.sync.iter().map(|sync| sync.in_flight).collect();
+                    self.device.wait_for_fences(&fences, true, u64::MAX).unwrap();
+                    per_window.chain.set_capture(&self.device, per_window.swapchain.extent, source, sampled);
+                }
+                //nothing samples the one before anymore
+                if let Some(shown) = shown.filter(|shown| capture.current != Some(*shown)) { negotiated.lock().unwrap().release(shown) }
+            }
+        }
+
         thread::sleep(Duration::from_millis(33));
         self.windows.iter().for_each(|(window_id,per_window)|per_window.window.request_redraw());
     }
@@ -1132,14 +1173,11 @@ impl ApplicationHandler for App {
     fn exiting(&mut self, event_loop: &ActiveEventLoop) {
         info!("Cleaning up...");
         unsafe {
+            //pipewire's done with the buffers before the imports go
+            if let Some(session) = self.session.as_mut() { session.stop() }
             self.device.device_wait_idle().unwrap();
             self.device.destroy_command_pool(self.command_pool,None);
-            if let Some(holder) = self.screencast.as_mut() {
-                self.device.destroy_sampler(holder.sampler, None);
-                self.device.destroy_image_view(holder.view, None);
-                self.device.destroy_image(holder.img, None);
-                self.device.free_memory(holder.mem, None);
-            }
+            self.sources.iter().for_each(|capture| capture.destroy(&self.device));
             cleanup(&self.ext,self.debug_messenger,self.debug_reporter,&self.device);
         }
 
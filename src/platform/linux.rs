@@ -4,6 +4,10 @@ pub(crate) mod kwin;
 pub(crate) mod vmem;
 #[cfg(target_os = "linux")]
 pub(crate) mod util;
+#[cfg(target_os = "linux")]
+pub(crate) mod capture;
+#[cfg(target_os = "linux")]
+pub(crate) mod session;
 
 
 #[cfg(not(target_os = "linux"))]
@@ -55,4 +59,14 @@ pub(crate) mod kwin {
 #[cfg(not(target_os = "linux"))]
 pub(crate) mod vmem {
     
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) mod capture {
+    
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) mod session {
+    
 }
\ No newline at end of file
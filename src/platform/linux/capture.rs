@@ -0,0 +1,362 @@
+use std::collections::HashSet;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::{io, mem, ptr, slice};
+use ash::vk;
+use log::{debug, info, warn};
+use pipewire::spa;
+use pipewire::stream::Stream;
+use pipewire::sys::pw_buffer;
+use pipewire::spa::param::format::{FormatProperties, MediaSubtype, MediaType};
+use pipewire::spa::param::video::{VideoFormat, VideoInfoRaw};
+use pipewire::spa::param::ParamType;
+use pipewire::spa::pod::{ChoiceValue, Object, Pod, Property, PropertyFlags, Value};
+use pipewire::spa::utils::{Choice, ChoiceEnum, ChoiceFlags, SpaTypes};
+use crate::util::capture::{importable_modifiers, multiplanar, CaptureFormat, DmaBuf, DmaBufPlane, Ycbcr};
+
+
+//the pipewire side of the screencast. the stream's callbacks run on the pipewire thread and leave what they learn
+//  in a Negotiated, the main thread picks it up from there and makes the capture image (see util::capture).
+//buffers are dequeued raw, so they stay out of pipewire's hands for as long as the main thread samples them.
+//  shared memory ones are the exception, they get copied out right away and go straight back.
+
+//the formats the stream offers, most wanted first, and what they are to vulkan. the x formats get sampled like
+//  their alpha counterparts, the alpha is just garbage then. the YUV ones come from hardware encoders mostly.
+pub(crate) const FORMATS: [(VideoFormat, vk::Format); 6] = [
+    (VideoFormat::BGRA, vk::Format::B8G8R8A8_SRGB),
+    (VideoFormat::BGRx, vk::Format::B8G8R8A8_SRGB),
+    (VideoFormat::RGBA, vk::Format::R8G8B8A8_SRGB),
+    (VideoFormat::RGBx, vk::Format::R8G8B8A8_SRGB),
+    (VideoFormat::NV12, vk::Format::G8_B8R8_2PLANE_420_UNORM),
+    (VideoFormat::I420, vk::Format::G8_B8_R8_3PLANE_420_UNORM),
+];
+
+pub(crate) fn vk_format(format: VideoFormat) -> Option<vk::Format> {
+    FORMATS.iter().find(|(video, _)| *video == format).map(|(_, format)| *format)
+}
+
+//the EnumFormat params to connect the stream with: one per format there's anything to import with, offering the
+//  modifiers the device takes. the producer picks one of them, see fixate.
+//the YUV formats only with `ycbcr`, the sampler YCbCr conversion feature. DMA-BUFs only with `dmabuf`, the extensions
+//  to import them with. after those come the same formats in shared memory, for producers that have nothing else,
+//  without the YUV ones, which are only ever uploaded as RGB.
+pub(crate) unsafe fn offer(physical_device: vk::PhysicalDevice, ycbcr: bool, dmabuf: bool) -> Vec<Vec<u8>> {
+    let imported = FORMATS.iter().filter(|_| dmabuf).filter_map(|&(video, format)| {
+        if multiplanar(format) && !ycbcr { return None }
+        let modifiers = importable_modifiers(physical_device, format);
+        if modifiers.is_empty() {
+            debug!("Capture: {video:?} can't be imported, not offering it");
+            return None }
+        debug!("Capture: offering {video:?} with modifiers {modifiers:#x?}");
+        Some(enum_format(video, Some(modifiers.into_iter().map(|modifier| modifier as i64).collect())))
+    });
+    let shared = FORMATS.iter().filter(|(_, format)| !multiplanar(*format)).map(|&(video, _)| enum_format(video, None));
+    imported.chain(shared).collect()
+}
+
+//without modifiers it's a format in shared memory
+fn enum_format(video: VideoFormat, modifiers: Option<Vec<i64>>) -> Vec<u8> {
+    let mut properties = vec![
+        spa::pod::property!(FormatProperties::MediaType, Id, MediaType::Video),
+        spa::pod::property!(FormatProperties::MediaSubtype, Id, MediaSubtype::Raw),
+        spa::pod::property!(FormatProperties::VideoFormat, Id, video),
+    ];
+    if let Some(modifiers) = modifiers {
+        //left to the producer to fixate, it knows which of these it can allocate
+        properties.push(Property {
+            key: FormatProperties::VideoModifier.as_raw(),
+            flags: PropertyFlags::MANDATORY | PropertyFlags::DONT_FIXATE,
+            value: Value::Choice(ChoiceValue::Long(Choice(ChoiceFlags::empty(), ChoiceEnum::Enum {
+                default: modifiers[0],
+                alternatives: modifiers }))),
+        });
+    }
+    properties.extend([
+        spa::pod::property!(
+            FormatProperties::VideoSize,
+            Choice, Range, Rectangle,
+            spa::utils::Rectangle { width: 2560, height: 1440 }, // Default
+            spa::utils::Rectangle { width: 1, height: 1 },       // Min
+            spa::utils::Rectangle { width: 4096, height: 4096 }  // Max
+        ),
+        spa::pod::property!(
+            FormatProperties::VideoFramerate,
+            Choice, Range, Fraction,
+            spa::utils::Fraction { num: 240, denom: 1 }, // Default
+            spa::utils::Fraction { num: 0, denom: 1 },   // Min
+            spa::utils::Fraction { num: 244, denom: 1 }  // Max
+        ),
+    ]);
+    serialize(Object { type_: SpaTypes::ObjectParamFormat.as_raw(), id: ParamType::EnumFormat.as_raw(), properties })
+}
+
+//the Buffers param to answer a fixed format with: the kind of memory the buffers should come in. shared memory
+//  has to be mappable (see StreamFlags::MAP_BUFFERS), DMA-BUFs get imported.
+pub(crate) fn buffers(shm: bool) -> Vec<u8> {
+    let types = match shm {
+        true => (1 << spa::sys::SPA_DATA_MemFd) | (1 << spa::sys::SPA_DATA_MemPtr),
+        false => 1 << spa::sys::SPA_DATA_DmaBuf,
+    };
+    serialize(Object {
+        type_: SpaTypes::ObjectParamBuffers.as_raw(),
+        id: ParamType::Buffers.as_raw(),
+        properties: vec![Property {
+            key: spa::sys::SPA_PARAM_BUFFERS_dataType,
+            flags: PropertyFlags::empty(),
+            value: Value::Choice(ChoiceValue::Int(Choice(ChoiceFlags::empty(), ChoiceEnum::Flags { default: types, flags: vec![] }))),
+        }],
+    })
+}
+
+//a Format param the producer left the modifier open in, as it does with DONT_FIXATE. returns the same format with one
+//  of the modifiers picked, to hand back with update_params. a format that's already fixed returns None.
+pub(crate) fn fixate(param: &Pod) -> Option<Vec<u8>> {
+    let Ok((_, Value::Object(mut object))) = spa::pod::deserialize::PodDeserializer::deserialize_any_from(param.as_bytes()) else { return None };
+    let property = object.properties.iter_mut().find(|property| property.key == FormatProperties::VideoModifier.as_raw())?;
+    if !property.flags.contains(PropertyFlags::DONT_FIXATE) { return None }
+    let modifier = match &property.value {
+        Value::Choice(ChoiceValue::Long(Choice(_, ChoiceEnum::Enum { default, .. }))) => *default,
+        Value::Choice(ChoiceValue::Long(Choice(_, ChoiceEnum::None(modifier)))) => *modifier,
+        _ => return None,
+    };
+    debug!("Capture: fixating the modifier to {modifier:#x}");
+    property.flags = PropertyFlags::MANDATORY;
+    property.value = Value::Long(modifier);
+    object.id = ParamType::EnumFormat.as_raw();
+    Some(serialize(object))
+}
+
+//the conversion the stream's color metadata asks for. without any, it's guessed from the size like players do:
+//  BT.709 for HD and up, BT.601 below. narrow range unless it says full.
+fn ycbcr(info: &VideoInfoRaw) -> Ycbcr {
+    let raw = info.as_raw();
+    let model = match raw.color_matrix {
+        spa::sys::SPA_VIDEO_COLOR_MATRIX_BT601 => vk::SamplerYcbcrModelConversion::YCBCR_601,
+        spa::sys::SPA_VIDEO_COLOR_MATRIX_BT709 => vk::SamplerYcbcrModelConversion::YCBCR_709,
+        spa::sys::SPA_VIDEO_COLOR_MATRIX_BT2020 => vk::SamplerYcbcrModelConversion::YCBCR_2020,
+        _ if info.size().height >= 720 => vk::SamplerYcbcrModelConversion::YCBCR_709,
+        _ => vk::SamplerYcbcrModelConversion::YCBCR_601,
+    };
+    let range = match raw.color_range {
+        spa::sys::SPA_VIDEO_COLOR_RANGE_0_255 => vk::SamplerYcbcrRange::ITU_FULL,
+        _ => vk::SamplerYcbcrRange::ITU_NARROW,
+    };
+    debug!("Capture: YUV as {model:?}, {range:?}");
+    Ycbcr { model, range }
+}
+
+fn serialize(object: Object) -> Vec<u8> {
+    spa::pod::serialize::PodSerializer::serialize(std::io::Cursor::new(Vec::new()), &Value::Object(object))
+        .unwrap().0.into_inner()
+}
+
+//a buffer the stream finished, handed to the main thread
+pub(crate) struct Frame {
+    pub format: CaptureFormat,
+    pub data: FrameData,
+}
+
+pub(crate) enum FrameData {
+    DmaBuf {
+        //the buffer, for as long as pipewire has it. see Capture in util::capture.
+        key: u64,
+        //the first time a buffer comes around, what to import it from
+        planes: Option<DmaBuf>,
+    },
+    //a copy of what was in shared memory, `stride` bytes to a row. the buffer itself is pipewire's again already.
+    Shm { pixels: Vec<u8>, stride: u32 },
+}
+
+impl Frame {
+    //the buffer to release once it's not sampled anymore, if it's still ours
+    pub fn key(&self) -> Option<u64> {
+        match self.data {
+            FrameData::DmaBuf { key, .. } => Some(key),
+            FrameData::Shm { .. } => None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Negotiated {
+    //what the last format param said
+    format: Option<CaptureFormat>,
+    //the newest buffer, for the main thread to take. it's ours until then, or until a newer one replaces it.
+    latest: Option<Frame>,
+    //buffers the main thread took and hasn't released yet
+    held: HashSet<u64>,
+    //buffers the main thread is done with, they get queued back with the next process
+    released: Vec<u64>,
+    //buffers whose planes went to the main thread already
+    announced: HashSet<u64>,
+    //buffers pipewire took away, the main thread drops their imports
+    removed: Vec<u64>,
+}
+
+impl Negotiated {
+    //from param_changed, with a Format param. the buffers get reallocated after this, whatever came before is stale.
+    //  returns what it made of it, to ask for the right kind of buffers with (see buffers).
+    pub fn format(&mut self, param: &Pod) -> Option<CaptureFormat> {
+        let mut info = VideoInfoRaw::new();
+        if let Err(e) = info.parse(param) {
+            warn!("Capture: can't parse the negotiated format ({e})");
+            return None }
+        let Some(format) = vk_format(info.format()) else {
+            warn!("Capture: the stream settled on {:?}, which isn't one we offered", info.format());
+            return None };
+        let size = info.size();
+        //only the DMA-BUF formats were offered with a modifier
+        let shm = info.as_raw().flags & spa::sys::SPA_VIDEO_FLAG_MODIFIER == 0;
+        match shm {
+            true => info!("Capture: negotiated {:?} at {}x{} in shared memory", info.format(), size.width, size.height),
+            false => info!("Capture: negotiated {:?} at {}x{}, modifier {:#x}", info.format(), size.width, size.height, info.modifier()),
+        }
+        self.format = Some(CaptureFormat {
+            extent: vk::Extent2D { width: size.width, height: size.height },
+            format,
+            modifier: info.modifier(),
+            ycbcr: multiplanar(format).then(|| ycbcr(&info)),
+            shm });
+        self.format
+    }
+
+    //from process. everything the stream has ready gets dequeued, only the newest is kept, the rest goes right back.
+    //  whatever the main thread released since the last time goes back first.
+    pub unsafe fn process(&mut self, stream: &Stream) {
+        for key in self.released.drain(..) { stream.queue_raw_buffer(key as *mut pw_buffer) }
+        let mut newest: *mut pw_buffer = ptr::null_mut();
+        loop {
+            let buffer = stream.dequeue_raw_buffer();
+            if buffer.is_null() { break }
+            if !newest.is_null() { stream.queue_raw_buffer(newest) }
+            newest = buffer;
+        }
+        if newest.is_null() { return }
+        let key = newest as u64;
+        let Some(format) = self.format else {
+            stream.queue_raw_buffer(newest);
+            return };
+        if format.shm {
+            let copied = shm(newest);
+            stream.queue_raw_buffer(newest);
+            if let Some((pixels, stride)) = copied { self.supersede(stream, Frame { format, data: FrameData::Shm { pixels, stride } }) }
+            return
+        }
+        let planes = match self.announced.contains(&key) {
+            true => None,
+            false => match dmabuf(newest) {
+                Some(planes) => Some(planes),
+                None => {
+                    stream.queue_raw_buffer(newest);
+                    return }
+            },
+        };
+        self.announced.insert(key);
+        self.supersede(stream, Frame { format, data: FrameData::DmaBuf { key, planes } });
+    }
+
+    //the one before never made it to the main thread, so it goes straight back. if it brought its planes along,
+    //  they have to come again next time.
+    unsafe fn supersede(&mut self, stream: &Stream, frame: Frame) {
+        if let Some(FrameData::DmaBuf { key, planes }) = self.latest.replace(frame).map(|superseded| superseded.data) {
+            if planes.is_some() { self.announced.remove(&key); }
+            stream.queue_raw_buffer(key as *mut pw_buffer);
+        }
+    }
+
+    //from remove_buffer. the buffer's gone, nobody queues it back.
+    pub fn remove(&mut self, key: u64) {
+        self.announced.remove(&key);
+        self.held.remove(&key);
+        self.released.retain(|released| *released != key);
+        if self.latest.as_ref().is_some_and(|latest| latest.key() == Some(key)) { self.latest = None }
+        self.removed.push(key);
+    }
+
+    //for the main thread: the newest buffer, if there's been a new one since it last looked. it stays the main
+    //  thread's until it's released.
+    pub fn take(&mut self) -> Option<Frame> {
+        let frame = self.latest.take()?;
+        if let Some(key) = frame.key() { self.held.insert(key); }
+        Some(frame)
+    }
+
+    //for the main thread: done sampling it, pipewire can have it back
+    pub fn release(&mut self, key: u64) {
+        if self.held.remove(&key) { self.released.push(key) }
+    }
+
+    //for the main thread: the buffer couldn't be imported. it goes back like a released one, and brings its planes
+    //  along again next time, for another try.
+    pub fn reject(&mut self, key: u64) {
+        self.announced.remove(&key);
+        self.release(key);
+    }
+
+    //the stream's gone, and its buffers with it. the main thread still has to drop its imports, the format's
+    //  whatever the next stream says.
+    pub fn reset(&mut self) {
+        let removed = mem::take(&mut self.removed).into_iter().chain(self.announced.drain()).collect();
+        *self = Negotiated { removed, ..Default::default() };
+    }
+
+    //for the main thread: the buffers whose imports have to go
+    pub fn removed(&mut self) -> Vec<u64> {
+        mem::take(&mut self.removed)
+    }
+}
+
+//linux/dma-buf.h
+#[repr(C)]
+struct DmaBufExportSyncFile {
+    flags: u32,
+    fd: i32,
+}
+const DMA_BUF_SYNC_READ: u32 = 1 << 0;
+//_IOWR('b', 2, struct dma_buf_export_sync_file)
+const DMA_BUF_IOCTL_EXPORT_SYNC_FILE: u32 = 0xc008_6202;
+
+//the fences a reader of the DMA-BUF has to wait for, that is whatever's still writing it, as a sync_file.
+//  needs linux 6.0 or later.
+pub(crate) fn export_sync_file(dmabuf: BorrowedFd) -> io::Result<OwnedFd> {
+    let mut export = DmaBufExportSyncFile { flags: DMA_BUF_SYNC_READ, fd: -1 };
+    match unsafe { libc::ioctl(dmabuf.as_raw_fd(), DMA_BUF_IOCTL_EXPORT_SYNC_FILE as _, &mut export) } {
+        0 => Ok(unsafe { OwnedFd::from_raw_fd(export.fd) }),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+//the buffer's datas are its planes, each with its own offset and stride into the DMA-BUF. None if it's not one.
+unsafe fn dmabuf(buffer: *mut pw_buffer) -> Option<DmaBuf> {
+    let buffer = (*buffer).buffer;
+    //spa::buffer::Data is just a spa_data
+    let datas = slice::from_raw_parts((*buffer).datas.cast::<spa::buffer::Data>(), (*buffer).n_datas as usize);
+    let mut planes = Vec::with_capacity(datas.len());
+    for data in datas {
+        let fd = data.as_raw().fd;
+        if data.type_() != spa::buffer::DataType::DmaBuf || fd < 0 { return None }
+        //the buffer stays pipewire's, the import gets a duplicate to own
+        let fd = match BorrowedFd::borrow_raw(fd as i32).try_clone_to_owned() {
+            Ok(fd) => fd,
+            Err(e) => {
+                warn!("Capture: can't duplicate the buffer's fd ({e})");
+                return None }
+        };
+        let chunk = data.chunk();
+        planes.push(DmaBufPlane { fd, offset: chunk.offset(), stride: chunk.stride().max(0) as u32 });
+    }
+    Some(DmaBuf { planes })
+}
+
+//the buffer's mapped memory, copied out so it can go right back. None if it's not shared memory, or has nothing in it.
+unsafe fn shm(buffer: *mut pw_buffer) -> Option<(Vec<u8>, u32)> {
+    let buffer = (*buffer).buffer;
+    if (*buffer).n_datas == 0 { return None }
+    //the packed formats are all in the first one
+    let data = &mut *(*buffer).datas.cast::<spa::buffer::Data>();
+    if !matches!(data.type_(), spa::buffer::DataType::MemFd | spa::buffer::DataType::MemPtr) { return None }
+    let chunk = data.chunk();
+    let (offset, size, stride) = (chunk.offset() as usize, chunk.size() as usize, chunk.stride().max(0) as u32);
+    if size == 0 { return None }
+    let pixels = data.data()?.get(offset..offset + size)?.to_vec();
+    Some((pixels, stride))
+}
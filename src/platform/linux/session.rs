@@ -0,0 +1,389 @@
+use std::cell::Cell;
+use std::error::Error;
+use std::io::Write;
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::{env, fs, io, mem};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use log::{debug, error, info, warn};
+use pipewire::properties::properties;
+use pipewire::spa;
+use pipewire::stream::StreamState;
+use crate::platform::linux::capture::{self, Negotiated};
+
+
+//the screencast's pipewire thread. every start asks the portal for something to share and streams it until it's
+//  stopped, or until the streams end on their own. with more than one source the portal gets asked for several
+//  monitors or windows, each a stream of its own, with a Negotiated of its own.
+//  what the streams themselves get up to is in platform::linux::capture.
+//the portal hands out a restore token along with the streams, which gets kept in the user's state dir. the next start
+//  hands it back and gets the same monitors or windows without asking, until the user revokes it.
+//for testing, or setups without a portal, it can also connect to the local pipewire daemon and capture nodes by
+//  id or name instead (see --capture-node), say a videotestsrc or another app's video output.
+//the app hears about the thread through CaptureEvents, it doesn't have to watch it.
+
+//what happened to the stream
+#[derive(Debug)]
+pub(crate) enum CaptureEvent {
+    State { source: usize, state: StreamState },
+    //with whatever went wrong. the thread's done for unless it's a stream's, and there are others left.
+    Error(String),
+    //the producer's gone, say because the sharing got stopped from the compositor's side. once they all are, so's the thread.
+    Ended { source: usize },
+}
+
+//a node to capture straight from the local daemon, as given on the command line
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum CaptureNode {
+    Id(u32),
+    //a node.name, or anything else target.object takes
+    Name(String),
+}
+
+impl CaptureNode {
+    pub fn parse(node: &str) -> CaptureNode {
+        node.parse().map_or_else(|_| CaptureNode::Name(node.to_owned()), CaptureNode::Id)
+    }
+}
+
+pub(crate) struct CaptureSession {
+    //the EnumFormat params to connect with, see capture::offer
+    offer: Vec<Vec<u8>>,
+    //by source
+    negotiated: Vec<Arc<Mutex<Negotiated>>>,
+    events: mpsc::Receiver<CaptureEvent>,
+    sender: mpsc::Sender<CaptureEvent>,
+    //the running thread, what quits its loop, and how far it got
+    thread: Option<(pipewire::channel::Sender<()>, thread::JoinHandle<()>, Arc<Mutex<Phase>>)>,
+    //whether the next start asks the user again, instead of restoring what was picked the last time
+    reselect: bool,
+    //what to capture without the portal, one per source. the portal's asked if there's none.
+    nodes: Vec<CaptureNode>,
+}
+
+impl CaptureSession {
+    //doesn't start anything yet. the frames end up in `negotiated`, one per source.
+    //  with `reselect`, the first start ignores the restore token (see --reselect-capture). with `nodes`, there's no
+    //  portal at all.
+    pub fn new(offer: Vec<Vec<u8>>, negotiated: Vec<Arc<Mutex<Negotiated>>>, reselect: bool, nodes: Vec<CaptureNode>) -> CaptureSession {
+        let (sender, events) = mpsc::channel();
+        CaptureSession { offer, negotiated, events, sender, thread: None, reselect, nodes }
+    }
+
+    //does nothing if it's running already
+    pub fn start(&mut self) -> io::Result<()> {
+        if self.running() { return Ok(()) }
+        //whatever's left of the last one is done, it only has to be joined
+        self.stop();
+        let (quit, quit_recv) = pipewire::channel::channel::<()>();
+        let offer = self.offer.clone();
+        let negotiated = self.negotiated.clone();
+        let events = self.sender.clone();
+        let target = match (self.nodes.is_empty(), mem::take(&mut self.reselect)) {
+            (false, _) => Target::Nodes(self.nodes.clone()),
+            (true, true) => Target::Portal(None),
+            (true, false) => Target::Portal(load_token()),
+        };
+        let phase = Arc::new(Mutex::new(match target {
+            Target::Portal(_) => Phase::Portal,
+            Target::Nodes(_) => Phase::Streaming,
+        }));
+        let thread_phase = phase.clone();
+        let handle = thread::Builder::new()
+            .name("pipewire".to_owned())
+            .spawn(move || {
+                let result = run(offer, negotiated.clone(), events.clone(), quit_recv, target, &thread_phase);
+                //nobody's listening anymore, and the Negotiated might be another thread's by now
+                if *thread_phase.lock().unwrap() == Phase::Abandoned { return }
+                //the streams took their buffers with them
+                negotiated.iter().for_each(|negotiated| negotiated.lock().unwrap().reset());
+                if let Err(e) = result {
+                    error!("Capture: {e}");
+                    let _ = events.send(CaptureEvent::Error(e.to_string()));
+                }
+            })?;
+        self.thread = Some((quit, handle, phase));
+        Ok(())
+    }
+
+    //quits the stream's loop and waits for the thread. whatever the main thread imported shows up in
+    //  Negotiated::removed after this. a portal that's still asking what to share can take as long as the user
+    //  does, that thread's left to finish on its own and whatever it gets thrown away.
+    pub fn stop(&mut self) {
+        let Some((quit, handle, phase)) = self.thread.take() else { return };
+        {
+            let mut phase = phase.lock().unwrap();
+            if *phase == Phase::Portal {
+                debug!("Capture: leaving the portal's dialog to itself");
+                *phase = Phase::Abandoned;
+                return }
+        }
+        //a thread that's done already isn't listening anymore
+        let _ = quit.send(());
+        if handle.join().is_err() { error!("Capture: the pipewire thread panicked") }
+    }
+
+    //asks the user again, which is how to get at another monitor or window
+    pub fn restart(&mut self) -> io::Result<()> {
+        self.stop();
+        self.reselect = true;
+        self.start()
+    }
+
+    pub fn running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|(_, handle, _)| !handle.is_finished())
+    }
+
+    //whatever happened since the last time
+    pub fn events(&self) -> mpsc::TryIter<'_, CaptureEvent> {
+        self.events.try_iter()
+    }
+}
+
+impl Drop for CaptureSession {
+    fn drop(&mut self) {
+        self.stop()
+    }
+}
+
+//how far the thread got, for stop to know whether to wait for it
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    //asking the portal, there's no quitting that
+    Portal,
+    Streaming,
+    //stopped while still asking the portal, and not waited for
+    Abandoned,
+}
+
+//what the tokens are asked to last, kept in the file along with them. one from another mode doesn't get handed back.
+const PERSIST_MODE: (portal_screencast_waycap::PersistMode, &str) = (portal_screencast_waycap::PersistMode::ExplicitlyRevoked, "explicitly-revoked");
+
+//where the streams come from
+enum Target {
+    //with the restore token, if there's one to skip the dialog with
+    Portal(Option<String>),
+    Nodes(Vec<CaptureNode>),
+}
+
+//the thread itself. returns once the loop quits, be it because of `quit` or because all the streams are over.
+fn run(offer: Vec<Vec<u8>>, negotiated: Vec<Arc<Mutex<Negotiated>>>, events: mpsc::Sender<CaptureEvent>,
+       quit: pipewire::channel::Receiver<()>, target: Target, phase: &Mutex<Phase>) -> Result<(), Box<dyn Error + Send + Sync>> {
+    //the portal's session has to outlive the streams, it's what they're shared through
+    let (screencast, pipewire_fd, nodes) = match target {
+        Target::Portal(token) => {
+            let mut screencast = portal_screencast_waycap::ScreenCast::new()?;
+            //lets the user pick as many as they like, the ones past the sources we have go unused
+            if negotiated.len() > 1 { screencast.enable_multiple() }
+            //for the token to outlive the session, and this process
+            screencast.set_persist_mode(PERSIST_MODE.0);
+            if let Some(token) = token {
+                debug!("Capture: restoring the last session");
+                screencast.set_restore_token(&token);
+            }
+            let screencast = screencast.start(None)?;
+            //stopped in the meantime, what got picked goes unused
+            {
+                let mut phase = phase.lock().unwrap();
+                if *phase == Phase::Abandoned { return Ok(()) }
+                *phase = Phase::Streaming;
+            }
+            //every token only works once, the next start needs the new one
+            match screencast.restore_token() {
+                Some(token) => store_token(token),
+                None => debug!("Capture: the portal didn't hand out a restore token"),
+            }
+            let pipewire_fd = screencast.pipewire_fd() as i32;
+            let nodes: Vec<CaptureNode> = screencast.streams().map(|stream| CaptureNode::Id(stream.pipewire_node())).collect();
+            (Some(screencast), Some(pipewire_fd), nodes)
+        }
+        Target::Nodes(nodes) => {
+            info!("Capture: connecting to {nodes:?} directly, without the portal");
+            (None, None, nodes)
+        }
+    };
+    if nodes.is_empty() { return Err("there's nothing to capture".into()) }
+    if nodes.len() > negotiated.len() {
+        warn!("Capture: {} streams to capture, only the first {} are used (see [capture] in ember.conf)", nodes.len(), negotiated.len()) }
+
+    let pw_loop = Arc::new(pipewire::main_loop::MainLoopBox::new(None)?);
+    let pw_context = pipewire::context::ContextBox::new(pw_loop.loop_(), None)?;
+    //the portal's fd only gets at what was shared, the daemon's socket at everything
+    let pw_core = match pipewire_fd {
+        Some(pipewire_fd) => pw_context.connect_fd(unsafe { OwnedFd::from_raw_fd(pipewire_fd) }, None)?,
+        None => pw_context.connect(None)?,
+    };
+    let core_events = events.clone();
+    let _core_listener = pw_core
+        .add_listener_local()
+        .info(|i| debug!("VIDEO CORE:\n{i:#?}"))
+        .error(move |e, f, g, h| {
+            error!("{e},{f},{g},{h}");
+            let _ = core_events.send(CaptureEvent::Error(format!("{h} ({g})")));
+        })
+        .done(|d, _| debug!("DONE: {d}"))
+        .register();
+
+    let running = Rc::new(Cell::new(0));
+    let mut streams = Vec::with_capacity(nodes.len());
+    for (source, (node, negotiated)) in nodes.into_iter().zip(negotiated).enumerate() {
+        //counted before it connects, it might be over before connect even returns
+        running.set(running.get() + 1);
+        let stream = connect(&pw_core, &pw_loop, &offer, source, node, negotiated, events.clone(), running.clone())
+            .inspect_err(|_| running.set(running.get().saturating_sub(1)))?;
+        streams.push(stream);
+    }
+
+    let quit_loop = pw_loop.clone();
+    let _quit = quit.attach(pw_loop.loop_(), move |_| {
+        debug!("Terminating video capture loop");
+        quit_loop.quit();
+    });
+
+    pw_loop.run();
+    //the listeners hold on to the loop, and the streams have to go before the portal session does
+    drop(streams);
+    drop(screencast);
+    Ok(())
+}
+
+//a stream for `node`, be it the portal's or one from --capture-node, leaving what it gets in `negotiated`. the loop quits once there's none of them `running`.
+fn connect<'c>(core: &'c pipewire::core::Core, pw_loop: &Arc<pipewire::main_loop::MainLoopBox>, offer: &[Vec<u8>], source: usize,
+               node: CaptureNode, negotiated: Arc<Mutex<Negotiated>>, events: mpsc::Sender<CaptureEvent>, running: Rc<Cell<usize>>)
+               -> Result<(pipewire::stream::StreamListener<()>, pipewire::stream::StreamBox<'c>), Box<dyn Error + Send + Sync>> {
+    let mut properties = properties! {
+        *pipewire::keys::MEDIA_TYPE => "Video",
+        *pipewire::keys::MEDIA_CATEGORY => "Capture",
+        *pipewire::keys::MEDIA_ROLE => "Screen",
+    };
+    //ids go to connect, names can only be asked for through the properties
+    let node = match node {
+        CaptureNode::Id(id) => Some(id),
+        CaptureNode::Name(name) => {
+            properties.insert(*pipewire::keys::TARGET_OBJECT, name);
+            None }
+    };
+    let stream = pipewire::stream::StreamBox::new(core, &format!("EMBER_CAPTURE_{source}"), properties)?;
+
+    let state_loop = pw_loop.clone();
+    let state_negotiated = negotiated.clone();
+    let param_negotiated = negotiated.clone();
+    let process_negotiated = negotiated.clone();
+    let remove_negotiated = negotiated;
+    let stream_listener = stream
+        .add_local_listener::<()>()
+        .state_changed(move |_, _, old, new| {
+            info!("Video Stream {source} State Changed: {old:?} -> {new:?}");
+            //neither comes back on its own. it's over however far it got, say a --capture-node that isn't there
+            //  goes from connecting straight back. an errored one's over already.
+            let over = match (&old, &new) {
+                (_, StreamState::Error(e)) => Some(CaptureEvent::Error(format!("stream {source}: {e}"))),
+                (StreamState::Error(_) | StreamState::Unconnected, StreamState::Unconnected) => None,
+                (_, StreamState::Unconnected) => Some(CaptureEvent::Ended { source }),
+                _ => None,
+            };
+            let _ = events.send(CaptureEvent::State { source, state: new });
+            if let Some(over) = over {
+                let _ = events.send(over);
+                //its buffers are gone for good
+                state_negotiated.lock().unwrap().reset();
+                running.set(running.get().saturating_sub(1));
+                if running.get() == 0 { state_loop.quit() }
+            }
+        })
+        .param_changed(move |stream, _, id, param| {
+            let Some(param) = param else { return; };
+
+            if id != spa::param::ParamType::Format.as_raw() { return; }
+
+            let (media_type, media_subtype) =
+                match spa::param::format_utils::parse_format(param) {
+                    Ok(v) => v,
+                    Err(_) => return,
+                };
+
+            if media_type != spa::param::format::MediaType::Video
+                || media_subtype != spa::param::format::MediaSubtype::Raw
+            { return; }
+
+            //the producer leaves the modifier to us, the format comes around again once it's fixed
+            if let Some(fixated) = capture::fixate(param) {
+                if let Err(e) = stream.update_params(&mut [spa::pod::Pod::from_bytes(&fixated).unwrap()]) {
+                    warn!("Capture: can't fixate the format ({e})") }
+                return;
+            }
+
+            //the capture image gets made (again) from this once the first buffer is in
+            let Some(format) = param_negotiated.lock().unwrap().format(param) else { return };
+            let buffers = capture::buffers(format.shm);
+            if let Err(e) = stream.update_params(&mut [spa::pod::Pod::from_bytes(&buffers).unwrap()]) {
+                warn!("Capture: can't ask for the buffers ({e})") }
+        })
+        //the buffers stay dequeued while the main thread samples them, see Negotiated::process
+        .process(move |stream, _| unsafe {
+            process_negotiated.lock().unwrap().process(stream);
+        })
+        .remove_buffer(move |_, _, buffer| {
+            remove_negotiated.lock().unwrap().remove(buffer as u64);
+        })
+        .register()?;
+
+    let mut video_params: Vec<&spa::pod::Pod> = offer.iter().map(|param| spa::pod::Pod::from_bytes(param).unwrap()).collect();
+    stream.connect(
+        spa::utils::Direction::Input,
+        node,
+        pipewire::stream::StreamFlags::AUTOCONNECT
+            | pipewire::stream::StreamFlags::MAP_BUFFERS
+            | pipewire::stream::StreamFlags::RT_PROCESS,
+        &mut video_params)?;
+    Ok((stream_listener, stream))
+}
+
+//$XDG_STATE_HOME/ember/restore_token, ~/.local/state/ember/restore_token without
+fn token_path() -> Option<PathBuf> {
+    let state = env::var_os("XDG_STATE_HOME").filter(|state| !state.is_empty()).map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+    Some(state.join("ember").join("restore_token"))
+}
+
+//the persist mode on the first line, the token on the second
+fn load_token() -> Option<String> {
+    let path = token_path()?;
+    match fs::read_to_string(&path) {
+        Ok(stored) => {
+            let mut lines = stored.lines().map(str::trim);
+            if lines.next() != Some(PERSIST_MODE.1) {
+                debug!("Capture: the restore token in {} was asked for differently, not restoring it", path.display());
+                return None }
+            lines.next().filter(|token| !token.is_empty()).map(str::to_owned) }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            warn!("Capture: can't read the restore token from {} ({e})", path.display());
+            None }
+    }
+}
+
+//failing to is no reason to stop, it's only the dialog again next time. it gets written next to the old one and
+//  moved over it, a crash halfway through leaves the old one. only the user gets to read it, it's as good as a
+//  permission to record their screen.
+fn store_token(token: &str) {
+    let Some(path) = token_path() else {
+        warn!("Capture: nowhere to keep the restore token, neither XDG_STATE_HOME nor HOME are set");
+        return };
+    let temp = path.with_extension("tmp");
+    let stored = path.parent().map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(&temp))
+        .and_then(|mut file| {
+            //mode only applies to new files, there might be one left over from a crash
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+            writeln!(file, "{}\n{token}", PERSIST_MODE.1)?;
+            file.sync_all() })
+        .and_then(|_| fs::rename(&temp, &path));
+    match stored {
+        Ok(()) => debug!("Capture: kept the restore token in {}", path.display()),
+        Err(e) => warn!("Capture: can't keep the restore token in {} ({e})", path.display()),
+    }
+}
@@ -1,5 +1,5 @@
 #version 460
-#extension GL_EXT_shader_explicit_arithmetic_types_int64 : enable
+#include <ember/interface.glsl>
 
 #include <lygia/generative/voronoi.glsl>
 #include <lygia/color/hueShift.glsl>
@@ -10,20 +10,12 @@ layout(location = 0) in vec3 outPosition;
 layout(location = 2) in vec2 outTexCoords;
 
 
-layout(push_constant) uniform pc {
-    vec2 rand;
-    float aspect;
-    float t;
-    int id;
-};
-
 layout(binding = 1) uniform sampler2D tex;
 
-layout(std140, binding = 2) buffer MVBufferObject {
-    uint64_t size;
-    ivec2 dims;
-    uint[] data;
-} mv;
+
+//overridden from ember.conf, see [pipeline.basic.specialization]
+layout(constant_id = 0) const int MARGIN = 16;
+layout(constant_id = 1) const int SCALE = 3;
 
 
 
@@ -104,11 +96,16 @@ vec3[5][5] mult(float S, vec3[5][5] arr) {
 }
 
 
+//a single texel of the capture. texelFetch skips the sampler, so a YUV capture would come out unconverted.
+vec4 fetch(sampler2D tex, ivec2 texel) {
+    return texture(tex, (vec2(texel) + 0.5) / pc.capture);
+}
+
 vec3 convolve3(vec3[5][5] kernel, sampler2D tex, vec2 pos) {
     vec3 ret = vec3(0);
     for (int i = 0; i < 5; i++) {
     for (int j = 0; j < 5; j++) {
-        ret += kernel[j][i] * texelFetch(tex, ivec2(pos*vec2(1920,1200)-2)+ivec2(i,j), 0).xyz;
+        ret += kernel[j][i] * fetch(tex, ivec2(pos*pc.capture-2)+ivec2(i,j)).xyz;
     }}
     return ret;
 }
@@ -116,7 +113,7 @@ vec3 convolve3(float[5][5] kernel, sampler2D tex, vec2 pos) {
     vec3 ret = vec3(0);
     for (int i = 0; i < 5; i++) {
         for (int j = 0; j < 5; j++) {
-            ret += kernel[j][i] * texelFetch(tex, ivec2(pos*vec2(1920,1200)-2)+ivec2(i,j), 0).xyz;
+            ret += kernel[j][i] * fetch(tex, ivec2(pos*pc.capture-2)+ivec2(i,j)).xyz;
         }}
     return ret;
 }
@@ -124,7 +121,7 @@ float convolve(float[5][5] kernel, sampler2D tex, vec2 pos) {
     float ret = 0;
     for (int i = 0; i < 5; i++) {
         for (int j = 0; j < 5; j++) {
-            ret += kernel[j][i] * dot(texelFetch(tex, ivec2(pos*vec2(1920,1200)-2)+ivec2(i,j), 0).xyz, vec3(1.0/3.0));
+            ret += kernel[j][i] * dot(fetch(tex, ivec2(pos*pc.capture-2)+ivec2(i,j)).xyz, vec3(1.0/3.0));
         }}
     return ret;
 }
@@ -132,7 +129,7 @@ vec3 convolve3(vec3[3][3] kernel, sampler2D tex, vec2 pos) {
     vec3 ret = vec3(0);
     for (int i = 0; i < 3; i++) {
         for (int j = 0; j < 3; j++) {
-            ret += kernel[j][i] * texelFetch(tex, ivec2(pos*vec2(1920,1200)-1)+ivec2(i,j), 0).xyz;
+            ret += kernel[j][i] * fetch(tex, ivec2(pos*pc.capture-1)+ivec2(i,j)).xyz;
         }}
     return ret;
 }
@@ -140,7 +137,7 @@ vec3 convolve3(float[3][3] kernel, sampler2D tex, vec2 pos) {
     vec3 ret = vec3(0);
     for (int i = 0; i < 3; i++) {
         for (int j = 0; j < 3; j++) {
-            ret += kernel[j][i] * texelFetch(tex, ivec2(pos*vec2(1920,1200)-1)+ivec2(i,j), 0).xyz;
+            ret += kernel[j][i] * fetch(tex, ivec2(pos*pc.capture-1)+ivec2(i,j)).xyz;
         }}
     return ret;
 }
@@ -148,7 +145,7 @@ float convolve(float[3][3] kernel, sampler2D tex, vec2 pos) {
     float ret = 0;
     for (int i = 0; i < 3; i++) {
         for (int j = 0; j < 3; j++) {
-            ret += kernel[j][i] * dot(texelFetch(tex, ivec2(pos*vec2(1920,1200)-1)+ivec2(i,j), 0).xyz, vec3(1.0/3.0));
+            ret += kernel[j][i] * dot(fetch(tex, ivec2(pos*pc.capture-1)+ivec2(i,j)).xyz, vec3(1.0/3.0));
         }}
     return ret;
 }
@@ -158,7 +155,7 @@ float convolve(float[11][11] kernel, sampler2D tex, vec2 pos) {
     float ret = 0;
     for (int i = 0; i < 11; i++) {
         for (int j = 0; j < 11; j++) {
-            ret += kernel[j][i] * dot(texelFetch(tex, ivec2(pos*vec2(1920,1200)-5)+ivec2(i,j), 0).xyz, vec3(1.0/3.0));
+            ret += kernel[j][i] * dot(fetch(tex, ivec2(pos*pc.capture-5)+ivec2(i,j)).xyz, vec3(1.0/3.0));
         }}
     return ret;
 }
@@ -176,12 +173,12 @@ float taxicab(vec3 a, vec3 b) { return taxicab(a.xy, b.xy) + abs(b.z - a.z); }
 
 
 void main() {
-    vec2 pos = outPosition.xy * vec2(aspect,1);
+    vec2 pos = outPosition.xy * vec2(pc.aspect,1);
     vec3 C = vec3(0);
     float A = 1;
 
     /*
-    vec2 tex_pos = ((outPosition.xy + vec2(1))/2  * 1151.0/1200.0) * vec2((1200.0/1920.0)*aspect,1);
+    vec2 tex_pos = ((outPosition.xy + vec2(1))/2  * (pc.capture.y-49.0)/pc.capture.y) * vec2((pc.capture.y/pc.capture.x)*pc.aspect,1);
 
     vec3[3][3] identity = {
         {vec3( 0),vec3( 0),vec3( 0)},
@@ -229,13 +226,13 @@ void main() {
     */
 
 
-    ivec2 dim = mv.dims;
+    ivec2 dim = mv.dimensions;
     int X = int(gl_FragCoord.x);
     int Y = int(gl_FragCoord.y);
     int x = 0;
     int y = 0;
-    int margin = 16;
-    int scale = 3;
+    int margin = MARGIN;
+    int scale = SCALE;
 
     //1920x1122
 
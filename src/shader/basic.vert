@@ -1,4 +1,5 @@
 #version 460
+#include <ember/interface.glsl>
 
 
 layout(location = 0) in vec3 inPosition;
@@ -7,16 +8,10 @@ layout(location = 1) in vec2 inTexCoords;
 layout(location = 2) out vec2 outTexCoords;
 
 
-layout(binding = 0) uniform UniformBufferObject {
-    mat4 model;
-    mat4 view;
-    mat4 proj;
-} ubo;
-
-
 void main() {
 
-    vec3 scale = vec3(1920.0/1200.0, 1.0, 1.0);
+    //the capture's aspect ratio, nothing to stretch without one
+    vec3 scale = vec3(pc.capture.y > 0.0 ? pc.capture.x / pc.capture.y : 1.0, 1.0, 1.0);
     //gl_Position = ubo.proj * ubo.view * ubo.model * vec4(inPosition * scale, 1);
     gl_Position = vec4(inPosition, 1);
     outTexCoords = inTexCoords;
@@ -0,0 +1,32 @@
+#version 460
+//the error pipeline. font.glsl and the TEXT_* constants get pasted in below the #version, see build_error_pipeline.
+
+layout(location = 0) out vec4 color;
+
+
+const int SCALE = 2;                //screen pixels per font pixel
+const ivec2 MARGIN = ivec2(2, 1);   //in characters
+const ivec2 CELL = ivec2(FONT_WIDTH, FONT_HEIGHT);
+
+const vec4 BACKGROUND = vec4(0.10, 0.02, 0.02, 1.0);
+const vec4 HEADING = vec4(1.00, 0.35, 0.30, 1.0);
+const vec4 FOREGROUND = vec4(0.95, 0.90, 0.85, 1.0);
+
+
+bool glyph(uint c, ivec2 pixel) {
+    if (c < 32u || c > 126u) return false;
+    int index = int(c) - 32;
+    ivec2 p = ivec2(index % 16, index / 16) * CELL + pixel;
+    return ((FONT[p.y * FONT_STRIDE + p.x / 32] >> (31 - p.x % 32)) & 1u) != 0u;
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy) / SCALE;
+    ivec2 cell = pixel / CELL - MARGIN;
+    color = BACKGROUND;
+    if (any(lessThan(cell, ivec2(0))) || cell.x >= TEXT_COLUMNS || cell.y >= TEXT_ROWS) return;
+
+    int index = cell.y * TEXT_COLUMNS + cell.x;
+    uint c = (TEXT[index / 4] >> (8 * (index % 4))) & 0xffu;
+    if (glyph(c, pixel % CELL)) color = cell.y == 0 ? HEADING : FOREGROUND;
+}
@@ -0,0 +1,9 @@
+#version 460
+
+//same quad as the basic pipeline, just without anything else
+layout(location = 0) in vec3 inPosition;
+
+
+void main() {
+    gl_Position = vec4(inPosition, 1);
+}
@@ -0,0 +1,46 @@
+//6x13 from the X11 misc-fixed fonts (public domain), ' ' through '~' in a 16x6 grid of glyphs.
+//one bit per pixel, msb first. every pixel row of the grid is 96 bits, so 3 words.
+const int FONT_WIDTH = 6;
+const int FONT_HEIGHT = 13;
+const int FONT_STRIDE = 3;
+const uint FONT[234] = uint[](
+    0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u, 0x00001100u, 0x00000000u,
+    0x00850021u, 0x20082082u, 0x00000002u, 0x0085147au, 0xa408208au, 0x80000002u,
+    0x008514a1u, 0x4a084047u, 0x08000004u, 0x00803ea0u, 0x4a00404au, 0x88000004u,
+    0x00801470u, 0x84004042u, 0x3e03e008u, 0x00803e29u, 0x0a004040u, 0x08000010u,
+    0x00801429u, 0x49804040u, 0x08000010u, 0x000014f2u, 0xa9002080u, 0x00300220u,
+    0x00800022u, 0x46802080u, 0x00200720u, 0x00000000u, 0x00001100u, 0x00400200u,
+    0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u,
+    0x00000000u, 0x00000000u, 0x00000000u, 0x20873e13u, 0xe73e71c0u, 0x0008081cu,
+    0x51888212u, 0x08828a20u, 0x00100422u, 0x8a888432u, 0x08048a22u, 0x08200222u,
+    0x88808852u, 0xc8048a27u, 0x1c43e102u, 0x88811c53u, 0x2f0871e2u, 0x08800084u,
+    0x88820290u, 0x28888820u, 0x00400108u, 0x888402f8u, 0x28908820u, 0x0023e208u,
+    0x50882212u, 0x28908a22u, 0x0c100400u, 0x23ef9c11u, 0xc71071c7u, 0x08080808u,
+    0x00000000u, 0x00000002u, 0x10000000u, 0x00000000u, 0x00000000u, 0x00000000u,
+    0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u,
+    0x708f1cf3u, 0xef9c89c3u, 0xa282289cu, 0x8944a24au, 0x08228881u, 0x22822ca2u,
+    0x8a24a04au, 0x08208881u, 0x24836ca2u, 0x9a24a04au, 0x08208881u, 0x2882aaa2u,
+    0xaa27204bu, 0xcf20f881u, 0x3082aaa2u, 0xabe4a04au, 0x08268881u, 0x288229a2u,
+    0xb224a04au, 0x08228881u, 0x248229a2u, 0x8224a24au, 0x08228889u, 0x228228a2u,
+    0x7a2f1cf3u, 0xe81c89c6u, 0x22fa289cu, 0x00000000u, 0x00000000u, 0x00000000u,
+    0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u,
+    0x00000000u, 0x00000000u, 0x1c01c000u, 0xf1cf1cfau, 0x28a28a2fu, 0x90804200u,
+    0x8a28a222u, 0x28a28a20u, 0x90804500u, 0x8a28a022u, 0x28a25141u, 0x10404880u,
+    0x8a28a022u, 0x28a25141u, 0x10404000u, 0xf22f1c22u, 0x252a2082u, 0x10204000u,
+    0x822a0222u, 0x252a5084u, 0x10104000u, 0x82290222u, 0x252a5084u, 0x10104000u,
+    0x82a8a222u, 0x222a8888u, 0x10084000u, 0x81c89c21u, 0xc214888fu, 0x90084000u,
+    0x00200000u, 0x00000000u, 0x1c01c03eu, 0x00000000u, 0x00000000u, 0x00000000u,
+    0x00000000u, 0x00000000u, 0x00000000u, 0x20000000u, 0x00000000u, 0x00000000u,
+    0x10080008u, 0x03008000u, 0x20600000u, 0x00080008u, 0x04808081u, 0x20200000u,
+    0x00080008u, 0x04008000u, 0x20200000u, 0x01cf1c79u, 0xc41cb183u, 0x24234b1cu,
+    0x0028a28au, 0x2f22c881u, 0x2822aca2u, 0x01e8a08bu, 0xe4228881u, 0x3022a8a2u,
+    0x0228a08au, 0x04228881u, 0x2822a8a2u, 0x0268a28au, 0x241e8881u, 0x2422a8a2u,
+    0x01af1c79u, 0xc40289c9u, 0x2272289cu, 0x00000000u, 0x00220009u, 0x00000000u,
+    0x00000000u, 0x001c0006u, 0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u,
+    0x00000000u, 0x00000000u, 0x06030000u, 0x00000000u, 0x00000000u, 0x0820849cu,
+    0x00000040u, 0x00000000u, 0x08208aa2u, 0x00000040u, 0x00000000u, 0x08208922u,
+    0xf1eb1cf2u, 0x28a28a2fu, 0x88208002u, 0x8a2ca242u, 0x28a25221u, 0x30206004u,
+    0x8a281842u, 0x28aa2222u, 0x08208008u, 0x8a280442u, 0x252a2264u, 0x08208008u,
+    0xf1e8224au, 0x652a51a8u, 0x08208000u, 0x80281c31u, 0xa214882fu, 0x88208008u,
+    0x80200000u, 0x00000220u, 0x06030000u, 0x80200000u, 0x000001c0u, 0x00000000u
+);
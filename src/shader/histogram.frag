@@ -0,0 +1,25 @@
+#version 460
+//the capture, with the histogram from histogram.comp along the bottom edge
+
+
+layout(location = 0) out vec4 color;
+layout(location = 0) in vec3 outPosition;
+layout(location = 2) in vec2 outTexCoords;
+
+
+layout(binding = 1) uniform sampler2D capture;
+layout(std430, binding = 9) readonly buffer Histogram {
+    uint bins[256];
+};
+
+
+//how much of the window the histogram gets
+layout(constant_id = 0) const float HEIGHT = 0.2;
+
+
+void main() {
+    color = texture(capture, outTexCoords);
+    ivec2 size = textureSize(capture, 0);
+    float bin = float(bins[uint(outTexCoords.x * 255.0)]) / float(size.x * size.y) * 64.0;
+    if (1.0 - outTexCoords.y < min(bin, 1.0) * HEIGHT) color = mix(color, vec4(1), 0.5);
+}
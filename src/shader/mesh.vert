@@ -0,0 +1,17 @@
+#version 460
+#include <ember/interface.glsl>
+//for passes drawing a mesh, see [pass.*] in ember.conf. where basic.vert covers the window, this puts the mesh where the ubo says.
+
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 0) out vec3 outPosition;
+layout(location = 1) in vec2 inTexCoords;
+layout(location = 2) out vec2 outTexCoords;
+
+
+void main() {
+    vec4 world = ubo.model * vec4(inPosition, 1);
+    gl_Position = ubo.proj * ubo.view * world;
+    outPosition = world.xyz;
+    outTexCoords = inTexCoords;
+}
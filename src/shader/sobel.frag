@@ -0,0 +1,37 @@
+#version 460
+//edge detection over whatever the previous pass rendered. meant as the last link of a chain, see [pass.*] in ember.conf
+
+
+layout(location = 0) out vec4 color;
+layout(location = 0) in vec3 outPosition;
+layout(location = 2) in vec2 outTexCoords;
+
+
+layout(binding = 3) uniform sampler2D previous;
+
+
+layout(constant_id = 0) const float STRENGTH = 1.0;
+
+
+const float[3][3] SOBEL_X = {
+    {-3,0,3},
+    {-10,0,10},
+    {-3,0,3}};
+const float[3][3] SOBEL_Y = {
+    {-3,-10,-3},
+    {0,0,0},
+    {3,10,3}};
+
+
+void main() {
+    ivec2 pos = ivec2(gl_FragCoord.xy);
+    ivec2 size = textureSize(previous, 0);
+    vec2 gradient = vec2(0);
+    for (int i = 0; i < 3; i++) {
+    for (int j = 0; j < 3; j++) {
+        vec3 texel = texelFetch(previous, clamp(pos + ivec2(i-1,j-1), ivec2(0), size-1), 0).rgb;
+        float luma = dot(texel, vec3(1.0/3.0));
+        gradient += vec2(SOBEL_X[j][i], SOBEL_Y[j][i]) * luma;
+    }}
+    color = vec4(vec3(length(gradient) * STRENGTH / 16.0), 1);
+}
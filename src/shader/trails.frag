@@ -0,0 +1,22 @@
+#version 460
+//the capture, smeared out over time by blending in what the window showed last frame. see [pass.*] in ember.conf
+
+
+layout(location = 0) out vec4 color;
+layout(location = 0) in vec3 outPosition;
+layout(location = 2) in vec2 outTexCoords;
+
+
+layout(binding = 1) uniform sampler2D capture;
+layout(binding = 4) uniform sampler2D feedback;
+
+
+//how much of the last frame is left after one more
+layout(constant_id = 0) const float DECAY = 0.9;
+
+
+void main() {
+    vec4 current = texture(capture, outTexCoords);
+    vec4 trail = texture(feedback, outTexCoords) * DECAY;
+    color = vec4(max(current.rgb, trail.rgb), 1);
+}
@@ -0,0 +1,34 @@
+#version 460
+//stretches what the pass before rendered at a fraction of the window's size over all of it. added as the last pass
+//  by [scale] in ember.conf, which sets the constants below.
+
+
+layout(location = 0) out vec4 color;
+layout(location = 0) in vec3 outPosition;
+layout(location = 2) in vec2 outTexCoords;
+
+
+layout(binding = 3) uniform sampler2D previous;
+
+
+//0 nearest, 1 bilinear, 2 bilinear and sharpened
+layout(constant_id = 0) const int FILTER = 1;
+//how much the sharpening pushes a texel away from its neighbours, 0 to 1
+layout(constant_id = 1) const float SHARPNESS = 0.5;
+
+
+void main() {
+    ivec2 size = textureSize(previous, 0);
+    if (FILTER == 0) {
+        color = texelFetch(previous, clamp(ivec2(outTexCoords * size), ivec2(0), size-1), 0);
+        return;
+    }
+    color = texture(previous, outTexCoords);
+    if (FILTER == 2) {
+        //unsharp mask: the difference to the surrounding texels gets added back on top
+        vec2 texel = 1.0 / vec2(size);
+        vec3 around = (texture(previous, outTexCoords + vec2(texel.x, 0)).rgb + texture(previous, outTexCoords - vec2(texel.x, 0)).rgb
+                     + texture(previous, outTexCoords + vec2(0, texel.y)).rgb + texture(previous, outTexCoords - vec2(0, texel.y)).rgb) / 4.0;
+        color.rgb = max(color.rgb + (color.rgb - around) * SHARPNESS * 2.0, vec3(0));
+    }
+}
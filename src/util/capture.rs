@@ -0,0 +1,634 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::os::fd::{AsFd, AsRawFd, IntoRawFd, OwnedFd};
+use std::os::unix::fs::MetadataExt;
+use std::ptr;
+use ash::{khr, vk, Device};
+use log::warn;
+use crate::INSTANCE;
+use crate::platform::linux::capture::export_sync_file;
+use crate::util::chain::SampledImage;
+use crate::util::config::Config;
+use crate::util::helpers::{create_buffer, memory_type, submit_once};
+use crate::util::upload::Uploader;
+
+
+//the screencast as the passes sample it. the stream says what it sends (see platform::linux::capture), and the image
+//  gets made from that. whenever it changes, say because the monitor's resolution did, the image gets made anew and
+//  the windows' chains get pointed at the new one (see Chain::set_capture).
+//YUV streams get a multi-planar image, and a sampler converting to RGB on the way. the passes don't notice,
+//  they sample it like any other.
+//the compositor might still be writing a buffer when it comes around. its DMA-BUF knows when it's done, and every
+//  frame waits for that on a semaphore (see Capture::acquire).
+//there can be several streams at once, each its own Capture. every window samples one of them as "capture" (see CaptureDesc),
+//  and passes can sample all of them at once as "captures" (see util::chain).
+//without a way to import DMA-BUFs, or a compositor that only has shared memory to offer, the frames get copied
+//  out of the stream and uploaded into an image of our own instead (see Capture::write).
+
+const CAPTURE_SECTION: &str = "capture";
+//the most streams the portal gets asked for
+pub(crate) const MAX_SOURCES: usize = 8;
+
+//the [capture] section: how many monitors or windows to share, and which of them each window shows
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct CaptureDesc {
+    pub sources: usize,
+    //by window, in the order they're made. the ones past the end show the first source.
+    pub windows: Vec<usize>,
+}
+
+impl CaptureDesc {
+    pub fn source(&self, window: usize) -> usize {
+        self.windows.get(window).copied().unwrap_or(0)
+    }
+}
+
+//broken values get warned about and replaced with the defaults: one source, shown everywhere
+pub(crate) fn describe_capture(config: &Config) -> CaptureDesc {
+    let Some(section) = config.section(CAPTURE_SECTION) else { return CaptureDesc { sources: 1, windows: vec![] } };
+    let sources = section.get_or("sources", 1usize);
+    if !(1..=MAX_SOURCES).contains(&sources) {
+        warn!("Config [{}]: sources {sources} is out of range, clamping it to 1..{MAX_SOURCES}", section.name) }
+    let sources = sources.clamp(1, MAX_SOURCES);
+    let windows = section.get::<String>("windows").map_or(vec![], |windows| windows.split(',').map(|source| {
+        match source.trim().parse::<usize>() {
+            Ok(source) if source < sources => source,
+            _ => {
+                warn!("Config [{}]: there's no source \"{}\" among the {sources}, showing the first one", section.name, source.trim());
+                0 }
+        }
+    }).collect());
+    CaptureDesc { sources, windows }
+}
+
+//what the image gets made from
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct CaptureFormat {
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    //DRM format modifier of the buffers. 0 is linear.
+    pub modifier: u64,
+    //how to get to RGB, for the multi-planar formats
+    pub ycbcr: Option<Ycbcr>,
+    //the frames come in shared memory, to be uploaded instead of imported
+    pub shm: bool,
+}
+
+//what the stream's color metadata says about its YUV
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct Ycbcr {
+    pub model: vk::SamplerYcbcrModelConversion,
+    pub range: vk::SamplerYcbcrRange,
+}
+
+//the YUV formats, which sample through a YCbCr conversion
+pub(crate) fn multiplanar(format: vk::Format) -> bool {
+    matches!(format, vk::Format::G8_B8R8_2PLANE_420_UNORM | vk::Format::G8_B8_R8_3PLANE_420_UNORM)
+}
+
+//what there is to sample without a stream
+impl Default for CaptureFormat {
+    fn default() -> Self {
+        CaptureFormat { extent: vk::Extent2D { width: 1920, height: 1200 }, format: vk::Format::B8G8R8A8_SRGB, modifier: 0, ycbcr: None, shm: false }
+    }
+}
+
+//one of a buffer's memory planes, as the stream hands them out
+pub(crate) struct DmaBufPlane {
+    pub fd: OwnedFd,
+    //bytes into the buffer the plane starts at
+    pub offset: u32,
+    //bytes per row
+    pub stride: u32,
+}
+
+//a buffer to import. the planes all live in the same DMA-BUF, so it's the first one's fd that gets imported, the
+//  others only say where their plane is. buffers with a DMA-BUF per plane don't get imported, see same_buffer.
+pub(crate) struct DmaBuf {
+    pub planes: Vec<DmaBufPlane>,
+}
+
+impl DmaBuf {
+    //whether all the planes are in the same DMA-BUF. their fds don't have to be the same for that, but the inode does.
+    fn same_buffer(&self) -> bool {
+        let ids: Vec<Option<(u64, u64)>> = self.planes.iter().map(|plane| plane.fd.try_clone().map(File::from)
+            .and_then(|file| file.metadata()).map(|metadata| (metadata.dev(), metadata.ino())).ok()).collect();
+        ids.iter().all(|id| id.is_some() && *id == ids[0])
+    }
+}
+
+//the modifiers `format` can be imported with as a sampled image, in the order the driver lists them.
+//needs VK_EXT_image_drm_format_modifier and VK_KHR_external_memory_fd, without them there's nothing to import with.
+pub(crate) unsafe fn importable_modifiers(physical_device: vk::PhysicalDevice, format: vk::Format) -> Vec<u64> {
+    let mut modifier_list = vk::DrmFormatModifierPropertiesListEXT::default();
+    INSTANCE.get_physical_device_format_properties2(physical_device, format, &mut vk::FormatProperties2::default().push_next(&mut modifier_list));
+    let mut modifiers = vec![vk::DrmFormatModifierPropertiesEXT::default(); modifier_list.drm_format_modifier_count as usize];
+    modifier_list.p_drm_format_modifier_properties = modifiers.as_mut_ptr();
+    INSTANCE.get_physical_device_format_properties2(physical_device, format, &mut vk::FormatProperties2::default().push_next(&mut modifier_list));
+    modifiers.truncate(modifier_list.drm_format_modifier_count as usize);
+
+    //a modifier the format supports isn't necessarily one a DMA-BUF can be imported with, that's asked separately
+    //YUV gets its chroma interpolated between the samples, see SCHolder::new
+    let features = match multiplanar(format) {
+        true => vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::MIDPOINT_CHROMA_SAMPLES
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_YCBCR_CONVERSION_LINEAR_FILTER,
+        false => vk::FormatFeatureFlags::SAMPLED_IMAGE,
+    };
+    modifiers.into_iter()
+        .filter(|modifier| modifier.drm_format_modifier_tiling_features.contains(features))
+        .map(|modifier| modifier.drm_format_modifier)
+        .filter(|&modifier| {
+            let mut modifier_info = vk::PhysicalDeviceImageDrmFormatModifierInfoEXT {
+                drm_format_modifier: modifier,
+                sharing_mode: vk::SharingMode::EXCLUSIVE,
+                ..Default::default()};
+            let mut external_info = vk::PhysicalDeviceExternalImageFormatInfo {
+                handle_type: vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT,
+                ..Default::default()};
+            let format_info = vk::PhysicalDeviceImageFormatInfo2 {
+                format,
+                ty: vk::ImageType::TYPE_2D,
+                tiling: vk::ImageTiling::DRM_FORMAT_MODIFIER_EXT,
+                usage: vk::ImageUsageFlags::SAMPLED,
+                ..Default::default()}
+                .push_next(&mut external_info)
+                .push_next(&mut modifier_info);
+            let mut external_properties = vk::ExternalImageFormatProperties::default();
+            let mut properties = vk::ImageFormatProperties2::default().push_next(&mut external_properties);
+            INSTANCE.get_physical_device_image_format_properties2(physical_device, &format_info, &mut properties).is_ok()
+                && external_properties.external_memory_properties.external_memory_features.contains(vk::ExternalMemoryFeatureFlags::IMPORTABLE)
+        })
+        .collect()
+}
+
+//how the capture gets sampled. all the buffers of a format share it, so a converting sampler (which is baked into
+//  the passes' layouts) stays the same from one buffer to the next.
+#[derive(Default)]
+pub(crate) struct CaptureSampler {
+    pub sampler: vk::Sampler,
+    //null unless the format's YUV
+    pub conversion: vk::SamplerYcbcrConversion,
+    //for destroying the conversion
+    ycbcr: Option<khr::sampler_ycbcr_conversion::Device>,
+}
+
+impl CaptureSampler {
+    //YUV formats need `ycbcr`, it's loaded only if the device has the feature
+    pub unsafe fn new(device: &Device, physical_device: vk::PhysicalDevice, ycbcr: Option<&khr::sampler_ycbcr_conversion::Device>,
+                      format: CaptureFormat) -> Result<CaptureSampler, vk::Result> {
+        let ycbcr = match (multiplanar(format.format), ycbcr) {
+            (true, Some(ycbcr)) => Some(ycbcr),
+            (true, None) => return Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED),
+            (false, _) => None,
+        };
+        let conversion = match ycbcr {
+            Some(ycbcr) => {
+                let color = format.ycbcr.unwrap_or(Ycbcr { model: vk::SamplerYcbcrModelConversion::YCBCR_709, range: vk::SamplerYcbcrRange::ITU_NARROW });
+                let conversion_info = vk::SamplerYcbcrConversionCreateInfo {
+                    format: format.format,
+                    ycbcr_model: color.model,
+                    ycbcr_range: color.range,
+                    components: vk::ComponentMapping::default(),
+                    x_chroma_offset: vk::ChromaLocation::MIDPOINT,
+                    y_chroma_offset: vk::ChromaLocation::MIDPOINT,
+                    chroma_filter: vk::Filter::LINEAR,
+                    force_explicit_reconstruction: vk::FALSE,
+                    ..Default::default()};
+                ycbcr.create_sampler_ycbcr_conversion(&conversion_info, None)?
+            }
+            None => vk::SamplerYcbcrConversion::null(),
+        };
+        let conversion_info = vk::SamplerYcbcrConversionInfo { conversion, ..Default::default() };
+
+        //a converting sampler can't do anisotropy or borders
+        let address_mode = if ycbcr.is_some() { vk::SamplerAddressMode::CLAMP_TO_EDGE } else { vk::SamplerAddressMode::CLAMP_TO_BORDER };
+        let sampler_info = vk::SamplerCreateInfo {
+            p_next: if ycbcr.is_some() { ptr::from_ref(&conversion_info).cast() } else { ptr::null() },
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: if ycbcr.is_some() { vk::FALSE } else { vk::TRUE },
+            max_anisotropy: 4f32.min(INSTANCE.get_physical_device_properties(physical_device).limits.max_sampler_anisotropy),
+            compare_enable: vk::FALSE,
+            compare_op: vk::CompareOp::ALWAYS,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: vk::BorderColor::FLOAT_OPAQUE_BLACK,
+            unnormalized_coordinates: vk::FALSE,
+            ..Default::default()};
+        let sampler = match device.create_sampler(&sampler_info, None) {
+            Ok(sampler) => sampler,
+            Err(e) => {
+                if let Some(ycbcr) = ycbcr { ycbcr.destroy_sampler_ycbcr_conversion(conversion, None) }
+                return Err(e) }
+        };
+        Ok(CaptureSampler { sampler, conversion, ycbcr: ycbcr.cloned() })
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        device.destroy_sampler(self.sampler, None);
+        if let Some(ycbcr) = &self.ycbcr { ycbcr.destroy_sampler_ycbcr_conversion(self.conversion, None) }
+    }
+}
+
+//a single image of the capture
+#[derive(Default)]
+pub(crate) struct SCHolder {
+    pub mem: vk::DeviceMemory,
+    pub img: vk::Image,
+    pub view: vk::ImageView,
+    pub format: CaptureFormat,
+    //what's imported, to ask for its fences
+    pub dmabuf: Option<OwnedFd>,
+}
+
+impl SCHolder {
+    //imports `buffer`, laid out as its planes say. without one it's an image of our own, which only gets cleared or
+    //  uploaded into (see Capture::new). the view is made for `sampler`, which matters if it converts.
+    pub unsafe fn new(device: &Device, physical_device: vk::PhysicalDevice, extmem_fd: Option<&khr::external_memory_fd::Device>,
+                      sampler: &CaptureSampler, format: CaptureFormat, buffer: Option<DmaBuf>) -> Result<SCHolder, vk::Result> {
+        let buffer = match (buffer, extmem_fd) {
+            (Some(buffer), _) if buffer.planes.is_empty() || !buffer.same_buffer() => return Err(vk::Result::ERROR_INVALID_EXTERNAL_HANDLE),
+            (Some(buffer), Some(extmem_fd)) => Some((buffer, extmem_fd)),
+            (Some(_), None) => return Err(vk::Result::ERROR_EXTENSION_NOT_PRESENT),
+            (None, _) => None,
+        };
+
+        //the layout is the buffer's, not something the driver gets to pick. the size has to be left 0.
+        let plane_layouts = buffer.as_ref().map_or(vec![], |(buffer, _)| buffer.planes.iter()
+            .map(|plane| vk::SubresourceLayout { offset: plane.offset as u64, size: 0, row_pitch: plane.stride as u64, array_pitch: 0, depth_pitch: 0 })
+            .collect());
+        let drm_format_modifier_info = vk::ImageDrmFormatModifierExplicitCreateInfoEXT {
+            drm_format_modifier: format.modifier,
+            drm_format_modifier_plane_count: plane_layouts.len() as u32,
+            p_plane_layouts: plane_layouts.as_ptr(),
+            ..Default::default() };
+        let ext_img_info = vk::ExternalMemoryImageCreateInfo {
+            p_next: ptr::from_ref(&drm_format_modifier_info).cast(),
+            handle_types: vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT,
+            ..Default::default()};
+        let img_info = vk::ImageCreateInfo {
+            p_next: if buffer.is_some() { ptr::from_ref(&ext_img_info).cast() } else { ptr::null() },
+            image_type: vk::ImageType::TYPE_2D,
+            format: format.format,
+            extent: vk::Extent3D { width: format.extent.width, height: format.extent.height, depth: 1 },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: if buffer.is_some() { vk::ImageTiling::DRM_FORMAT_MODIFIER_EXT } else { vk::ImageTiling::OPTIMAL },
+            //ours get cleared, and shared memory frames copied in. see Capture::upload
+            usage: if buffer.is_some() { vk::ImageUsageFlags::SAMPLED } else { vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST },
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            ..Default::default()};
+        let img = device.create_image(&img_info, None)?;
+
+        //imported memory can only go where the fd allows, and drivers tend to want it dedicated to the image
+        let mut requirements = device.get_image_memory_requirements(img);
+        let fd = buffer.map(|(buffer, extmem_fd)| (buffer.planes.into_iter().next().unwrap().fd, extmem_fd));
+        //the import takes the fd, the fences are asked for on another one
+        let dmabuf = fd.as_ref().and_then(|(fd, _)| fd.try_clone().ok());
+        if let Some((fd, extmem_fd)) = &fd {
+            let mut fd_properties = vk::MemoryFdPropertiesKHR::default();
+            let bits = extmem_fd.get_memory_fd_properties(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT, fd.as_raw_fd(), &mut fd_properties)
+                .map(|_| fd_properties.memory_type_bits);
+            match bits {
+                Ok(bits) if bits & requirements.memory_type_bits != 0 => requirements.memory_type_bits &= bits,
+                failed => {
+                    device.destroy_image(img, None);
+                    return Err(failed.err().unwrap_or(vk::Result::ERROR_INVALID_EXTERNAL_HANDLE)) }
+            }
+        }
+        let dedicated_info = vk::MemoryDedicatedAllocateInfo { image: img, ..Default::default() };
+        let mem_import_info = vk::ImportMemoryFdInfoKHR {
+            p_next: ptr::from_ref(&dedicated_info).cast(),
+            handle_type: vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT,
+            fd: fd.as_ref().map_or(-1, |(fd, _)| fd.as_raw_fd()),
+            ..Default::default()};
+        let mem_alloc_info = vk::MemoryAllocateInfo {
+            p_next: if fd.is_some() { ptr::from_ref(&mem_import_info).cast() } else { ptr::null() },
+            allocation_size: requirements.size,
+            memory_type_index: memory_type(physical_device, requirements, vk::MemoryPropertyFlags::empty()),
+            ..Default::default()};
+        //a successful import takes the fd with it, a failed one leaves it to us
+        let mem = match device.allocate_memory(&mem_alloc_info, None) {
+            Ok(mem) => {
+                if let Some((fd, _)) = fd { fd.into_raw_fd(); }
+                mem }
+            Err(e) => {
+                device.destroy_image(img, None);
+                return Err(e) }
+        };
+        if let Err(e) = device.bind_image_memory(img, mem, 0) {
+            device.destroy_image(img, None);
+            device.free_memory(mem, None);
+            return Err(e)
+        }
+
+        let conversion_info = vk::SamplerYcbcrConversionInfo { conversion: sampler.conversion, ..Default::default() };
+        let view_info = vk::ImageViewCreateInfo {
+            p_next: if sampler.conversion != vk::SamplerYcbcrConversion::null() { ptr::from_ref(&conversion_info).cast() } else { ptr::null() },
+            image: img,
+            view_type: vk::ImageViewType::TYPE_2D,
+            format: format.format,
+            components: vk::ComponentMapping::default(),
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            ..Default::default()};
+        let view = match device.create_image_view(&view_info, None) {
+            Ok(view) => view,
+            Err(e) => {
+                device.destroy_image(img, None);
+                device.free_memory(mem, None);
+                return Err(e) }
+        };
+
+        Ok(SCHolder { mem, img, view, format, dmabuf })
+    }
+
+    pub fn sampled(&self, sampler: &CaptureSampler) -> SampledImage {
+        SampledImage { image: self.img, view: self.view, sampler: sampler.sampler, extent: self.format.extent,
+                       converts: sampler.conversion != vk::SamplerYcbcrConversion::null(), owned: self.dmabuf.is_none() }
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        device.destroy_image_view(self.view, None);
+        device.destroy_image(self.img, None);
+        device.free_memory(self.mem, None);
+    }
+}
+
+
+//the stream's buffers as images, by buffer. pipewire cycles through a handful of them, each gets imported the first
+//  time it comes around and is reused after. switching between them only takes rewriting descriptors, see Chain::set_capture.
+pub(crate) struct Capture {
+    pub format: CaptureFormat,
+    sampler: CaptureSampler,
+    //what gets sampled as long as there's no buffer
+    placeholder: SCHolder,
+    buffers: HashMap<u64, SCHolder>,
+    //the buffer that gets sampled, if any
+    pub current: Option<u64>,
+    //buffers that didn't import, so that's only warned about once. see Capture::reject
+    rejected: HashSet<u64>,
+    //the last format there were no images to be had for, so it doesn't get tried again with every frame
+    unusable: Option<CaptureFormat>,
+    //the newest shared memory frame and its stride, waiting to be uploaded into the placeholder
+    pending: Option<(Vec<u8>, u32)>,
+    physical_device: vk::PhysicalDevice,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    extmem_fd: Option<khr::external_memory_fd::Device>,
+    extsem_fd: Option<khr::external_semaphore_fd::Device>,
+    ycbcr: Option<khr::sampler_ycbcr_conversion::Device>,
+}
+
+impl Capture {
+    //`queue` and `command_pool` are for getting the placeholder ready, see clear
+    pub unsafe fn new(device: &Device, physical_device: vk::PhysicalDevice, queue: vk::Queue, command_pool: vk::CommandPool,
+                      extmem_fd: Option<&khr::external_memory_fd::Device>, extsem_fd: Option<&khr::external_semaphore_fd::Device>,
+                      ycbcr: Option<&khr::sampler_ycbcr_conversion::Device>, format: CaptureFormat) -> Result<Capture, vk::Result> {
+        let sampler = CaptureSampler::new(device, physical_device, ycbcr, format)?;
+        let placeholder = match SCHolder::new(device, physical_device, None, &sampler, format, None) {
+            Ok(placeholder) => placeholder,
+            Err(e) => {
+                sampler.destroy(device);
+                return Err(e) }
+        };
+        //black until the first frame is in. the uploads expect it sampleable from the start, as does the graph (see SampledImage::start).
+        clear(device, physical_device, queue, command_pool, placeholder.img, format);
+        Ok(Capture {
+            format, sampler, placeholder, physical_device, queue, command_pool,
+            buffers: HashMap::new(),
+            current: None,
+            rejected: HashSet::new(),
+            unusable: None,
+            pending: None,
+            extmem_fd: extmem_fd.cloned(),
+            extsem_fd: extsem_fd.cloned(),
+            ycbcr: ycbcr.cloned() })
+    }
+
+    //whether frames in `format` call for a reformat: it's not what the images are made for, and didn't fail to be before
+    pub fn wants(&self, format: CaptureFormat) -> bool {
+        format != self.format && self.unusable != Some(format)
+    }
+
+    //the stream changed its format, none of what's there fits anymore. the device has to be idle.
+    //  if the new format can't be had, it's the old one still, with no buffers.
+    pub unsafe fn reformat(&mut self, device: &Device, format: CaptureFormat) -> Result<(), vk::Result> {
+        let capture = Capture::new(device, self.physical_device, self.queue, self.command_pool, self.extmem_fd.as_ref(), self.extsem_fd.as_ref(),
+                                   self.ycbcr.as_ref(), format);
+        self.buffers.drain().for_each(|(_, holder)| holder.destroy(device));
+        self.rejected.clear();
+        self.current = None;
+        if capture.is_err() { self.unusable = Some(format) }
+        let capture = capture?;
+        self.destroy(device);
+        *self = capture;
+        Ok(())
+    }
+
+    //makes `key` the buffer that gets sampled, importing it from `planes` if it's new. a buffer that isn't
+    //  imported yet and comes without them is of no use.
+    pub unsafe fn show(&mut self, device: &Device, key: u64, planes: Option<DmaBuf>) -> Result<(), vk::Result> {
+        if !self.buffers.contains_key(&key) {
+            let Some(planes) = planes else { return Err(vk::Result::ERROR_INVALID_EXTERNAL_HANDLE) };
+            let holder = SCHolder::new(device, self.physical_device, self.extmem_fd.as_ref(), &self.sampler, self.format, Some(planes))?;
+            self.buffers.insert(key, holder);
+        }
+        self.rejected.remove(&key);
+        self.current = Some(key);
+        Ok(())
+    }
+
+    //`key` didn't import. true the first time, for as long as pipewire has the buffer.
+    pub fn reject(&mut self, key: u64) -> bool {
+        self.rejected.insert(key)
+    }
+
+    //a shared memory frame, `stride` bytes per row. it's the placeholder that gets sampled, the frame gets written
+    //  into it with the next one rendered (see Capture::upload). frames that weren't uploaded by then are skipped.
+    pub fn write(&mut self, pixels: Vec<u8>, stride: u32) {
+        self.current = None;
+        self.pending = Some((pixels, stride));
+    }
+
+    //stages the pending frame, if there is one, for the frame's command buffer. the first window that renders does it,
+    //  the others sample what it wrote. see Uploader::record_images.
+    pub unsafe fn upload(&mut self, device: &Device, uploader: &mut Uploader, frame: usize) {
+        let Some((pixels, stride)) = self.pending.take() else { return };
+        let extent = self.format.extent;
+        //all the formats there's shared memory for are 4 bytes to a pixel. no stride means tightly packed.
+        let stride = if stride == 0 { extent.width * 4 } else { stride };
+        if stride % 4 != 0 || (stride as usize) < extent.width as usize * 4
+            || pixels.len() < (stride as usize) * (extent.height as usize - 1) + extent.width as usize * 4 {
+            warn!("Capture: a frame of {} bytes, {stride} to a row, doesn't fit {}x{}", pixels.len(), extent.width, extent.height);
+            return }
+        let copy = vk::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: stride / 4,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers { aspect_mask: vk::ImageAspectFlags::COLOR, mip_level: 0, base_array_layer: 0, layer_count: 1 },
+            image_offset: vk::Offset3D::default(),
+            image_extent: vk::Extent3D { width: extent.width, height: extent.height, depth: 1 },
+        };
+        uploader.write_image(device, self.physical_device, frame, self.placeholder.img, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, copy, &pixels);
+    }
+
+    //pipewire took the buffer away. the device has to be idle.
+    pub unsafe fn forget(&mut self, device: &Device, key: u64) {
+        if let Some(holder) = self.buffers.remove(&key) { holder.destroy(device) }
+        self.rejected.remove(&key);
+        if self.current == Some(key) { self.current = None }
+    }
+
+    //right before a frame that samples the capture gets submitted: hands the fences of whatever's still writing the
+    //  current buffer to `semaphore`, for the frame to wait on. false if there's nothing to wait for, or no way to.
+    //the import is temporary, the wait uses it up and the semaphore's back to what it was.
+    pub unsafe fn acquire(&mut self, semaphore: vk::Semaphore) -> bool {
+        let Some(extsem_fd) = &self.extsem_fd else { return false };
+        let Some(dmabuf) = self.current.and_then(|key| self.buffers.get(&key)).and_then(|holder| holder.dmabuf.as_ref()) else { return false };
+        let sync_file = match export_sync_file(dmabuf.as_fd()) {
+            Ok(sync_file) => sync_file,
+            //older kernels can't, and won't start to. the frames go without from now on.
+            Err(e) => {
+                warn!("Capture: can't get the buffer's fences ({e}), not synchronizing with the compositor");
+                self.extsem_fd = None;
+                return false }
+        };
+        let import_info = vk::ImportSemaphoreFdInfoKHR {
+            semaphore,
+            flags: vk::SemaphoreImportFlags::TEMPORARY,
+            handle_type: vk::ExternalSemaphoreHandleTypeFlags::SYNC_FD,
+            fd: sync_file.as_raw_fd(),
+            ..Default::default()};
+        match extsem_fd.import_semaphore_fd(&import_info) {
+            //the semaphore owns it now
+            Ok(()) => {
+                sync_file.into_raw_fd();
+                true }
+            Err(e) => {
+                warn!("Capture: can't import the buffer's fences ({e})");
+                false }
+        }
+    }
+
+    //what the windows sample
+    pub fn sampled(&self) -> SampledImage {
+        self.current.and_then(|key| self.buffers.get(&key)).unwrap_or(&self.placeholder).sampled(&self.sampler)
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        self.buffers.values().for_each(|holder| holder.destroy(device));
+        self.placeholder.destroy(device);
+        self.sampler.destroy(device);
+    }
+}
+
+//UNDEFINED to SHADER_READ_ONLY_OPTIMAL by way of black. YUV can't be cleared, its planes get copied in instead.
+unsafe fn clear(device: &Device, physical_device: vk::PhysicalDevice, queue: vk::Queue, command_pool: vk::CommandPool, image: vk::Image,
+                format: CaptureFormat) {
+    let range = vk::ImageSubresourceRange { aspect_mask: vk::ImageAspectFlags::COLOR, base_mip_level: 0, level_count: 1, base_array_layer: 0, layer_count: 1 };
+    let barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| vk::ImageMemoryBarrier {
+        src_access_mask, dst_access_mask, old_layout, new_layout,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        image,
+        subresource_range: range,
+        ..Default::default()};
+    let planes = black_planes(format);
+    let staging = (!planes.is_empty()).then(|| {
+        let size: usize = planes.iter().map(|(_, _, data)| data.len()).sum();
+        let (buffer, memory) = create_buffer(device, physical_device, size as u64, vk::BufferUsageFlags::TRANSFER_SRC,
+                                             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT);
+        let mapped = device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty()).unwrap().cast::<u8>();
+        let mut copies = Vec::with_capacity(planes.len());
+        let mut offset = 0;
+        for (aspect_mask, extent, data) in &planes {
+            ptr::copy_nonoverlapping(data.as_ptr(), mapped.add(offset), data.len());
+            copies.push(vk::BufferImageCopy {
+                buffer_offset: offset as u64,
+                image_subresource: vk::ImageSubresourceLayers { aspect_mask: *aspect_mask, mip_level: 0, base_array_layer: 0, layer_count: 1 },
+                image_extent: vk::Extent3D { width: extent.width, height: extent.height, depth: 1 },
+                ..Default::default()});
+            offset += data.len();
+        }
+        device.unmap_memory(memory);
+        (buffer, memory, copies)
+    });
+    submit_once(device, queue, command_pool, |command_buffer| {
+        device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TOP_OF_PIPE, vk::PipelineStageFlags::TRANSFER, vk::DependencyFlags::empty(), &[], &[],
+                                    &[barrier(vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::AccessFlags::empty(), vk::AccessFlags::TRANSFER_WRITE)]);
+        match &staging {
+            Some((buffer, _, copies)) => device.cmd_copy_buffer_to_image(command_buffer, *buffer, image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, copies),
+            None => device.cmd_clear_color_image(command_buffer, image, vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                                                 &vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] }, &[range]),
+        }
+        device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                                    vk::DependencyFlags::empty(), &[], &[],
+                                    &[barrier(vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::SHADER_READ)]);
+    });
+    if let Some((buffer, memory, _)) = staging {
+        device.destroy_buffer(buffer, None);
+        device.free_memory(memory, None);
+    }
+}
+
+//black for each plane of a YUV format: the lowest luma there is, and chroma right in the middle. nothing for RGB.
+//  every plane's data starts 4 byte aligned, which is what copies into the 2 byte chroma pairs need.
+fn black_planes(format: CaptureFormat) -> Vec<(vk::ImageAspectFlags, vk::Extent2D, Vec<u8>)> {
+    let narrow = format.ycbcr.is_none_or(|ycbcr| ycbcr.range == vk::SamplerYcbcrRange::ITU_NARROW);
+    let luma = vk::Extent2D { width: format.extent.width, height: format.extent.height };
+    let chroma = vk::Extent2D { width: luma.width.div_ceil(2), height: luma.height.div_ceil(2) };
+    let plane = |aspect, extent: vk::Extent2D, texel: usize, value: u8| {
+        let size = (extent.width * extent.height) as usize * texel;
+        (aspect, extent, vec![value; size.next_multiple_of(4)])
+    };
+    let y = plane(vk::ImageAspectFlags::PLANE_0, luma, 1, if narrow { 16 } else { 0 });
+    match format.format {
+        vk::Format::G8_B8R8_2PLANE_420_UNORM => vec![y, plane(vk::ImageAspectFlags::PLANE_1, chroma, 2, 128)],
+        vk::Format::G8_B8_R8_3PLANE_420_UNORM => vec![y, plane(vk::ImageAspectFlags::PLANE_1, chroma, 1, 128), plane(vk::ImageAspectFlags::PLANE_2, chroma, 1, 128)],
+        _ => vec![],
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes() {
+        let cases: [(&str, usize, &[usize]); 8] = [
+            ("", 1, &[]),
+            ("[capture]", 1, &[]),
+            ("[capture]\nsources = 2\nwindows = 1, 0, 1", 2, &[1, 0, 1]),
+            //too many, too few, or not a count at all
+            ("[capture]\nsources = 20", MAX_SOURCES, &[]),
+            ("[capture]\nsources = 0", 1, &[]),
+            ("[capture]\nsources = two", 1, &[]),
+            //sources that aren't there show the first one
+            ("[capture]\nsources = 2\nwindows = 1, 2, x, -1", 2, &[1, 0, 0, 0]),
+            ("[capture]\nwindows = 1", 1, &[0]),
+        ];
+        for (text, sources, windows) in cases {
+            assert_eq!(describe_capture(&Config::from_text(text)), CaptureDesc { sources, windows: windows.to_vec() }, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn assigns_windows() {
+        let desc = CaptureDesc { sources: 3, windows: vec![2, 1] };
+        assert_eq!([0, 1, 2, 5].map(|window| desc.source(window)), [2, 1, 0, 0]);
+    }
+}
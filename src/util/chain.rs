@@ -0,0 +1,1226 @@
+use std::ptr;
+use ash::{khr, vk, Device};
+use log::{error, warn};
+use crate::{MVBufferObject, PushConstants, UniformBufferObject, INSTANCE, MAX_FRAMES_IN_FLIGHT};
+use crate::util::capture::describe_capture;
+use crate::util::config::{Config, Section};
+use crate::util::helpers::{build_compute_pipeline, build_error_pipeline, build_graphics_pipeline, compile_pass, create_render_pass, DynamicRendering};
+use crate::util::graph::{Access, BufferId, ImageId, RenderGraph};
+use crate::util::mesh::Mesh;
+use crate::util::reflect::{DescriptorBinding, RustBinding, ShaderInterface};
+use crate::util::scale::scaled;
+use crate::util::shaders::{Diagnostic, ShaderError, ShaderSources};
+use crate::util::texture::{Pixels, Texture, TextureDesc};
+
+
+//the passes every window renders, in the order they're declared in the config:
+//
+//  [pass.blur]
+//  fragment = src/shader/blur.frag
+//  inputs = capture, mv
+//  format = R16G16B16A16_SFLOAT
+//
+//  [pass.edges]
+//  fragment = src/shader/sobel.frag
+//  inputs = previous
+//  samples = 4
+//
+//every pass but the last renders into an offscreen image of its own, which the next pass can sample as "previous".
+//the last one renders into the swapchain image, so its format is whatever the swapchain has.
+//passes with more than one sample render into a multisampled image of their own, which gets resolved into their output.
+//passes with a mesh draw that instead of the fullscreen quad, with a depth buffer and mesh.vert (which applies the ubo's
+//  model/view/proj) as their default vertex shader:
+//
+//  [pass.screen]
+//  mesh = assets/monitor.glb
+//  inputs = capture, mv
+//"capture" is the screencast the window shows (see util::capture). with several sources, "captures" is all of them at once,
+//  an array with as many elements as [capture] sources says, at binding 5:
+//
+//  [pass.wall]
+//  inputs = captures
+//
+//  layout(binding = 5) uniform sampler2D captures[2];
+//any pass can sample what the last one drew the frame before as "feedback". if one does, the last pass renders into
+//  a ping-pong pair instead (see RenderGraph::ping_pong), which gets copied into the swapchain image.
+//
+//compute passes come before all of that, in the order they're declared:
+//
+//  [compute.histogram]
+//  shader = src/shader/histogram.comp
+//  inputs = capture
+//  buffer = 1024
+//  binding = 8
+//
+//they write a swapchain-sized storage image, a storage buffer (zeroed every frame), or both. both live at the same
+//  bindings for the compute pass and everyone who names it in their inputs: the image at `binding`, the buffer right after.
+//  the compute pass gets a storage image there, the readers a sampler.
+//groups are the workgroup counts to dispatch. by default one invocation per pixel of the window.
+//
+//textures are images from files, for masks, LUTs, noise and the like. passes name them in their inputs, and get them
+//  as a sampler at their binding:
+//
+//  [texture.noise]
+//  path = assets/noise.png
+//  binding = 32
+//  wrap = repeat
+//without any [pass.*] sections the chain is just the basic pipeline, straight into the swapchain.
+//
+//with a [scale] section (see util::scale) everything but the last pass renders at a fraction of the window's size,
+//  and the upscale pass gets added at the end to stretch it back out. a factor of 1 that doesn't adapt adds nothing.
+const PASS_PREFIX: &str = "pass";
+const COMPUTE_PREFIX: &str = "compute";
+const TEXTURE_PREFIX: &str = "texture";
+const BASIC_PIPELINE: &str = "pipeline.basic";
+const BASIC_SPECIALIZATION: &str = "pipeline.basic.specialization";
+const BASIC_VERTEX: &str = "src/shader/basic.vert";
+const MESH_VERTEX: &str = "src/shader/mesh.vert";
+const BASIC_FRAGMENT: &str = "src/shader/basic.frag";
+const UPSCALE_FRAGMENT: &str = "src/shader/upscale.frag";
+const PASS_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+//where the inputs end up in a pass's descriptor set. the ubo is always there, at binding 0.
+const CAPTURE_BINDING: RustBinding = RustBinding { set: 0, binding: 1, descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER, count: 1,
+    name: "capture", size: None, tail_stride: None };
+const PREVIOUS_BINDING: RustBinding = RustBinding { set: 0, binding: 3, descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER, count: 1,
+    name: "previous", size: None, tail_stride: None };
+const FEEDBACK_BINDING: RustBinding = RustBinding { set: 0, binding: 4, descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER, count: 1,
+    name: "feedback", size: None, tail_stride: None };
+//one element per source, see PassInput::Captures
+const CAPTURES_BINDING: RustBinding = RustBinding { set: 0, binding: 5, descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER, count: 1,
+    name: "captures", size: None, tail_stride: None };
+const UBO: u32 = UniformBufferObject::BINDING.binding;
+const CAPTURE: u32 = CAPTURE_BINDING.binding;
+const MV: u32 = MVBufferObject::BINDING.binding;
+const PREVIOUS: u32 = PREVIOUS_BINDING.binding;
+const FEEDBACK: u32 = FEEDBACK_BINDING.binding;
+const CAPTURES: u32 = CAPTURES_BINDING.binding;
+//compute outputs go from here on, two bindings per compute pass. they'd collide with the fixed ones otherwise.
+const COMPUTE_BINDINGS: u32 = 8;
+//textures go from here on by default, one binding each. they can go anywhere compute outputs don't, though.
+const TEXTURE_BINDINGS: u32 = 32;
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum PassInput {
+    Capture,
+    //every source, by index. the count is what the config asks for, sources that aren't there are black.
+    Captures { count: u32 },
+    Previous,
+    Mv,
+    Feedback,
+    //the outputs of the compute pass at index `pass`, see PassKind::Compute
+    Compute { pass: usize, binding: u32, image: bool, buffer: bool },
+}
+
+impl PassInput {
+    fn bindings(self) -> Vec<RustBinding> {
+        match self {
+            PassInput::Capture => vec![CAPTURE_BINDING],
+            PassInput::Captures { count } => vec![RustBinding { count, ..CAPTURES_BINDING }],
+            PassInput::Previous => vec![PREVIOUS_BINDING],
+            PassInput::Mv => vec![MVBufferObject::BINDING],
+            PassInput::Feedback => vec![FEEDBACK_BINDING],
+            PassInput::Compute { binding, image, buffer, .. } => compute_bindings(binding, image, buffer, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        }
+    }
+}
+
+//the image at `binding`, the buffer right after it. written as a storage image, read through a sampler.
+fn compute_bindings(binding: u32, image: bool, buffer: bool, image_type: vk::DescriptorType) -> Vec<RustBinding> {
+    let mut bindings = Vec::new();
+    if image { bindings.push(RustBinding { set: 0, binding, descriptor_type: image_type, count: 1,
+        name: "compute image", size: None, tail_stride: None }) }
+    if buffer { bindings.push(RustBinding { set: 0, binding: binding + 1, descriptor_type: vk::DescriptorType::STORAGE_BUFFER, count: 1,
+        name: "compute buffer", size: None, tail_stride: None }) }
+    bindings
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum PassKind {
+    Graphics {
+        vertex: String,
+        fragment: String,
+        //of the offscreen image. ignored for the last pass.
+        format: vk::Format,
+        //as asked for. what the device can do is only known once there's a window, see sample_count
+        samples: u32,
+        //path of an OBJ or glTF file, see util::mesh. the fullscreen quad if there's none.
+        mesh: Option<String>,
+    },
+    Compute {
+        shader: String,
+        //format of the storage image, if it writes one
+        image: Option<vk::Format>,
+        //size of the storage buffer in bytes, if it writes one
+        buffer: Option<u64>,
+        binding: u32,
+        groups: Option<[u32; 3]>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PassDesc {
+    pub name: String,
+    pub kind: PassKind,
+    pub inputs: Vec<PassInput>,
+    //the ones named in its inputs, see util::texture
+    pub textures: Vec<TextureDesc>,
+    pub specialization: Option<Section>,
+}
+
+impl PassDesc {
+    //what the pass's descriptor set gets, for the reflection check
+    pub fn bindings(&self) -> Vec<RustBinding> {
+        let mut bindings = vec![UniformBufferObject::BINDING];
+        bindings.extend(self.inputs.iter().flat_map(|input| input.bindings()));
+        bindings.extend(self.textures.iter().map(|texture| RustBinding { set: 0, binding: texture.binding,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER, count: 1, name: "texture", size: None, tail_stride: None }));
+        if let PassKind::Compute { image, buffer, binding, .. } = self.kind {
+            bindings.extend(compute_bindings(binding, image.is_some(), buffer.is_some(), vk::DescriptorType::STORAGE_IMAGE));
+        }
+        bindings
+    }
+}
+
+//reads the pass list from the config, compute passes first. broken entries get warned about and skipped, never fatal.
+pub(crate) fn describe(config: &Config) -> Vec<PassDesc> {
+    let textures = textures(config);
+    let sources = describe_capture(config).sources as u32;
+    let mut passes: Vec<PassDesc> = Vec::new();
+    for section in config.sections(COMPUTE_PREFIX) {
+        let image = section.get::<String>("image").and_then(|format| parse_format(&format).or_else(|| {
+            warn!("Config [{}]: unsupported format {format}, the pass writes no image", section.name);
+            None }));
+        let buffer = section.get::<u64>("buffer").filter(|size| *size > 0);
+        if image.is_none() && buffer.is_none() {
+            warn!("Config [{}]: a compute pass needs an image or a buffer to write to, skipping it", section.name);
+            continue }
+        //every compute pass before this one took two bindings, every texture one
+        let free = |binding: u32| !passes.iter().any(|pass| matches!(pass.kind, PassKind::Compute { binding: taken, .. } if taken.abs_diff(binding) < 2))
+            && !textures.iter().any(|texture| texture.binding == binding || texture.binding == binding + 1);
+        let default_binding = (COMPUTE_BINDINGS..).step_by(2).find(|binding| free(*binding)).unwrap();
+        let binding = match section.get::<u32>("binding") {
+            Some(binding) if binding < COMPUTE_BINDINGS => {
+                warn!("Config [{}]: binding {binding} is taken by the fixed inputs, using {default_binding}", section.name);
+                default_binding }
+            Some(binding) if !free(binding) => {
+                warn!("Config [{}]: binding {binding} collides with another compute pass or a texture, using {default_binding}", section.name);
+                default_binding }
+            Some(binding) => binding,
+            None => default_binding,
+        };
+        let groups = section.get::<String>("groups").and_then(|groups| {
+            let groups: Vec<u32> = groups.split(',').filter_map(|count| count.trim().parse().ok()).collect();
+            match groups[..] {
+                [x, y, z] if x > 0 && y > 0 && z > 0 => Some([x, y, z]),
+                _ => {
+                    warn!("Config [{}]: groups should be three positive counts, like \"8, 8, 1\"", section.name);
+                    None }
+            }
+        });
+        passes.push(PassDesc {
+            name: section.short_name().to_owned(),
+            kind: PassKind::Compute {
+                shader: section.get("shader").unwrap_or_default(),
+                image, buffer, binding, groups },
+            inputs: inputs(section, &passes, &textures, sources),
+            textures: section_textures(section, &textures),
+            specialization: config.section(&format!("{}.specialization", section.name)).cloned() });
+    }
+
+    let computes = passes.len();
+    for section in config.sections(PASS_PREFIX) {
+        let format = match section.get::<String>("format") {
+            None => PASS_FORMAT,
+            Some(format) => parse_format(&format).unwrap_or_else(|| {
+                warn!("Config [{}]: unsupported format {format}, using {PASS_FORMAT:?}", section.name);
+                PASS_FORMAT }),
+        };
+        let mesh: Option<String> = section.get("mesh");
+        passes.push(PassDesc {
+            name: section.short_name().to_owned(),
+            kind: PassKind::Graphics {
+                vertex: section.get("vertex").unwrap_or(if mesh.is_some() { MESH_VERTEX } else { BASIC_VERTEX }.to_owned()),
+                fragment: section.get("fragment").unwrap_or(BASIC_FRAGMENT.to_owned()),
+                format,
+                samples: section.get("samples").unwrap_or(1),
+                mesh },
+            inputs: inputs(section, &passes, &textures, sources),
+            textures: section_textures(section, &textures),
+            specialization: config.section(&format!("{}.specialization", section.name)).cloned() });
+    }
+
+    if passes.len() == computes {
+        let section = config.section(BASIC_PIPELINE);
+        let mesh: Option<String> = section.and_then(|section| section.get("mesh"));
+        passes.push(PassDesc {
+            name: "basic".to_owned(),
+            kind: PassKind::Graphics {
+                vertex: section.and_then(|section| section.get("vertex")).unwrap_or(if mesh.is_some() { MESH_VERTEX } else { BASIC_VERTEX }.to_owned()),
+                fragment: section.and_then(|section| section.get("fragment")).unwrap_or(BASIC_FRAGMENT.to_owned()),
+                format: PASS_FORMAT,
+                samples: section.and_then(|section| section.get("samples")).unwrap_or(1),
+                mesh },
+            inputs: vec![PassInput::Capture, PassInput::Mv],
+            textures: Vec::new(),
+            specialization: config.section(BASIC_SPECIALIZATION).cloned() });
+    }
+
+    //stretches the last pass's output over the window. basic.vert includes <ember/interface.glsl>, so the mv buffer comes along.
+    //  a factor of 1 that doesn't adapt leaves nothing to stretch.
+    if let Some(scale) = crate::util::scale::describe(config).filter(|scale| scale.factor < 1.0 || scale.target.is_some()) {
+        passes.push(PassDesc {
+            name: "upscale".to_owned(),
+            kind: PassKind::Graphics {
+                vertex: BASIC_VERTEX.to_owned(),
+                fragment: UPSCALE_FRAGMENT.to_owned(),
+                format: PASS_FORMAT,
+                samples: 1,
+                mesh: None },
+            inputs: vec![PassInput::Previous, PassInput::Mv],
+            textures: Vec::new(),
+            specialization: Some(scale.specialization()) });
+    }
+    passes
+}
+
+//the [texture.*] sections. broken ones get skipped, like passes.
+fn textures(config: &Config) -> Vec<TextureDesc> {
+    let mut textures: Vec<TextureDesc> = Vec::new();
+    for section in config.sections(TEXTURE_PREFIX) {
+        let Some(path) = section.get::<String>("path") else {
+            warn!("Config [{}]: a texture needs a path, skipping it", section.name);
+            continue };
+        let default_binding = (TEXTURE_BINDINGS..).find(|binding| !textures.iter().any(|texture| texture.binding == *binding)).unwrap();
+        let binding = match section.get::<u32>("binding") {
+            Some(binding) if binding < COMPUTE_BINDINGS => {
+                warn!("Config [{}]: binding {binding} is taken by the fixed inputs, using {default_binding}", section.name);
+                default_binding }
+            Some(binding) if textures.iter().any(|texture| texture.binding == binding) => {
+                warn!("Config [{}]: binding {binding} collides with another texture, using {default_binding}", section.name);
+                default_binding }
+            Some(binding) => binding,
+            None => default_binding,
+        };
+        let filter = match section.get::<String>("filter").as_deref() {
+            None | Some("linear") => vk::Filter::LINEAR,
+            Some("nearest") => vk::Filter::NEAREST,
+            Some(filter) => {
+                warn!("Config [{}]: unknown filter {filter}, expected linear or nearest", section.name);
+                vk::Filter::LINEAR }
+        };
+        let wrap = match section.get::<String>("wrap").as_deref() {
+            None | Some("repeat") => vk::SamplerAddressMode::REPEAT,
+            Some("clamp") => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            Some("mirror") => vk::SamplerAddressMode::MIRRORED_REPEAT,
+            Some("border") => vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            Some(wrap) => {
+                warn!("Config [{}]: unknown wrap {wrap}, expected repeat, clamp, mirror or border", section.name);
+                vk::SamplerAddressMode::REPEAT }
+        };
+        textures.push(TextureDesc {
+            name: section.short_name().to_owned(),
+            path, binding,
+            srgb: section.get_or("srgb", false),
+            mipmaps: section.get_or("mipmaps", true),
+            filter, wrap });
+    }
+    textures
+}
+
+//the textures named in a section's inputs
+fn section_textures(section: &Section, textures: &[TextureDesc]) -> Vec<TextureDesc> {
+    let names: Vec<String> = section.get::<String>("inputs").unwrap_or_default().split(',').map(|input| input.trim().to_owned()).collect();
+    textures.iter().filter(|texture| names.contains(&texture.name)).cloned().collect()
+}
+
+//the inputs of a pass coming after `before`. compute passes are referred to by name, as are textures (see section_textures).
+fn inputs(section: &Section, before: &[PassDesc], textures: &[TextureDesc], sources: u32) -> Vec<PassInput> {
+    let mut inputs: Vec<PassInput> = Vec::new();
+    for input in section.get::<String>("inputs").unwrap_or_default().split(',').map(str::trim).filter(|input| !input.is_empty()) {
+        let input = match input {
+            "capture" => PassInput::Capture,
+            "captures" => PassInput::Captures { count: sources },
+            "previous" if !before.iter().any(|pass| matches!(pass.kind, PassKind::Graphics { .. })) => {
+                warn!("Config [{}]: there's no previous pass to read from, ignoring it", section.name);
+                continue }
+            "previous" => PassInput::Previous,
+            "mv" => PassInput::Mv,
+            "feedback" => PassInput::Feedback,
+            _ if textures.iter().any(|texture| texture.name == input) => continue,
+            _ => match before.iter().position(|pass| pass.name == input && matches!(pass.kind, PassKind::Compute { .. })) {
+                Some(pass) => {
+                    let PassKind::Compute { image, buffer, binding, .. } = before[pass].kind else { unreachable!() };
+                    PassInput::Compute { pass, binding, image: image.is_some(), buffer: buffer.is_some() } }
+                None => {
+                    warn!("Config [{}]: unknown input \"{input}\", expected capture, captures, previous, mv, feedback, a texture or an earlier compute pass", section.name);
+                    continue }
+            }
+        };
+        if !inputs.contains(&input) { inputs.push(input) }
+    }
+    inputs
+}
+
+//the most samples the device does without going over what's asked for
+fn sample_count(requested: u32, supported: vk::SampleCountFlags) -> vk::SampleCountFlags {
+    (0..7).rev().map(|bit| vk::SampleCountFlags::from_raw(1 << bit))
+        .find(|samples| samples.as_raw() <= requested && supported.contains(*samples))
+        .unwrap_or(vk::SampleCountFlags::TYPE_1)
+}
+
+//D16 is guaranteed to work as a depth attachment, D32 is nicer if it's there
+unsafe fn depth_format(physical_device: vk::PhysicalDevice) -> vk::Format {
+    [vk::Format::D32_SFLOAT, vk::Format::D16_UNORM].into_iter()
+        .find(|format| INSTANCE.get_physical_device_format_properties(physical_device, *format)
+            .optimal_tiling_features.contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT))
+        .unwrap_or(vk::Format::D16_UNORM)
+}
+
+//formats that are renderable and sampleable pretty much everywhere
+fn parse_format(format: &str) -> Option<vk::Format> {
+    Some(match format {
+        "R8G8B8A8_UNORM" => vk::Format::R8G8B8A8_UNORM,
+        "R8G8B8A8_SRGB" => vk::Format::R8G8B8A8_SRGB,
+        "B8G8R8A8_UNORM" => vk::Format::B8G8R8A8_UNORM,
+        "B8G8R8A8_SRGB" => vk::Format::B8G8R8A8_SRGB,
+        "A2B10G10R10_UNORM_PACK32" => vk::Format::A2B10G10R10_UNORM_PACK32,
+        "R16G16B16A16_SFLOAT" => vk::Format::R16G16B16A16_SFLOAT,
+        "R32G32B32A32_SFLOAT" => vk::Format::R32G32B32A32_SFLOAT,
+        "R8_UNORM" => vk::Format::R8_UNORM,
+        "R16_SFLOAT" => vk::Format::R16_SFLOAT,
+        "R32_SFLOAT" => vk::Format::R32_SFLOAT,
+        _ => return None,
+    })
+}
+
+
+//what the passes read besides each other's output. all of it belongs to the window (or the app), the chain only binds it.
+#[derive(Clone)]
+pub(crate) struct ChainInputs {
+    pub ubufs: Vec<vk::Buffer>,
+    pub mv_ubufs: Vec<vk::Buffer>,
+    //the screencasts, by source. none without a screencast.
+    pub captures: Vec<SampledImage>,
+    //the one that's "capture"
+    pub source: usize,
+    //for uploading meshes and textures. the queue's family is the one that takes the capture over, see SampledImage::start
+    pub queue: vk::Queue,
+    pub family: u32,
+    pub command_pool: vk::CommandPool,
+    //whether the swapchain images can be copied into, which feedback needs. see PerSwapchain::create_swapchain
+    pub copyable: bool,
+}
+
+#[derive(Copy, Clone)]
+pub(crate) struct SampledImage {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub sampler: vk::Sampler,
+    pub extent: vk::Extent2D,
+    //whether the sampler does a YCbCr conversion. then it's immutable, part of the descriptor set layout.
+    pub converts: bool,
+    //whether it's an image of our own rather than an imported one. those are cleared when they're made, and only ever
+    //  written through the uploader, which leaves them sampleable from one frame to the next (see Uploader::write_image).
+    //  imported ones belong to the compositor, they get taken over for every frame and handed back after.
+    pub owned: bool,
+}
+
+impl ChainInputs {
+    fn immutable_samplers(&self, fallback: vk::Sampler) -> ImmutableSamplers {
+        ImmutableSamplers {
+            capture: self.captures.get(self.source).and_then(SampledImage::immutable_sampler),
+            captures: match self.captures.iter().any(|capture| capture.converts) {
+                true => self.captures.iter().map(|capture| capture.sampler).collect(),
+                false => Vec::new(),
+            },
+            fallback }
+    }
+}
+
+//the samplers that are part of the layouts, see Pass::build. others mean building the passes anew.
+#[derive(PartialEq)]
+struct ImmutableSamplers {
+    //"capture"'s, if it converts YUV
+    capture: Option<vk::Sampler>,
+    //all of "captures"', if any of them converts. a binding's elements are either all immutable or none are.
+    captures: Vec<vk::Sampler>,
+    //for the elements of "captures" there's no source for
+    fallback: vk::Sampler,
+}
+
+impl SampledImage {
+    //the sampler layouts have to be built with, if any
+    fn immutable_sampler(&self) -> Option<vk::Sampler> {
+        self.converts.then_some(self.sampler)
+    }
+
+    //what the graph finds it in at the start of a frame
+    fn start(&self) -> Option<Access> {
+        Some(if self.owned { Access::Sampled } else { Access::Foreign })
+    }
+}
+
+pub(crate) struct Pass {
+    pub desc: PassDesc,
+    //null with dynamic rendering, and for compute passes
+    pub render_pass: vk::RenderPass,
+    //what it renders into. a transient image of the graph, or the swapchain image for the last pass.
+    //  compute passes have their storage image here, if they write one.
+    pub output: Option<ImageId>,
+    //the storage buffer of a compute pass
+    pub buffer: Option<BufferId>,
+    pub format: vk::Format,
+    pub samples: vk::SampleCountFlags,
+    //what gets rendered into when there's more than one sample, resolved into `output` at the end of the pass
+    pub msaa: Option<ImageId>,
+    //what it draws instead of the fullscreen quad, and its depth buffer
+    pub mesh: Option<Mesh>,
+    pub depth: Option<(ImageId, vk::Format)>,
+    //false while the error pipeline stands in, which draws the quad
+    pub draws_mesh: bool,
+    //by the attachments they're made of, see Pass::attachments. a single-sampled pass rendering into the swapchain has none,
+    //  it uses the swapchain's framebuffers. nobody has any with dynamic rendering.
+    pub framebuffers: Vec<(Vec<vk::ImageView>, vk::Framebuffer)>,
+
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    pub push_constant_range: vk::PushConstantRange,
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    pub interface: ShaderInterface,
+    //what the pipeline got built from, so it can be rebuilt when any of it changes
+    pub shader_sources: ShaderSources,
+    //why the shaders didn't build, if they didn't. the last pass shows the first of these, see Chain::upstream_error
+    pub error: Option<ShaderError>,
+}
+
+pub(crate) struct Chain {
+    pub passes: Vec<Pass>,
+    //the passes' images and buffers, and the barriers between them. see util::graph
+    pub graph: RenderGraph,
+    pub swapchain: ImageId,
+    //the screencasts, by source
+    captures: Vec<ImageId>,
+    //(current, previous), if any pass reads the last frame
+    pub feedback: Option<(ImageId, ImageId)>,
+    //None if we're stuck with render passes
+    pub rendering: Option<DynamicRendering>,
+    //the window's. multisampled last passes bring a render pass of their own, and need the views for their framebuffers.
+    present_pass: vk::RenderPass,
+    present_format: vk::Format,
+    present_views: Vec<vk::ImageView>,
+    inputs: ChainInputs,
+    physical_device: vk::PhysicalDevice,
+    //for reading the previous pass's output
+    sampler: vk::Sampler,
+    //of the window's size, what every pass but the last renders at. see util::scale
+    pub scale: f32,
+    //every texture any pass reads, each with a sampler of its own
+    textures: Vec<(TextureDesc, Texture)>,
+    //black, for whatever a pass samples that isn't there. see write_binding
+    fallback: Texture,
+}
+
+
+impl Chain {
+    //the last pass renders with `present_pass`, which stays the window's (the swapchain framebuffers are made for it),
+    //  unless it's multisampled. with dynamic rendering it's null, like every other pass's.
+    //every other pass renders at `scale` times `extent`, as do the compute passes.
+    pub unsafe fn new(device: &Device, physical_device: vk::PhysicalDevice, extent: vk::Extent2D, scale: f32, present_pass: vk::RenderPass,
+                      present_format: vk::Format, present_views: Vec<vk::ImageView>, descs: Vec<PassDesc>, inputs: ChainInputs,
+                      sync2: Option<khr::synchronization2::Device>, rendering: Option<DynamicRendering>) -> Chain {
+        let sampler_info = vk::SamplerCreateInfo {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            max_lod: vk::LOD_CLAMP_NONE,
+            ..Default::default()};
+        let sampler = device.create_sampler(&sampler_info, None).unwrap();
+
+        let mut graph = RenderGraph::new(physical_device, inputs.family, extent, scaled(extent, scale), MAX_FRAMES_IN_FLIGHT as usize, sync2);
+        let swapchain = graph.import_swapchain("swapchain");
+        let captures: Vec<ImageId> = inputs.captures.iter().enumerate()
+            .map(|(source, capture)| graph.import_image(&format!("capture {source}"), capture.image, capture.start())).collect();
+        let ubo = graph.import_buffers(inputs.ubufs.clone());
+        let mv = graph.import_buffers(inputs.mv_ubufs.clone());
+        //same format as the swapchain, so it can be copied over as is. if it can't be, the last pass renders into the swapchain
+        //  like it would without feedback, and the passes reading it get black instead (see write_binding).
+        let feedback = descs.iter().any(|desc| desc.inputs.contains(&PassInput::Feedback));
+        if feedback && !inputs.copyable { warn!("The surface doesn't support copying into its images, passes reading feedback get black instead") }
+        let feedback = (feedback && inputs.copyable).then(|| graph.ping_pong("feedback", present_format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_SRC));
+
+        //a texture that doesn't load gets warned about, and its passes sample black instead
+        let mut textures: Vec<(TextureDesc, Texture)> = Vec::new();
+        for desc in descs.iter().flat_map(|desc| &desc.textures) {
+            if textures.iter().any(|(loaded, _)| loaded.name == desc.name) { continue }
+            let texture = crate::util::texture::load(desc)
+                .and_then(|pixels| Texture::upload(device, physical_device, inputs.queue, inputs.command_pool, desc, &pixels))
+                .unwrap_or_else(|e| {
+                    warn!("Texture \"{}\": {e}", desc.name);
+                    Texture::upload(device, physical_device, inputs.queue, inputs.command_pool, desc, &Pixels::black()).unwrap() });
+            textures.push((desc.clone(), texture));
+        }
+        let fallback_desc = TextureDesc { name: "fallback".to_owned(), path: "fallback".to_owned(), binding: 0, srgb: false, mipmaps: false,
+                                          filter: vk::Filter::NEAREST, wrap: vk::SamplerAddressMode::CLAMP_TO_EDGE };
+        let fallback = Texture::upload(device, physical_device, inputs.queue, inputs.command_pool, &fallback_desc, &Pixels::black()).unwrap();
+        let samplers = inputs.immutable_samplers(fallback.sampler);
+
+        let limits = INSTANCE.get_physical_device_properties(physical_device).limits;
+        let depth_format = depth_format(physical_device);
+        let last = descs.len() - 1;
+        let mut passes: Vec<Pass> = Vec::with_capacity(descs.len());
+        //the output of the last graphics pass so far
+        let mut previous: Option<ImageId> = None;
+        for (idx, desc) in descs.into_iter().enumerate() {
+            let mut images: Vec<(ImageId, Access)> = Vec::new();
+            let mut buffers = vec![(ubo, Access::Uniform)];
+            //a mesh that doesn't load gets warned about, and the pass draws the quad instead
+            let mesh = match &desc.kind {
+                PassKind::Graphics { mesh: Some(path), .. } => match crate::util::mesh::load(path) {
+                    Ok((vertices, indices)) => Some(Mesh::upload(device, physical_device, inputs.queue, inputs.command_pool, &vertices, &indices)),
+                    Err(e) => {
+                        warn!("Pass \"{}\": {e}", desc.name);
+                        None }
+                },
+                _ => None,
+            };
+            let supported = match mesh {
+                Some(_) => limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts,
+                None => limits.framebuffer_color_sample_counts,
+            };
+            let depth = mesh.as_ref().map(|_| depth_format);
+            let samples = match desc.kind {
+                PassKind::Graphics { samples: requested, .. } => {
+                    let samples = sample_count(requested, supported);
+                    if samples.as_raw() != requested.max(1) {
+                        warn!("Pass \"{}\": {requested} samples aren't supported, using {}", desc.name, samples.as_raw()) }
+                    samples }
+                PassKind::Compute { .. } => vk::SampleCountFlags::TYPE_1,
+            };
+            let render_pass = |format: vk::Format| match rendering {
+                Some(_) => vk::RenderPass::null(),
+                None => create_render_pass(device, format, samples, depth),
+            };
+            let (render_pass, output, buffer, format) = match desc.kind {
+                PassKind::Graphics { .. } if idx == last => {
+                    let output = feedback.map_or(swapchain, |(current, _)| current);
+                    images.push((output, Access::ColorAttachment));
+                    //the window's render pass only knows a single-sampled color attachment
+                    let plain = samples == vk::SampleCountFlags::TYPE_1 && depth.is_none();
+                    (if plain { present_pass } else { render_pass(present_format) }, Some(output), None, present_format) }
+                PassKind::Graphics { format, .. } => {
+                    let output = graph.transient_image(&desc.name, format, vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED);
+                    images.push((output, Access::ColorAttachment));
+                    (render_pass(format), Some(output), None, format) }
+                PassKind::Compute { image, buffer, .. } => {
+                    //a format the device can't store to leaves the pass without an image, which breaks it (see Pass::build)
+                    let storable = image.filter(|&format| INSTANCE.get_physical_device_format_properties(physical_device, format).optimal_tiling_features
+                        .contains(vk::FormatFeatureFlags::STORAGE_IMAGE | vk::FormatFeatureFlags::SAMPLED_IMAGE));
+                    let output = storable.map(|format| graph.transient_image(&desc.name, format, vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED));
+                    let buffer = buffer.map(|size| graph.transient_buffer(size, vk::BufferUsageFlags::STORAGE_BUFFER));
+                    images.extend(output.map(|output| (output, Access::StorageWrite)));
+                    buffers.extend(buffer.map(|buffer| (buffer, Access::StorageWrite)));
+                    (vk::RenderPass::null(), output, buffer, image.unwrap_or_default()) }
+            };
+            let msaa = (samples != vk::SampleCountFlags::TYPE_1).then(|| graph.multisampled_image(&format!("{}.msaa", desc.name), format, samples));
+            images.extend(msaa.map(|msaa| (msaa, Access::ColorAttachment)));
+            let depth = depth.map(|format| (graph.depth_image(&format!("{}.depth", desc.name), format, samples), format));
+            images.extend(depth.map(|(depth, _)| (depth, Access::DepthAttachment)));
+            //the last pass fills the window, whatever it reads from
+            if idx != last {
+                output.into_iter().chain(msaa).chain(depth.map(|(depth, _)| depth)).for_each(|image| graph.scale(image)) }
+
+            for input in &desc.inputs {
+                match *input {
+                    PassInput::Capture => images.extend(captures.get(inputs.source).map(|capture| (*capture, Access::Sampled))),
+                    PassInput::Captures { .. } => images.extend(captures.iter().map(|capture| (*capture, Access::Sampled))),
+                    PassInput::Previous => images.extend(previous.map(|previous| (previous, Access::Sampled))),
+                    PassInput::Mv => buffers.push((mv, Access::StorageRead)),
+                    PassInput::Feedback => images.extend(feedback.map(|(_, previous)| (previous, Access::Sampled))),
+                    PassInput::Compute { pass, .. } => {
+                        images.extend(passes[pass].output.map(|output| (output, Access::Sampled)));
+                        buffers.extend(passes[pass].buffer.map(|buffer| (buffer, Access::StorageRead))) }
+                }
+            }
+            graph.add_pass(&desc.name, &images, &buffers);
+            if let PassKind::Graphics { .. } = desc.kind { previous = output }
+
+            let mut pass = Pass {
+                desc, render_pass, output, buffer, format, samples, msaa, mesh, depth,
+                draws_mesh: false,
+                framebuffers: Vec::new(),
+                pipeline: vk::Pipeline::null(),
+                layout: vk::PipelineLayout::null(),
+                push_constant_range: vk::PushConstantRange::default(),
+                descriptor_set_layout: vk::DescriptorSetLayout::null(),
+                descriptor_pool: vk::DescriptorPool::null(),
+                descriptor_sets: Vec::new(),
+                interface: ShaderInterface::default(),
+                shader_sources: ShaderSources::default(),
+                error: None };
+            let upstream = (idx == last).then(|| upstream_error(&passes).cloned()).flatten();
+            pass.build(device, if idx == last { extent } else { scaled(extent, scale) }, &samplers, upstream.as_ref());
+            passes.push(pass);
+        }
+        //see record_into_buffer
+        if let Some((current, _)) = feedback {
+            graph.add_pass("feedback", &[(current, Access::TransferSrc), (swapchain, Access::TransferDst)], &[]);
+        }
+        graph.allocate(device);
+
+        let mut chain = Chain { passes, graph, swapchain, captures, feedback, rendering, present_pass, present_format, present_views,
+                                inputs, physical_device, sampler, scale, textures, fallback };
+        chain.create_framebuffers(device);
+        (0..chain.passes.len()).for_each(|idx| chain.write_descriptors(device, idx));
+        chain
+    }
+
+    //the output of the last graphics pass before `idx`
+    fn previous(&self, idx: usize) -> Option<ImageId> {
+        self.passes[..idx].iter().rev().find(|pass| matches!(pass.desc.kind, PassKind::Graphics { .. })).and_then(|pass| pass.output)
+    }
+
+    //what pass `idx` renders at, for a window that's `extent` large
+    pub fn extent(&self, idx: usize, extent: vk::Extent2D) -> vk::Extent2D {
+        if idx + 1 == self.passes.len() { extent } else { scaled(extent, self.scale) }
+    }
+
+    //whether the chain was built from exactly these passes
+    pub fn describes(&self, descs: &[PassDesc]) -> bool {
+        self.passes.iter().map(|pass| &pass.desc).eq(descs.iter())
+    }
+
+    //indices of the passes whose shader sources changed since they were built
+    pub fn changed(&self) -> Vec<usize> {
+        self.passes.iter().enumerate().filter(|(_, pass)| pass.shader_sources.changed()).map(|(idx, _)| idx).collect()
+    }
+
+    //once per frame, before recording anything. the feedback pair traded places, so whoever reads it gets pointed at the other one.
+    //  the descriptor sets of this frame aren't in use anymore, we waited for its fence.
+    pub unsafe fn begin(&mut self, device: &Device, frame: usize) {
+        self.graph.begin(frame);
+        if self.feedback.is_none() { return }
+        for (idx, pass) in self.passes.iter().enumerate() {
+            for binding in pass.interface.bindings.iter().filter(|binding| binding.binding == FEEDBACK) {
+                self.write_binding(device, idx, frame, binding);
+            }
+        }
+    }
+
+    //the whole chain from scratch, for when the pass list itself changed. the device has to be idle.
+    pub unsafe fn rebuild(&mut self, device: &Device, extent: vk::Extent2D, descs: Vec<PassDesc>) {
+        let sync2 = self.graph.sync2().cloned();
+        self.destroy(device);
+        *self = Chain::new(device, self.physical_device, extent, self.scale, self.present_pass, self.present_format, self.present_views.clone(), descs, self.inputs.clone(),
+                           sync2, self.rendering.clone());
+    }
+
+    //the swapchain got made anew in another format, along with the window's render pass. the device has to be idle.
+    //  the chain's still using the old render pass, but doesn't destroy it, it's the window's.
+    pub unsafe fn set_present(&mut self, device: &Device, extent: vk::Extent2D, present_pass: vk::RenderPass, present_format: vk::Format,
+                              present_views: Vec<vk::ImageView>) {
+        let descs = self.passes.iter().map(|pass| pass.desc.clone()).collect();
+        let sync2 = self.graph.sync2().cloned();
+        self.destroy(device);
+        *self = Chain::new(device, self.physical_device, extent, self.scale, present_pass, present_format, present_views, descs, self.inputs.clone(),
+                           sync2, self.rendering.clone());
+    }
+
+    //a single pass's pipeline, layout and descriptors. the device has to be idle.
+    //  the last pass shows the errors of the ones before it, so it gets rebuilt too if that might have changed.
+    pub unsafe fn rebuild_pass(&mut self, device: &Device, extent: vk::Extent2D, idx: usize) {
+        let last = self.passes.len() - 1;
+        let shown = upstream_error(&self.passes[..last]).is_some();
+        self.build_pass(device, extent, idx);
+        if idx != last && (shown || upstream_error(&self.passes[..last]).is_some()) { self.build_pass(device, extent, last) }
+    }
+
+    unsafe fn build_pass(&mut self, device: &Device, extent: vk::Extent2D, idx: usize) {
+        let extent = self.extent(idx, extent);
+        let last = self.passes.len() - 1;
+        let upstream = (idx == last).then(|| upstream_error(&self.passes[..last]).cloned()).flatten();
+        self.passes[idx].destroy_pipeline(device);
+        self.passes[idx].build(device, extent, &self.inputs.immutable_samplers(self.fallback.sampler), upstream.as_ref());
+        self.write_descriptors(device, idx);
+    }
+
+    //the graph's images are as large as the swapchain, so they follow it around
+    pub unsafe fn resize(&mut self, device: &Device, extent: vk::Extent2D, present_views: Vec<vk::ImageView>) {
+        self.passes.iter_mut().for_each(|pass| pass.destroy_framebuffers(device));
+        self.present_views = present_views;
+        self.graph.resize(device, extent, scaled(extent, self.scale));
+        self.create_framebuffers(device);
+        (0..self.passes.len()).for_each(|idx| self.write_descriptors(device, idx));
+    }
+
+    //the screencast of `source` got made anew or moved to another buffer, see util::capture. the device has to be idle.
+    //  that's only new descriptors, unless it comes with another converting sampler. those are baked into the layouts,
+    //  so the passes get rebuilt then.
+    pub unsafe fn set_capture(&mut self, device: &Device, extent: vk::Extent2D, source: usize, capture: SampledImage) {
+        let Some(id) = self.captures.get(source).copied() else { return };
+        let samplers = self.inputs.immutable_samplers(self.fallback.sampler);
+        self.inputs.captures[source] = capture;
+        self.graph.reimport_image(id, capture.image, capture.start());
+        match self.inputs.immutable_samplers(self.fallback.sampler) != samplers {
+            true => (0..self.passes.len()).for_each(|idx| self.rebuild_pass(device, extent, idx)),
+            false => (0..self.passes.len()).for_each(|idx| self.write_descriptors(device, idx)),
+        }
+    }
+
+    //"capture" is another source from now on. the passes reading it read another image, which takes a new graph.
+    //  the device has to be idle.
+    pub unsafe fn set_source(&mut self, device: &Device, extent: vk::Extent2D, source: usize) {
+        self.inputs.source = source;
+        let descs = self.passes.iter().map(|pass| pass.desc.clone()).collect();
+        self.rebuild(device, extent, descs);
+    }
+
+    //whether any pass samples `source`, be it as "capture" or among "captures"
+    pub fn reads(&self, source: usize) -> bool {
+        source < self.captures.len() && self.passes.iter().flat_map(|pass| &pass.desc.inputs).any(|input| match input {
+            PassInput::Capture => source == self.inputs.source,
+            PassInput::Captures { .. } => true,
+            _ => false })
+    }
+
+    //"capture"
+    pub fn capture(&self) -> Option<SampledImage> {
+        self.inputs.captures.get(self.inputs.source).copied()
+    }
+
+    //renders at another fraction of the window's size from now on. the device has to be idle.
+    pub unsafe fn set_scale(&mut self, device: &Device, extent: vk::Extent2D, scale: f32) {
+        self.scale = scale;
+        self.resize(device, extent, self.present_views.clone());
+    }
+
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        for pass in &mut self.passes {
+            pass.destroy_pipeline(device);
+            pass.destroy_framebuffers(device);
+            if let Some(mesh) = pass.mesh.take() { mesh.destroy(device) }
+            if pass.render_pass != self.present_pass { device.destroy_render_pass(pass.render_pass, None) }
+        }
+        self.graph.destroy(device);
+        device.destroy_sampler(self.sampler, None);
+        self.textures.drain(..).for_each(|(_, texture)| texture.destroy(device));
+        self.fallback.destroy(device);
+    }
+
+    unsafe fn create_framebuffers(&mut self, device: &Device) {
+        if self.rendering.is_some() { return }
+        for pass in self.passes.iter_mut().filter(|pass| matches!(pass.desc.kind, PassKind::Graphics { .. })) {
+            let Some(output) = pass.output else { continue };
+            if output == self.swapchain && pass.msaa.is_none() && pass.depth.is_none() { continue }
+            //the swapchain's counts as full size, like the feedback pair
+            let extent = self.graph.extent(output);
+            for frame in 0..MAX_FRAMES_IN_FLIGHT as usize {
+                let outputs = if output == self.swapchain { self.present_views.clone() } else { self.graph.views(output, frame) };
+                for view in outputs {
+                    let attachments: Vec<vk::ImageView> = pass.msaa.map(|msaa| self.graph.view(msaa, frame)).into_iter().chain([view])
+                        .chain(pass.depth.map(|(depth, _)| self.graph.view(depth, frame))).collect();
+                    let framebuffer_info = vk::FramebufferCreateInfo {
+                        render_pass: pass.render_pass,
+                        attachment_count: attachments.len() as u32,
+                        p_attachments: attachments.as_ptr(),
+                        width: extent.width,
+                        height: extent.height,
+                        layers: 1,
+                        ..Default::default()};
+                    let framebuffer = device.create_framebuffer(&framebuffer_info, None).unwrap();
+                    pass.framebuffers.push((attachments, framebuffer));
+                }
+            }
+        }
+    }
+
+    //writes whatever the pass's layout has room for. the layout comes from the shaders, which got checked against
+    //  PassDesc::bindings, so it never asks for something the pass doesn't have.
+    unsafe fn write_descriptors(&self, device: &Device, idx: usize) {
+        for frame in 0..MAX_FRAMES_IN_FLIGHT as usize {
+            for binding in &self.passes[idx].interface.bindings {
+                self.write_binding(device, idx, frame, binding);
+            }
+        }
+    }
+
+    unsafe fn write_binding(&self, device: &Device, idx: usize, frame: usize, binding: &DescriptorBinding) {
+        let read = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+        if binding.binding == CAPTURES {
+            self.write_captures(device, idx, frame, binding);
+            return }
+        let (buffer_info, image_info) = match binding.binding {
+            UBO => (Some((self.inputs.ubufs[frame], size_of::<UniformBufferObject>() as u64)), None),
+            MV => (Some((self.inputs.mv_ubufs[frame], size_of::<MVBufferObject>() as u64)), None),
+            CAPTURE => (None, self.capture().map(|capture| (capture.sampler, capture.view, read))),
+            PREVIOUS => (None, self.previous(idx).map(|previous| (self.sampler, self.graph.view(previous, frame), read))),
+            FEEDBACK => (None, self.feedback.map(|(_, previous)| (self.sampler, self.graph.view(previous, frame), read))),
+            at => match self.textures.iter().find(|(desc, _)| desc.binding == at) {
+                Some((_, texture)) => (None, Some((texture.sampler, texture.view, read))),
+                //compute outputs. bindings don't overlap between compute passes, so whichever has this one is the one.
+                None => match self.passes.iter().enumerate().find_map(|(writer, pass)| match pass.desc.kind {
+                    PassKind::Compute { binding, .. } if binding == at || binding + 1 == at => Some((writer, binding + 1 == at)),
+                    _ => None }) {
+                    Some((writer, true)) => (self.passes[writer].buffer.map(|buffer| (self.graph.buffer(buffer, frame), vk::WHOLE_SIZE)), None),
+                    Some((writer, false)) => (None, self.passes[writer].output.map(|output| match writer == idx {
+                        true => (vk::Sampler::null(), self.graph.view(output, frame), vk::ImageLayout::GENERAL),
+                        false => (self.sampler, self.graph.view(output, frame), read) })),
+                    None => (None, None),
+                },
+            }
+        };
+        let buffer_info = buffer_info.map(|(buffer, range)| vk::DescriptorBufferInfo { buffer, offset: 0, range });
+        //nothing there, say "previous" in the first pass. samplers read black instead, and nothing else can be missing:
+        //  the shaders only get the bindings their pass has (see PassDesc::bindings).
+        let image_info = match (&buffer_info, image_info) {
+            (None, None) if binding.descriptor_type == vk::DescriptorType::COMBINED_IMAGE_SAMPLER => Some((self.fallback.sampler, self.fallback.view, read)),
+            (_, image_info) => image_info,
+        };
+        let image_info = image_info.map(|(sampler, image_view, image_layout)| vk::DescriptorImageInfo { sampler, image_view, image_layout });
+        if buffer_info.is_none() && image_info.is_none() { return }
+
+        let descriptor_write = vk::WriteDescriptorSet {
+            dst_set: self.passes[idx].descriptor_sets[frame],
+            dst_binding: binding.binding,
+            dst_array_element: 0,
+            descriptor_count: 1,
+            descriptor_type: binding.descriptor_type,
+            p_buffer_info: buffer_info.as_ref().map_or(ptr::null(), ptr::from_ref),
+            p_image_info: image_info.as_ref().map_or(ptr::null(), ptr::from_ref),
+            ..Default::default()};
+        device.update_descriptor_sets(&[descriptor_write], &[]);
+    }
+
+    //every element at once, black for those there's no source for
+    unsafe fn write_captures(&self, device: &Device, idx: usize, frame: usize, binding: &DescriptorBinding) {
+        let image_info: Vec<vk::DescriptorImageInfo> = (0..binding.count as usize).map(|source| {
+            let (sampler, image_view) = self.inputs.captures.get(source)
+                .map_or((self.fallback.sampler, self.fallback.view), |capture| (capture.sampler, capture.view));
+            vk::DescriptorImageInfo { sampler, image_view, image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL } }).collect();
+        let descriptor_write = vk::WriteDescriptorSet {
+            dst_set: self.passes[idx].descriptor_sets[frame],
+            dst_binding: binding.binding,
+            dst_array_element: 0,
+            descriptor_count: image_info.len() as u32,
+            descriptor_type: binding.descriptor_type,
+            p_image_info: image_info.as_ptr(),
+            ..Default::default()};
+        device.update_descriptor_sets(&[descriptor_write], &[]);
+    }
+}
+
+
+//the first error among `passes` that the window should know about. an offscreen pass that doesn't build only draws
+//  its errors into its own image, which the passes after it might not show at all, and a compute pass has nowhere to.
+fn upstream_error(passes: &[Pass]) -> Option<&ShaderError> {
+    passes.iter().find_map(|pass| pass.error.as_ref())
+}
+
+impl Pass {
+    //everything descriptor-related is derived from the shaders themselves, then checked against what we bind.
+    //shaders that don't build still need a layout. the assumed one puts everything in every graphics stage,
+    //  and the error pipeline showing why doesn't use any of it.
+    //`samplers` go into the capture bindings' layouts, for the captures that convert YUV.
+    //`upstream` is an error of an earlier pass, for the last one to show in the window in place of what it renders.
+    unsafe fn build(&mut self, device: &Device, extent: vk::Extent2D, samplers: &ImmutableSamplers, upstream: Option<&ShaderError>) {
+        let mut sources = ShaderSources::default();
+        let shaders = match (&self.desc.kind, compile_pass(&self.desc, &mut sources)) {
+            (PassKind::Compute { image: Some(format), .. }, Ok(_)) if self.output.is_none() => Err(ShaderError::new(&self.desc.name, vec![
+                Diagnostic { file: None, line: None, message: format!("the device can't write {format:?} as a storage image") }])),
+            (_, shaders) => shaders,
+        };
+        let error = shaders.as_ref().err().cloned();
+        let shaders = match upstream {
+            Some(e) => Err(e.clone()),
+            None => shaders,
+        };
+        let interface = match &shaders {
+            Ok((_, interface)) => interface.clone(),
+            Err(_) => ShaderInterface::assumed(&self.desc.bindings(), Some(size_of::<PushConstants>() as u32)),
+        };
+
+        let mut pool_size: Vec<vk::DescriptorPoolSize> = interface.pool_sizes(MAX_FRAMES_IN_FLIGHT);
+        //the immutable samplers of the capture bindings the pass has, "captures" gets one per element
+        let capture_samplers = samplers.capture.filter(|_| interface.bindings.iter().any(|binding| binding.binding == CAPTURE)).map(|sampler| vec![sampler]);
+        let captures_samplers = interface.bindings.iter().find(|binding| binding.binding == CAPTURES).filter(|_| !samplers.captures.is_empty())
+            .map(|binding| (0..binding.count as usize).map(|source| samplers.captures.get(source).copied().unwrap_or(samplers.fallback)).collect::<Vec<_>>());
+        //a converted image can take up a descriptor per plane, there are at most 3
+        let converted = capture_samplers.iter().chain(&captures_samplers).map(Vec::len).sum::<usize>() as u32;
+        if converted > 0 {
+            pool_size.iter_mut().filter(|size| size.ty == vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .for_each(|size| size.descriptor_count += 2 * converted * MAX_FRAMES_IN_FLIGHT) }
+        //pools can't be empty, even if the sets allocated from them are
+        if pool_size.is_empty() { pool_size.push(vk::DescriptorPoolSize { ty: vk::DescriptorType::UNIFORM_BUFFER, descriptor_count: 1 }) }
+        let pool_info = vk::DescriptorPoolCreateInfo {
+            max_sets: MAX_FRAMES_IN_FLIGHT,
+            pool_size_count: pool_size.len() as u32,
+            p_pool_sizes: pool_size.as_ptr(),
+            ..Default::default()};
+        let descriptor_pool = device.create_descriptor_pool(&pool_info, None).unwrap();
+
+        //shaders without push constants still get a (zero-sized) range back, but the layout won't reference it
+        let push_constant_ranges: Vec<vk::PushConstantRange> = interface.push_constant_range().into_iter().collect();
+        let push_constant_range = push_constant_ranges.first().copied().unwrap_or_default();
+
+        let mut bindings: Vec<vk::DescriptorSetLayoutBinding> = interface.set_layout_bindings(0);
+        for binding in &mut bindings {
+            let immutable = match binding.binding {
+                CAPTURE => &capture_samplers,
+                CAPTURES => &captures_samplers,
+                _ => &None,
+            };
+            if let Some(immutable) = immutable { binding.p_immutable_samplers = immutable.as_ptr() }
+        }
+        let descriptor_set_layout_info = vk::DescriptorSetLayoutCreateInfo {
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+            ..Default::default()};
+        let descriptor_set_layout = device.create_descriptor_set_layout(&descriptor_set_layout_info, None).unwrap();
+
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
+            set_layout_count: 1,
+            p_set_layouts: &descriptor_set_layout,
+            push_constant_range_count: push_constant_ranges.len() as u32,
+            p_push_constant_ranges: push_constant_ranges.as_ptr(),
+            ..Default::default()};
+        let layout = device.create_pipeline_layout(&pipeline_layout_info, None).unwrap();
+
+        let depth = self.depth.map(|(_, format)| format);
+        self.draws_mesh = self.mesh.is_some() && shaders.is_ok();
+        let topology = if self.draws_mesh { vk::PrimitiveTopology::TRIANGLE_LIST } else { vk::PrimitiveTopology::TRIANGLE_STRIP };
+        let pipeline = match (&self.desc.kind, shaders) {
+            (PassKind::Graphics { .. }, Ok((codes, interface))) => build_graphics_pipeline(device, extent, self.render_pass, self.format, self.samples,
+                depth, topology, layout, &codes[0], &codes[1], &interface, self.desc.specialization.as_ref(), &self.desc.name),
+            (PassKind::Graphics { .. }, Err(e)) => build_error_pipeline(device, extent, self.render_pass, self.format, self.samples, depth, layout, &e),
+            (PassKind::Compute { .. }, Ok((codes, interface))) =>
+                build_compute_pipeline(device, layout, &codes[0], &interface, self.desc.specialization.as_ref(), &self.desc.name),
+            //nothing to show the error with, so the pass just doesn't dispatch until it builds again. the last pass shows it instead.
+            (PassKind::Compute { .. }, Err(e)) => { error!("{e}"); vk::Pipeline::null() }
+        };
+
+        let sets = vec![descriptor_set_layout; MAX_FRAMES_IN_FLIGHT as usize];
+        let descriptor_set_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: MAX_FRAMES_IN_FLIGHT,
+            p_set_layouts: sets.as_ptr(),
+            ..Default::default()};
+        let descriptor_sets = device.allocate_descriptor_sets(&descriptor_set_info).unwrap();
+
+        self.pipeline = pipeline;
+        self.layout = layout;
+        self.push_constant_range = push_constant_range;
+        self.descriptor_set_layout = descriptor_set_layout;
+        self.descriptor_pool = descriptor_pool;
+        self.descriptor_sets = descriptor_sets;
+        self.interface = interface;
+        self.shader_sources = sources;
+        self.error = error;
+    }
+
+    //what the pass renders into this frame: the multisampled image and what it gets resolved into, or just the output.
+    //  then the depth buffer, if it has one.
+    pub fn attachments(&self, graph: &RenderGraph, frame: usize) -> Vec<vk::ImageView> {
+        self.msaa.iter().chain(&self.output).chain(self.depth.as_ref().map(|(depth, _)| depth)).map(|image| graph.view(*image, frame)).collect()
+    }
+
+    //how many workgroups a compute pass dispatches
+    pub fn groups(&self, extent: vk::Extent2D) -> [u32; 3] {
+        let PassKind::Compute { groups, .. } = self.desc.kind else { return [1, 1, 1] };
+        match (groups, self.interface.workgroup_size) {
+            (Some(groups), _) => groups,
+            (None, Some([x, y, _])) => [extent.width.div_ceil(x.max(1)), extent.height.div_ceil(y.max(1)), 1],
+            (None, None) => [1, 1, 1],
+        }
+    }
+
+    unsafe fn destroy_pipeline(&mut self, device: &Device) {
+        device.destroy_pipeline(self.pipeline, None);
+        device.destroy_pipeline_layout(self.layout, None);
+        //takes the descriptor sets with it
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+    }
+
+    unsafe fn destroy_framebuffers(&mut self, device: &Device) {
+        self.framebuffers.drain(..).for_each(|(_, framebuffer)| device.destroy_framebuffer(framebuffer, None));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe_text(text: &str) -> Vec<PassDesc> {
+        describe(&Config::from_text(text))
+    }
+
+    //image, buffer, binding and groups of a compute pass
+    type Compute = (Option<vk::Format>, Option<u64>, u32, Option<[u32; 3]>);
+
+    fn compute(pass: &PassDesc) -> Compute {
+        let PassKind::Compute { image, buffer, binding, groups, .. } = pass.kind else { panic!("{} isn't a compute pass", pass.name) };
+        (image, buffer, binding, groups)
+    }
+
+    #[test]
+    fn parses_formats() {
+        let cases = [
+            ("R8G8B8A8_UNORM", Some(vk::Format::R8G8B8A8_UNORM)),
+            ("R16G16B16A16_SFLOAT", Some(vk::Format::R16G16B16A16_SFLOAT)),
+            ("R32_SFLOAT", Some(vk::Format::R32_SFLOAT)),
+            ("r8g8b8a8_unorm", None),
+            ("R8G8B8_UNORM", None),
+            ("", None),
+        ];
+        for (format, expected) in cases {
+            assert_eq!(parse_format(format), expected, "{format}");
+        }
+    }
+
+    #[test]
+    fn counts_samples() {
+        use vk::SampleCountFlags as S;
+        let cases = [
+            (1, S::TYPE_1 | S::TYPE_4, S::TYPE_1),
+            (4, S::TYPE_1 | S::TYPE_4, S::TYPE_4),
+            //the most that doesn't go over
+            (8, S::TYPE_1 | S::TYPE_2 | S::TYPE_4, S::TYPE_4),
+            (3, S::TYPE_1 | S::TYPE_2 | S::TYPE_4, S::TYPE_2),
+            (0, S::TYPE_1 | S::TYPE_4, S::TYPE_1),
+            (64, S::TYPE_1 | S::TYPE_64, S::TYPE_64),
+        ];
+        for (requested, supported, expected) in cases {
+            assert_eq!(sample_count(requested, supported), expected, "{requested}");
+        }
+    }
+
+    #[test]
+    fn defaults_to_basic() {
+        let passes = describe_text("");
+        assert_eq!(passes.len(), 1);
+        assert_eq!(passes[0].name, "basic");
+        assert_eq!(passes[0].inputs, [PassInput::Capture, PassInput::Mv]);
+        assert_eq!(passes[0].kind, PassKind::Graphics { vertex: BASIC_VERTEX.to_owned(), fragment: BASIC_FRAGMENT.to_owned(),
+            format: PASS_FORMAT, samples: 1, mesh: None });
+
+        let passes = describe_text("[pipeline.basic]\nmesh = assets/monitor.glb\nsamples = 4\n[pipeline.basic.specialization]\nMARGIN = 16");
+        let PassKind::Graphics { vertex, samples, .. } = &passes[0].kind else { unreachable!() };
+        assert_eq!((vertex.as_str(), *samples), (MESH_VERTEX, 4));
+        assert_eq!(passes[0].specialization.as_ref().and_then(|section| section.get::<u32>("MARGIN")), Some(16));
+    }
+
+    #[test]
+    fn describes_passes() {
+        let cases = [
+            ("[pass.a]", PASS_FORMAT, 1),
+            ("[pass.a]\nformat = R8G8B8A8_UNORM\nsamples = 4", vk::Format::R8G8B8A8_UNORM, 4),
+            //unsupported formats and broken counts are the defaults
+            ("[pass.a]\nformat = R8G8B8_UNORM", PASS_FORMAT, 1),
+            ("[pass.a]\nformat = \nsamples = many", PASS_FORMAT, 1),
+            ("[pass.a]\nsamples = -4", PASS_FORMAT, 1),
+        ];
+        for (text, expected_format, expected_samples) in cases {
+            let passes = describe_text(text);
+            assert_eq!(passes.len(), 1, "{text:?}");
+            let PassKind::Graphics { format, samples, .. } = passes[0].kind else { unreachable!() };
+            assert_eq!((format, samples), (expected_format, expected_samples), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn describes_computes() {
+        let cases: [(&str, &[Compute]); 10] = [
+            ("[compute.a]\nimage = R32_SFLOAT", &[(Some(vk::Format::R32_SFLOAT), None, 8, None)]),
+            ("[compute.a]\nbuffer = 1024\ngroups = 8, 8, 1", &[(None, Some(1024), 8, Some([8, 8, 1]))]),
+            //nothing to write to, or nothing that can be written
+            ("[compute.a]", &[]),
+            ("[compute.a]\nbuffer = 0", &[]),
+            ("[compute.a]\nimage = R8G8B8_UNORM", &[]),
+            ("[compute.a]\nimage = R8G8B8_UNORM\nbuffer = 16", &[(None, Some(16), 8, None)]),
+            //broken groups dispatch per pixel
+            ("[compute.a]\nbuffer = 16\ngroups = 8, 8\n[compute.b]\nbuffer = 16\ngroups = 8, 0, 1\n[compute.c]\nbuffer = 16\ngroups = a, b, c",
+             &[(None, Some(16), 8, None), (None, Some(16), 10, None), (None, Some(16), 12, None)]),
+            //bindings of the fixed inputs, or taken by another pass
+            ("[compute.a]\nbuffer = 16\nbinding = 2", &[(None, Some(16), 8, None)]),
+            ("[compute.a]\nbuffer = 16\nbinding = 20\n[compute.b]\nbuffer = 16\nbinding = 21", &[(None, Some(16), 20, None), (None, Some(16), 8, None)]),
+            //or by a texture
+            ("[texture.t]\npath = a.png\nbinding = 9\n[compute.a]\nbuffer = 16\n[compute.b]\nbuffer = 16\nbinding = 8",
+             &[(None, Some(16), 10, None), (None, Some(16), 12, None)]),
+        ];
+        for (text, expected) in cases {
+            let passes = describe_text(text);
+            let computes = passes.iter().filter(|pass| matches!(pass.kind, PassKind::Compute { .. })).map(compute).collect::<Vec<_>>();
+            assert_eq!(computes, expected, "{text:?}");
+            //computes come first, the basic pipeline after them
+            assert_eq!(passes.last().unwrap().name, "basic", "{text:?}");
+        }
+    }
+
+    #[test]
+    fn describes_textures() {
+        use vk::{Filter as F, SamplerAddressMode as W};
+        //name, binding, filter and wrap
+        type Expected = (&'static str, u32, F, W);
+        let cases: [(&str, &[Expected]); 7] = [
+            ("[texture.a]\npath = a.png", &[("a", 32, F::LINEAR, W::REPEAT)]),
+            ("[texture.a]\npath = a.png\nbinding = 40\nfilter = nearest\nwrap = mirror", &[("a", 40, F::NEAREST, W::MIRRORED_REPEAT)]),
+            ("[texture.a]", &[]),
+            ("[texture.a]\npath = a.png\nfilter = cubic\nwrap = clamped", &[("a", 32, F::LINEAR, W::REPEAT)]),
+            ("[texture.a]\npath = a.png\nbinding = 1", &[("a", 32, F::LINEAR, W::REPEAT)]),
+            ("[texture.a]\npath = a.png\nbinding = 33\n[texture.b]\npath = b.png\nbinding = 33", &[("a", 33, F::LINEAR, W::REPEAT), ("b", 32, F::LINEAR, W::REPEAT)]),
+            ("[texture.a]\npath = a.png\nwrap = border\n[texture.b]\npath = b.png\nwrap = clamp", &[("a", 32, F::LINEAR, W::CLAMP_TO_BORDER), ("b", 33, F::LINEAR, W::CLAMP_TO_EDGE)]),
+        ];
+        for (text, expected) in cases {
+            let textures = textures(&Config::from_text(text));
+            let textures = textures.iter().map(|texture| (texture.name.as_str(), texture.binding, texture.filter, texture.wrap)).collect::<Vec<_>>();
+            assert_eq!(textures, expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn describes_inputs() {
+        let compute = PassInput::Compute { pass: 0, binding: 8, image: false, buffer: true };
+        let cases: [(&str, &[PassInput]); 9] = [
+            ("[pass.a]\ninputs = capture, mv", &[PassInput::Capture, PassInput::Mv]),
+            //as many captures as there are sources
+            ("[pass.a]\ninputs = captures, capture", &[PassInput::Captures { count: 1 }, PassInput::Capture]),
+            ("[capture]\nsources = 3\n[pass.a]\ninputs = captures", &[PassInput::Captures { count: 3 }]),
+            ("[pass.a]\ninputs = ", &[]),
+            //unknown ones, and doubles, are dropped
+            ("[pass.a]\ninputs = capture, screen, capture,, mv", &[PassInput::Capture, PassInput::Mv]),
+            //there's nothing before the first pass
+            ("[pass.a]\ninputs = previous, feedback", &[PassInput::Feedback]),
+            //textures go elsewhere
+            ("[texture.t]\npath = t.png\n[pass.a]\ninputs = t, capture", &[PassInput::Capture]),
+            //compute passes by name, but not graphics ones
+            ("[compute.c]\nbuffer = 16\n[pass.a]\ninputs = c", &[compute]),
+            ("[pass.b]\n[pass.a]\ninputs = b", &[]),
+        ];
+        for (text, expected) in cases {
+            let passes = describe_text(text);
+            let pass = passes.iter().find(|pass| pass.name == "a").unwrap();
+            assert_eq!(pass.inputs, expected, "{text:?}");
+        }
+
+        let passes = describe_text("[pass.a]\n[pass.b]\ninputs = previous\n[texture.t]\npath = t.png\n[pass.c]\ninputs = t");
+        assert_eq!(passes[1].inputs, [PassInput::Previous]);
+        assert_eq!(passes[2].textures.iter().map(|texture| texture.name.as_str()).collect::<Vec<_>>(), ["t"]);
+    }
+
+    #[test]
+    fn upscales() {
+        let cases = [("", false), ("[scale]", false), ("[scale]\nfactor = 1", false), ("[scale]\nfactor = 0.5", true), ("[scale]\ntarget = 8", true)];
+        for (text, expected) in cases {
+            let passes = describe_text(text);
+            assert_eq!(passes.last().unwrap().name == "upscale", expected, "{text:?}");
+        }
+    }
+}
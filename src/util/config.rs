@@ -0,0 +1,192 @@
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::SystemTime;
+use colored::Colorize;
+use log::{info, warn};
+
+//the runtime config. ini-ish, because that's all we need:
+//
+//  # comment
+//  [pipeline.basic.specialization]
+//  MARGIN = 16
+//
+//sections keep the order they're written in, as do the keys inside them.
+//the file gets polled for changes (see App::about_to_wait), so whatever depends on it should be rebuildable.
+pub(crate) struct Config {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    sections: Vec<Section>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Section {
+    pub name: String,
+    pub entries: Vec<(String, String)>,
+}
+
+impl Config {
+    //a missing or broken config isn't fatal, everything falls back to its defaults.
+    pub fn load(path: impl Into<PathBuf>) -> Config {
+        let mut config = Config { path: path.into(), modified: None, sections: Vec::new() };
+        config.read();
+        config
+    }
+
+    //for tests, a config that's just `text` and never changes
+    #[cfg(test)]
+    pub fn from_text(text: &str) -> Config {
+        Config { path: PathBuf::new(), modified: None, sections: parse(text) }
+    }
+
+    //returns whether the file changed since the last (re)load.
+    pub fn reload(&mut self) -> bool {
+        if self.mtime() == self.modified { return false }
+        self.read();
+        true
+    }
+
+    fn mtime(&self) -> Option<SystemTime> {
+        fs::metadata(&self.path).and_then(|meta| meta.modified()).ok()
+    }
+
+    fn read(&mut self) {
+        self.modified = self.mtime();
+        self.sections = match fs::read_to_string(&self.path) {
+            Ok(text) => {
+                info!("Loading config {}", self.path.display().to_string().bright_purple());
+                parse(&text) }
+            Err(e) => {
+                warn!("Config {} unavailable ({e}), using defaults", self.path.display().to_string().bright_purple());
+                Vec::new() }
+        };
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    //all sections named "{prefix}.<name>", in file order. nested ones like "{prefix}.<name>.specialization" aren't included.
+    pub fn sections(&self, prefix: &str) -> impl Iterator<Item = &Section> {
+        let prefix = format!("{prefix}.");
+        self.sections.iter().filter(move |section| section.name.strip_prefix(&prefix).is_some_and(|name| !name.contains('.')))
+    }
+}
+
+impl Section {
+    pub fn get<T: FromStr>(&self, key: &str) -> Option<T> {
+        let value = self.entries.iter().rev().find(|(k, _)| k == key)?.1.as_str();
+        match value.parse() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                warn!("Config [{}]: can't parse {key} = \"{value}\"", self.name);
+                None }
+        }
+    }
+
+    pub fn get_or<T: FromStr>(&self, key: &str, default: T) -> T {
+        self.get(key).unwrap_or(default)
+    }
+
+    //the part after the prefix, i.e. "basic" for [pipeline.basic]
+    pub fn short_name(&self) -> &str {
+        self.name.rsplit_once('.').map(|(_, name)| name).unwrap_or(&self.name)
+    }
+}
+
+fn parse(text: &str) -> Vec<Section> {
+    //keys before the first header land in an unnamed section
+    let mut sections = vec![Section::default()];
+    for (line_idx, line) in text.lines().enumerate() {
+        let line = line.split_once('#').map(|(line, _)| line).unwrap_or(line).trim();
+        if line.is_empty() { continue }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            sections.push(Section { name: name.trim().to_owned(), entries: Vec::new() });
+            continue
+        }
+        match line.split_once('=') {
+            Some((key, value)) => sections.last_mut().unwrap().entries.push(
+                (key.trim().to_owned(), value.trim().trim_matches('"').to_owned())),
+            None => warn!("Config line {}: expected \"key = value\" or \"[section]\", got \"{line}\"", line_idx + 1),
+        }
+    }
+    sections.retain(|section| !(section.name.is_empty() && section.entries.is_empty()));
+    sections
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(section: &Section) -> Vec<(&str, &str)> {
+        section.entries.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect()
+    }
+
+    //the text, and the sections with their entries it should turn into
+    type ParseCase = (&'static str, &'static [(&'static str, &'static [(&'static str, &'static str)])]);
+
+    #[test]
+    fn parses() {
+        let cases: [ParseCase; 8] = [
+            ("", &[]),
+            ("# just a comment\n\n   \n", &[]),
+            ("[a]\nkey = value", &[("a", &[("key", "value")])]),
+            //whitespace around everything, quotes around values
+            ("  [ a ]  \n\t key\t=  \"some value\"  ", &[("a", &[("key", "some value")])]),
+            //comments after a value, and empty values
+            ("[a]\nkey = value # not this\nempty =\n", &[("a", &[("key", "value"), ("empty", "")])]),
+            //keys before the first header, and empty sections
+            ("key = value\n[a]\n[b]\nother = 1", &[("", &[("key", "value")]), ("a", &[]), ("b", &[("other", "1")])]),
+            //only the first = splits
+            ("[a]\nkey = x = y", &[("a", &[("key", "x = y")])]),
+            //lines that are neither get skipped
+            ("[a]\nnonsense\n[b\nkey = value", &[("a", &[("key", "value")])]),
+        ];
+        for (text, expected) in cases {
+            let sections = parse(text);
+            let sections = sections.iter().map(|section| (section.name.as_str(), entries(section))).collect::<Vec<_>>();
+            let expected = expected.iter().map(|(name, entries)| (*name, entries.to_vec())).collect::<Vec<_>>();
+            assert_eq!(sections, expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn finds_sections() {
+        let config = Config::from_text("[pipeline.a]\n[pipeline.a.specialization]\n[pipeline]\n[other.b]\n[pipeline.c]\n[pipelines.d]");
+        assert!(config.section("pipeline.a.specialization").is_some());
+        assert!(config.section("pipeline.b").is_none());
+        let names = config.sections("pipeline").map(|section| section.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["pipeline.a", "pipeline.c"]);
+        let short = config.sections("pipeline").map(Section::short_name).collect::<Vec<_>>();
+        assert_eq!(short, ["a", "c"]);
+        assert_eq!(config.sections("nothing").count(), 0);
+    }
+
+    #[test]
+    fn gets() {
+        let config = Config::from_text("[a]\nnumber = 16\nfloat = 0.5\nword = hi\nbroken = 1x\ntwice = 1\ntwice = 2\nflag = true");
+        let section = config.section("a").unwrap();
+        let cases: [(&str, Option<u32>); 5] = [
+            ("number", Some(16)),
+            ("twice", Some(2)),
+            //unparseable, negative or missing
+            ("broken", None),
+            ("word", None),
+            ("missing", None),
+        ];
+        for (key, expected) in cases {
+            assert_eq!(section.get::<u32>(key), expected, "{key}");
+        }
+        assert_eq!(section.get::<f32>("float"), Some(0.5));
+        assert_eq!(section.get::<String>("word").as_deref(), Some("hi"));
+        assert_eq!(section.get::<bool>("flag"), Some(true));
+        assert_eq!(section.get::<i32>("float"), None);
+        //the default for whatever doesn't parse, not just what's missing
+        assert_eq!(section.get_or("number", 1u32), 16);
+        assert_eq!(section.get_or("broken", 1u32), 1);
+        assert_eq!(section.get_or("missing", 1u32), 1);
+        assert_eq!(section.get_or("word", 0.25f32), 0.25);
+    }
+}
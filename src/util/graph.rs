@@ -0,0 +1,639 @@
+use std::mem;
+use ash::{khr, vk, Device};
+use log::debug;
+use crate::INSTANCE;
+use crate::util::helpers::{aspect, create_views};
+
+
+//a small render graph. passes declare which images and buffers they touch and how, the graph works out the rest:
+//  - layout transitions and pipeline barriers between passes, tracked while recording (synchronization2 if we have it)
+//  - transient images: allocated by the graph, their contents don't outlive the frame. images whose lifetimes
+//    don't overlap share memory.
+//  - ping-pong pairs: allocated by the graph too, but their contents do carry over. the two trade places every frame.
+//  - transient buffers: one per frame in flight, zeroed before their first use in every frame.
+//transient and ping-pong images are as large as the graph's extent, or as its scaled extent if they're marked as scaled
+//  (see RenderGraph::scale). that's for rendering at a lower resolution than the window has.
+//
+//  let mut graph = RenderGraph::new(physical_device, family, extent, extent, frames, sync2);
+//  let swapchain = graph.import_swapchain("swapchain");
+//  let blurred = graph.transient_image("blurred", vk::Format::R16G16B16A16_SFLOAT, usage);
+//  graph.add_pass("blur", &[(blurred, Access::ColorAttachment)], &[]);
+//  graph.add_pass("present", &[(blurred, Access::Sampled), (swapchain, Access::ColorAttachment)], &[]);
+//  graph.allocate(device);
+//
+//then per frame: begin, barriers before every pass, finish after the last one.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ImageId(usize);
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BufferId(usize);
+
+//how a pass touches a resource
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Access {
+    ColorAttachment,
+    DepthAttachment,
+    Sampled,
+    Uniform,
+    StorageRead,
+    //compute shaders writing a storage image or buffer. they tend to read it too.
+    StorageWrite,
+    TransferSrc,
+    TransferDst,
+    Present,
+    //handed back to whoever outside the device it came from, the compositor say. like Present, it's only ever what
+    //  an image gets left in, or starts out in (see import_image).
+    Foreign,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct State {
+    layout: vk::ImageLayout,
+    stages: vk::PipelineStageFlags2,
+    access: vk::AccessFlags2,
+    //who owns it, if it's not us. see emit
+    family: u32,
+}
+
+const SHADER_STAGES: vk::PipelineStageFlags2 = vk::PipelineStageFlags2::from_raw(
+    vk::PipelineStageFlags2::VERTEX_SHADER.as_raw() | vk::PipelineStageFlags2::FRAGMENT_SHADER.as_raw()
+    | vk::PipelineStageFlags2::COMPUTE_SHADER.as_raw());
+const WRITES: vk::AccessFlags2 = vk::AccessFlags2::from_raw(
+    vk::AccessFlags2::SHADER_WRITE.as_raw() | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE.as_raw() | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+    | vk::AccessFlags2::TRANSFER_WRITE.as_raw() | vk::AccessFlags2::HOST_WRITE.as_raw() | vk::AccessFlags2::MEMORY_WRITE.as_raw());
+const NOTHING: State = State { layout: vk::ImageLayout::UNDEFINED, stages: vk::PipelineStageFlags2::NONE, access: vk::AccessFlags2::NONE,
+                              family: vk::QUEUE_FAMILY_IGNORED };
+const COLOR_RANGE: vk::ImageSubresourceRange = vk::ImageSubresourceRange {
+    aspect_mask: vk::ImageAspectFlags::COLOR,
+    base_mip_level: 0,
+    level_count: vk::REMAINING_MIP_LEVELS,
+    base_array_layer: 0,
+    layer_count: vk::REMAINING_ARRAY_LAYERS };
+
+impl Access {
+    //only the flags that exist in both synchronization apis, so the same state works for either
+    fn state(self) -> State {
+        type Stage = vk::PipelineStageFlags2;
+        type Flags = vk::AccessFlags2;
+        let (layout, stages, access) = match self {
+            Access::ColorAttachment => (vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, Stage::COLOR_ATTACHMENT_OUTPUT, Flags::COLOR_ATTACHMENT_WRITE),
+            Access::DepthAttachment => (vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL, Stage::EARLY_FRAGMENT_TESTS | Stage::LATE_FRAGMENT_TESTS,
+                                        Flags::DEPTH_STENCIL_ATTACHMENT_READ | Flags::DEPTH_STENCIL_ATTACHMENT_WRITE),
+            Access::Sampled => (vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, SHADER_STAGES, Flags::SHADER_READ),
+            Access::Uniform => (vk::ImageLayout::UNDEFINED, SHADER_STAGES, Flags::UNIFORM_READ),
+            Access::StorageRead => (vk::ImageLayout::UNDEFINED, SHADER_STAGES, Flags::SHADER_READ),
+            Access::StorageWrite => (vk::ImageLayout::GENERAL, SHADER_STAGES, Flags::SHADER_READ | Flags::SHADER_WRITE),
+            Access::TransferSrc => (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, Stage::TRANSFER, Flags::TRANSFER_READ),
+            Access::TransferDst => (vk::ImageLayout::TRANSFER_DST_OPTIMAL, Stage::TRANSFER, Flags::TRANSFER_WRITE),
+            //the present engine waits on a semaphore, the barrier only has to get the layout right
+            Access::Present => (vk::ImageLayout::PRESENT_SRC_KHR, Stage::NONE, Flags::NONE),
+            //same for whoever's outside, but they don't know about our layouts. GENERAL is what they get and give.
+            Access::Foreign => (vk::ImageLayout::GENERAL, Stage::NONE, Flags::NONE),
+        };
+        let family = if self == Access::Foreign { vk::QUEUE_FAMILY_FOREIGN_EXT } else { vk::QUEUE_FAMILY_IGNORED };
+        State { layout, stages, access, family }
+    }
+}
+
+enum ImageKind {
+    Transient { format: vk::Format, usage: vk::ImageUsageFlags, samples: vk::SampleCountFlags },
+    //half of a ping-pong pair, see RenderGraph::ping_pong
+    PingPong { format: vk::Format, usage: vk::ImageUsageFlags, partner: usize },
+    //someone else's. `start` is what it's in at the start of every frame, None if it keeps whatever the last frame left.
+    Imported { start: Option<State> },
+}
+
+struct GraphImage {
+    name: String,
+    kind: ImageKind,
+    //one per frame in flight
+    handles: Vec<vk::Image>,
+    views: Vec<vk::ImageView>,
+    states: Vec<State>,
+    //what the image has to be in once the frame is done, if anything
+    finally: Option<Access>,
+    //the transient image whose memory this one took over, if any. its last use is what the first use here waits on.
+    alias: Option<usize>,
+    //as large as the scaled extent instead of the full one
+    scaled: bool,
+}
+
+struct GraphBuffer {
+    handles: Vec<vk::Buffer>,
+    states: Vec<State>,
+    //size and usage, if the graph allocates it
+    transient: Option<(u64, vk::BufferUsageFlags)>,
+}
+
+struct GraphPass {
+    name: String,
+    images: Vec<(ImageId, Access)>,
+    buffers: Vec<(BufferId, Access)>,
+}
+
+pub(crate) struct RenderGraph {
+    images: Vec<GraphImage>,
+    buffers: Vec<GraphBuffer>,
+    passes: Vec<GraphPass>,
+    frames: usize,
+    //of every transient image, and of the scaled ones
+    extent: vk::Extent2D,
+    scaled: vk::Extent2D,
+    physical_device: vk::PhysicalDevice,
+    //of the queue the frames get submitted to, which takes foreign images over
+    family: u32,
+    //backing the transient images, per frame in flight
+    memory: Vec<vk::DeviceMemory>,
+    sync2: Option<khr::synchronization2::Device>,
+}
+
+
+impl RenderGraph {
+    pub fn new(physical_device: vk::PhysicalDevice, family: u32, extent: vk::Extent2D, scaled: vk::Extent2D, frames: usize,
+               sync2: Option<khr::synchronization2::Device>) -> RenderGraph {
+        RenderGraph { images: Vec::new(), buffers: Vec::new(), passes: Vec::new(), frames, extent, scaled, physical_device, family,
+                      memory: Vec::new(), sync2 }
+    }
+
+    //the image changes with every acquire, see set_image. it's undefined at the start of every frame and presentable at the end.
+    //the acquire semaphore gets waited on in COLOR_ATTACHMENT_OUTPUT, so that's where the first barrier has to start.
+    pub fn import_swapchain(&mut self, name: &str) -> ImageId {
+        let start = State { stages: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, ..NOTHING };
+        self.push_image(name, ImageKind::Imported { start: Some(start) }, Some(Access::Present))
+    }
+
+    //an image that lives across frames, the same in every one of them. starts out undefined, unless whoever owns it
+    //  keeps it in `start` from one frame to the next.
+    //one that's Foreign gets taken over from outside the device at its first use in a frame, contents and all,
+    //  and handed back at the end of it.
+    pub fn import_image(&mut self, name: &str, image: vk::Image, start: Option<Access>) -> ImageId {
+        let id = self.push_image(name, ImageKind::Imported { start: None }, None);
+        self.images[id.0].handles = vec![image; self.frames];
+        self.reimport_image(id, image, start);
+        id
+    }
+
+    //swaps an imported image for another one, which starts out like import_image says
+    pub fn reimport_image(&mut self, image: ImageId, handle: vk::Image, start: Option<Access>) {
+        self.images[image.0].kind = ImageKind::Imported { start: start.map(Access::state) };
+        self.images[image.0].finally = start.filter(|start| *start == Access::Foreign);
+        self.images[image.0].handles.fill(handle);
+        self.images[image.0].states.fill(start.map_or(NOTHING, Access::state));
+    }
+
+    pub fn transient_image(&mut self, name: &str, format: vk::Format, usage: vk::ImageUsageFlags) -> ImageId {
+        self.push_image(name, ImageKind::Transient { format, usage, samples: vk::SampleCountFlags::TYPE_1 }, None)
+    }
+
+    //a transient depth attachment, with as many samples as the color attachment it goes with
+    pub fn depth_image(&mut self, name: &str, format: vk::Format, samples: vk::SampleCountFlags) -> ImageId {
+        let usage = vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT;
+        self.push_image(name, ImageKind::Transient { format, usage, samples }, None)
+    }
+
+    //a transient color attachment with more than one sample. it only ever gets resolved, never sampled.
+    pub fn multisampled_image(&mut self, name: &str, format: vk::Format, samples: vk::SampleCountFlags) -> ImageId {
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT;
+        self.push_image(name, ImageKind::Transient { format, usage, samples }, None)
+    }
+
+    //two images that trade places at the start of every frame, so `previous` holds what got rendered into `current`
+    //  the frame before. previous reads as cleared until there's something in it.
+    //each frame in flight has a pair of its own. with more than one of them, "the frame before" is that many frames back.
+    pub fn ping_pong(&mut self, name: &str, format: vk::Format, usage: vk::ImageUsageFlags) -> (ImageId, ImageId) {
+        //the clearing needs the transfer
+        let usage = usage | vk::ImageUsageFlags::TRANSFER_DST;
+        let current = self.push_image(&format!("{name}.current"), ImageKind::PingPong { format, usage, partner: self.images.len() + 1 }, None);
+        let previous = self.push_image(&format!("{name}.previous"), ImageKind::PingPong { format, usage, partner: current.0 }, None);
+        (current, previous)
+    }
+
+    //one buffer per frame in flight
+    pub fn import_buffers(&mut self, buffers: Vec<vk::Buffer>) -> BufferId {
+        self.buffers.push(GraphBuffer { handles: buffers, states: vec![NOTHING; self.frames], transient: None });
+        BufferId(self.buffers.len() - 1)
+    }
+
+    //one per frame in flight, like the imported ones. zeroed before whichever pass touches it first.
+    pub fn transient_buffer(&mut self, size: u64, usage: vk::BufferUsageFlags) -> BufferId {
+        //the zeroing needs the transfer
+        let usage = usage | vk::BufferUsageFlags::TRANSFER_DST;
+        self.buffers.push(GraphBuffer { handles: vec![vk::Buffer::null(); self.frames], states: vec![NOTHING; self.frames], transient: Some((size, usage)) });
+        BufferId(self.buffers.len() - 1)
+    }
+
+    fn push_image(&mut self, name: &str, kind: ImageKind, finally: Option<Access>) -> ImageId {
+        self.images.push(GraphImage {
+            name: name.to_owned(), kind, finally,
+            handles: vec![vk::Image::null(); self.frames],
+            views: vec![vk::ImageView::null(); self.frames],
+            states: vec![NOTHING; self.frames],
+            alias: None,
+            scaled: false });
+        ImageId(self.images.len() - 1)
+    }
+
+    //makes a transient or ping-pong image as large as the scaled extent. call before allocate.
+    pub fn scale(&mut self, image: ImageId) {
+        self.images[image.0].scaled = true;
+    }
+
+    //how large the image is. imported ones are taken to be as large as the graph.
+    pub fn extent(&self, image: ImageId) -> vk::Extent2D {
+        if self.images[image.0].scaled { self.scaled } else { self.extent }
+    }
+
+    //passes get recorded in the order they're added
+    pub fn add_pass(&mut self, name: &str, images: &[(ImageId, Access)], buffers: &[(BufferId, Access)]) {
+        self.passes.push(GraphPass { name: name.to_owned(), images: images.to_vec(), buffers: buffers.to_vec() });
+    }
+
+    pub fn set_image(&mut self, image: ImageId, frame: usize, handle: vk::Image, view: vk::ImageView) {
+        self.images[image.0].handles[frame] = handle;
+        self.images[image.0].views[frame] = view;
+    }
+
+    pub fn image(&self, image: ImageId, frame: usize) -> vk::Image {
+        self.images[image.0].handles[frame]
+    }
+
+    pub fn view(&self, image: ImageId, frame: usize) -> vk::ImageView {
+        self.images[image.0].views[frame]
+    }
+
+    pub fn buffer(&self, buffer: BufferId, frame: usize) -> vk::Buffer {
+        self.buffers[buffer.0].handles[frame]
+    }
+
+    //every view the image can have in that frame. that's both of a ping-pong pair's, as they keep swapping.
+    pub fn views(&self, image: ImageId, frame: usize) -> Vec<vk::ImageView> {
+        let mut views = vec![self.view(image, frame)];
+        if let ImageKind::PingPong { partner, .. } = self.images[image.0].kind { views.push(self.images[partner].views[frame]) }
+        views
+    }
+
+    pub fn sync2(&self) -> Option<&khr::synchronization2::Device> {
+        self.sync2.as_ref()
+    }
+
+    //creates the transient images and hands out their memory. call once all passes are in.
+    pub unsafe fn allocate(&mut self, device: &Device) {
+        //first and last pass using each transient image
+        let mut lifetimes: Vec<(usize, usize, usize)> = self.images.iter().enumerate()
+            .filter(|(_, image)| matches!(image.kind, ImageKind::Transient { .. }))
+            .map(|(idx, _)| {
+                let uses: Vec<usize> = self.passes.iter().enumerate()
+                    .filter(|(_, pass)| pass.images.iter().any(|(image, _)| image.0 == idx))
+                    .map(|(pass_idx, _)| pass_idx).collect();
+                (idx, uses.first().copied().unwrap_or(0), uses.last().copied().unwrap_or(0))
+            }).collect();
+        lifetimes.sort_by_key(|(_, first, _)| *first);
+
+        for (idx, _, _) in &lifetimes {
+            let ImageKind::Transient { format, usage, samples } = self.images[*idx].kind else { unreachable!() };
+            let extent = self.extent(ImageId(*idx));
+            self.images[*idx].handles = (0..self.frames).map(|_| self.create_image(device, format, usage, samples, extent)).collect();
+        }
+
+        //greedy: every image goes into the first slot whose previous tenant is done by the time it's needed.
+        //the requirements are the same for every frame's copy, so frame 0 decides for all of them.
+        struct Slot { size: u64, type_bits: u32, last: usize, tenants: Vec<usize> }
+        let mut slots: Vec<Slot> = Vec::new();
+        for &(idx, first, last) in &lifetimes {
+            let requirements = device.get_image_memory_requirements(self.images[idx].handles[0]);
+            let slot = slots.iter_mut().find(|slot| slot.last < first && slot.type_bits & requirements.memory_type_bits != 0);
+            match slot {
+                Some(slot) => {
+                    let alias = *slot.tenants.last().unwrap();
+                    debug!("Render graph: {} reuses the memory of {} from pass \"{}\" on", self.images[idx].name, self.images[alias].name, self.passes[first].name);
+                    self.images[idx].alias = Some(alias);
+                    slot.size = slot.size.max(requirements.size);
+                    slot.type_bits &= requirements.memory_type_bits;
+                    slot.last = last;
+                    slot.tenants.push(idx);
+                }
+                None => slots.push(Slot { size: requirements.size, type_bits: requirements.memory_type_bits, last, tenants: vec![idx] }),
+            }
+        }
+        debug!("Render graph: {} transient image(s) in {} allocation(s) per frame", lifetimes.len(), slots.len());
+
+        for frame in 0..self.frames {
+            for slot in &slots {
+                let memory = self.allocate_memory(device, slot.size, slot.type_bits);
+                for &tenant in &slot.tenants {
+                    device.bind_image_memory(self.images[tenant].handles[frame], memory, 0).unwrap();
+                }
+            }
+        }
+
+        for (idx, _, _) in &lifetimes {
+            let image = &mut self.images[*idx];
+            let ImageKind::Transient { format, .. } = image.kind else { unreachable!() };
+            image.views = create_views(device, &image.handles, format);
+        }
+
+        //ping-pong images keep their contents, so they don't share memory with anything
+        for idx in 0..self.images.len() {
+            let ImageKind::PingPong { format, usage, .. } = self.images[idx].kind else { continue };
+            let extent = self.extent(ImageId(idx));
+            for frame in 0..self.frames {
+                let handle = self.create_image(device, format, usage, vk::SampleCountFlags::TYPE_1, extent);
+                let requirements = device.get_image_memory_requirements(handle);
+                let memory = self.allocate_memory(device, requirements.size, requirements.memory_type_bits);
+                device.bind_image_memory(handle, memory, 0).unwrap();
+                self.images[idx].handles[frame] = handle;
+            }
+            self.images[idx].views = create_views(device, &self.images[idx].handles, format);
+        }
+
+        //few and small, they get memory of their own too
+        for idx in 0..self.buffers.len() {
+            let Some((size, usage)) = self.buffers[idx].transient else { continue };
+            for frame in 0..self.frames {
+                let buffer_info = vk::BufferCreateInfo {
+                    size,
+                    usage,
+                    sharing_mode: vk::SharingMode::EXCLUSIVE,
+                    ..Default::default()};
+                let handle = device.create_buffer(&buffer_info, None).unwrap();
+                let requirements = device.get_buffer_memory_requirements(handle);
+                let memory = self.allocate_memory(device, requirements.size, requirements.memory_type_bits);
+                device.bind_buffer_memory(handle, memory, 0).unwrap();
+                self.buffers[idx].handles[frame] = handle;
+            }
+        }
+    }
+
+    unsafe fn create_image(&self, device: &Device, format: vk::Format, usage: vk::ImageUsageFlags, samples: vk::SampleCountFlags,
+                           extent: vk::Extent2D) -> vk::Image {
+        let image_info = vk::ImageCreateInfo {
+            image_type: vk::ImageType::TYPE_2D,
+            format,
+            extent: vk::Extent3D { width: extent.width, height: extent.height, depth: 1 },
+            mip_levels: 1,
+            array_layers: 1,
+            samples,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            ..Default::default()};
+        device.create_image(&image_info, None).unwrap()
+    }
+
+    //device local if at all possible. the graph frees it along with the images.
+    unsafe fn allocate_memory(&mut self, device: &Device, size: u64, type_bits: u32) -> vk::DeviceMemory {
+        let mem_properties = INSTANCE.get_physical_device_memory_properties(self.physical_device);
+        let types = mem_properties.memory_types[..mem_properties.memory_type_count as _].iter().enumerate()
+            .filter(|(idx, _)| (1u32 << idx) & type_bits != 0);
+        let mem_idx = types.clone().find(|(_, mem_type)| mem_type.property_flags.contains(vk::MemoryPropertyFlags::DEVICE_LOCAL))
+            .or(types.clone().next())
+            .map(|(idx, _)| idx as u32).expect("no matching mem type found");
+        let allocate_info = vk::MemoryAllocateInfo {
+            allocation_size: size,
+            memory_type_index: mem_idx,
+            ..Default::default()};
+        let memory = device.allocate_memory(&allocate_info, None).unwrap();
+        self.memory.push(memory);
+        memory
+    }
+
+    //transient images are as large as the graph's extent, or its scaled one
+    pub unsafe fn resize(&mut self, device: &Device, extent: vk::Extent2D, scaled: vk::Extent2D) {
+        self.free(device);
+        self.extent = extent;
+        self.scaled = scaled;
+        self.allocate(device);
+    }
+
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        self.free(device);
+    }
+
+    unsafe fn free(&mut self, device: &Device) {
+        for image in &mut self.images {
+            if matches!(image.kind, ImageKind::Imported { .. }) { continue }
+            image.views.drain(..).for_each(|view| device.destroy_image_view(view, None));
+            image.handles.iter_mut().for_each(|handle| {
+                device.destroy_image(*handle, None);
+                *handle = vk::Image::null() });
+            image.states.fill(NOTHING);
+            image.alias = None;
+        }
+        for buffer in self.buffers.iter_mut().filter(|buffer| buffer.transient.is_some()) {
+            buffer.handles.iter_mut().for_each(|handle| {
+                device.destroy_buffer(*handle, None);
+                *handle = vk::Buffer::null() });
+            buffer.states.fill(NOTHING);
+        }
+        self.memory.drain(..).for_each(|memory| device.free_memory(memory, None));
+    }
+
+    //everything that doesn't carry over between frames starts from scratch
+    pub fn begin(&mut self, frame: usize) {
+        for idx in 0..self.images.len() {
+            let ImageKind::PingPong { partner, .. } = self.images[idx].kind else { continue };
+            if partner < idx { continue }
+            let (left, right) = self.images.split_at_mut(partner);
+            let (current, previous) = (&mut left[idx], &mut right[0]);
+            mem::swap(&mut current.handles[frame], &mut previous.handles[frame]);
+            mem::swap(&mut current.views[frame], &mut previous.views[frame]);
+            mem::swap(&mut current.states[frame], &mut previous.states[frame]);
+        }
+        for image in &mut self.images {
+            match image.kind {
+                ImageKind::Transient { .. } => image.states[frame] = NOTHING,
+                ImageKind::Imported { start: Some(start) } => image.states[frame] = start,
+                ImageKind::Imported { start: None } | ImageKind::PingPong { .. } => {}
+            }
+        }
+        //host writes are made visible by the submission itself, as are uploads by the semaphore it waits on (see util::upload).
+        //  so buffers start with nothing to wait on
+        self.buffers.iter_mut().for_each(|buffer| buffer.states[frame] = NOTHING);
+    }
+
+    //everything the pass needs to wait on, as a single barrier
+    pub unsafe fn barriers(&mut self, device: &Device, command_buffer: vk::CommandBuffer, frame: usize, pass: usize) {
+        self.clear_fresh(device, command_buffer, frame, pass);
+
+        let mut image_barriers: Vec<(vk::Image, vk::ImageAspectFlags, State, State)> = Vec::new();
+        let mut buffer_barriers: Vec<(vk::Buffer, State, State)> = Vec::new();
+        for &(image, access) in &self.passes[pass].images {
+            //first use of an image in memory someone else had: wait for them to be done with it
+            let previous = self.images[image.0].alias.map(|alias| self.images[alias].states[frame]);
+            let graph_image = &mut self.images[image.0];
+            if let (Some(previous), vk::ImageLayout::UNDEFINED) = (previous, graph_image.states[frame].layout) {
+                graph_image.states[frame].stages = previous.stages;
+                graph_image.states[frame].access = previous.access;
+            }
+            if let Some((src, dst)) = advance(&mut graph_image.states[frame], access.state()) {
+                image_barriers.push((graph_image.handles[frame], graph_image.aspect(), src, dst));
+            }
+        }
+        for &(buffer, access) in &self.passes[pass].buffers {
+            let graph_buffer = &mut self.buffers[buffer.0];
+            if let Some((src, dst)) = advance(&mut graph_buffer.states[frame], access.state()) {
+                buffer_barriers.push((graph_buffer.handles[frame], src, dst));
+            }
+        }
+        self.emit(device, command_buffer, &image_barriers, &buffer_barriers);
+    }
+
+    //ping-pong images that get read before anything was ever written into them. black beats whatever was in memory.
+    //  transient buffers get zeroed on their first use every frame, so compute shaders can accumulate into them.
+    unsafe fn clear_fresh(&mut self, device: &Device, command_buffer: vk::CommandBuffer, frame: usize, pass: usize) {
+        let mut image_barriers: Vec<(vk::Image, vk::ImageAspectFlags, State, State)> = Vec::new();
+        for &(image, access) in &self.passes[pass].images {
+            let graph_image = &mut self.images[image.0];
+            let fresh = graph_image.states[frame].layout == vk::ImageLayout::UNDEFINED && !access.state().access.intersects(WRITES);
+            if !(fresh && matches!(graph_image.kind, ImageKind::PingPong { .. })) { continue }
+            if let Some((src, dst)) = advance(&mut graph_image.states[frame], Access::TransferDst.state()) {
+                image_barriers.push((graph_image.handles[frame], graph_image.aspect(), src, dst));
+            }
+        }
+        let mut buffer_barriers: Vec<(vk::Buffer, State, State)> = Vec::new();
+        for &(buffer, _) in &self.passes[pass].buffers {
+            let graph_buffer = &mut self.buffers[buffer.0];
+            let fresh = graph_buffer.states[frame].stages.is_empty() && graph_buffer.states[frame].access.is_empty();
+            if !(fresh && graph_buffer.transient.is_some()) { continue }
+            //nothing to wait on, but the fill still has to come before the pass
+            graph_buffer.states[frame] = Access::TransferDst.state();
+            buffer_barriers.push((graph_buffer.handles[frame], NOTHING, graph_buffer.states[frame]));
+        }
+        if image_barriers.is_empty() && buffer_barriers.is_empty() { return }
+        self.emit(device, command_buffer, &image_barriers, &buffer_barriers);
+        let clear_color = vk::ClearColorValue { float32: [0.0; 4] };
+        for (image, _, _, _) in image_barriers {
+            device.cmd_clear_color_image(command_buffer, image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &clear_color, &[COLOR_RANGE]);
+        }
+        for (buffer, _, _) in buffer_barriers {
+            device.cmd_fill_buffer(command_buffer, buffer, 0, vk::WHOLE_SIZE, 0);
+        }
+    }
+
+    //leaves every image the way whoever's after us expects it
+    pub unsafe fn finish(&mut self, device: &Device, command_buffer: vk::CommandBuffer, frame: usize) {
+        let mut image_barriers: Vec<(vk::Image, vk::ImageAspectFlags, State, State)> = Vec::new();
+        for image in &mut self.images {
+            let Some(finally) = image.finally else { continue };
+            if let Some((src, dst)) = advance(&mut image.states[frame], finally.state()) {
+                image_barriers.push((image.handles[frame], image.aspect(), src, dst));
+            }
+        }
+        self.emit(device, command_buffer, &image_barriers, &[]);
+    }
+
+    //an image changing hands gets both families, ours and the one it comes from or goes to
+    unsafe fn emit(&self, device: &Device, command_buffer: vk::CommandBuffer, images: &[(vk::Image, vk::ImageAspectFlags, State, State)], buffers: &[(vk::Buffer, State, State)]) {
+        if images.is_empty() && buffers.is_empty() { return }
+        let families = |src: &State, dst: &State| match src.family == dst.family {
+            true => (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED),
+            false => (if src.family == vk::QUEUE_FAMILY_IGNORED { self.family } else { src.family },
+                      if dst.family == vk::QUEUE_FAMILY_IGNORED { self.family } else { dst.family }),
+        };
+
+        match &self.sync2 {
+            Some(sync2) => {
+                let image_barriers: Vec<vk::ImageMemoryBarrier2> = images.iter().map(|(image, aspect_mask, src, dst)| {
+                    let (src_queue_family_index, dst_queue_family_index) = families(src, dst);
+                    vk::ImageMemoryBarrier2 {
+                        src_stage_mask: src.stages,
+                        src_access_mask: src.access & WRITES,
+                        dst_stage_mask: dst.stages,
+                        dst_access_mask: dst.access,
+                        old_layout: src.layout,
+                        new_layout: dst.layout,
+                        src_queue_family_index,
+                        dst_queue_family_index,
+                        image: *image,
+                        subresource_range: vk::ImageSubresourceRange { aspect_mask: *aspect_mask, ..COLOR_RANGE },
+                        ..Default::default()}
+                }).collect();
+                let buffer_barriers: Vec<vk::BufferMemoryBarrier2> = buffers.iter().map(|(buffer, src, dst)| vk::BufferMemoryBarrier2 {
+                    src_stage_mask: src.stages,
+                    src_access_mask: src.access & WRITES,
+                    dst_stage_mask: dst.stages,
+                    dst_access_mask: dst.access,
+                    src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    buffer: *buffer,
+                    offset: 0,
+                    size: vk::WHOLE_SIZE,
+                    ..Default::default()}).collect();
+                let dependency_info = vk::DependencyInfo {
+                    image_memory_barrier_count: image_barriers.len() as u32,
+                    p_image_memory_barriers: image_barriers.as_ptr(),
+                    buffer_memory_barrier_count: buffer_barriers.len() as u32,
+                    p_buffer_memory_barriers: buffer_barriers.as_ptr(),
+                    ..Default::default()};
+                sync2.cmd_pipeline_barrier2(command_buffer, &dependency_info);
+            }
+            None => {
+                //without synchronization2 there's only one pair of stage masks per barrier, and they can't be empty
+                let stages = |stages: vk::PipelineStageFlags2| vk::PipelineStageFlags::from_raw(stages.as_raw() as u32);
+                let access = |access: vk::AccessFlags2| vk::AccessFlags::from_raw(access.as_raw() as u32);
+                let mut src_stages = vk::PipelineStageFlags::empty();
+                let mut dst_stages = vk::PipelineStageFlags::empty();
+                let image_barriers: Vec<vk::ImageMemoryBarrier> = images.iter().map(|(image, aspect_mask, src, dst)| {
+                    src_stages |= stages(src.stages);
+                    dst_stages |= stages(dst.stages);
+                    let (src_queue_family_index, dst_queue_family_index) = families(src, dst);
+                    vk::ImageMemoryBarrier {
+                        src_access_mask: access(src.access & WRITES),
+                        dst_access_mask: access(dst.access),
+                        old_layout: src.layout,
+                        new_layout: dst.layout,
+                        src_queue_family_index,
+                        dst_queue_family_index,
+                        image: *image,
+                        subresource_range: vk::ImageSubresourceRange { aspect_mask: *aspect_mask, ..COLOR_RANGE },
+                        ..Default::default()}
+                }).collect();
+                let buffer_barriers: Vec<vk::BufferMemoryBarrier> = buffers.iter().map(|(buffer, src, dst)| {
+                    src_stages |= stages(src.stages);
+                    dst_stages |= stages(dst.stages);
+                    vk::BufferMemoryBarrier {
+                        src_access_mask: access(src.access & WRITES),
+                        dst_access_mask: access(dst.access),
+                        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                        buffer: *buffer,
+                        offset: 0,
+                        size: vk::WHOLE_SIZE,
+                        ..Default::default()}
+                }).collect();
+                if src_stages.is_empty() { src_stages = vk::PipelineStageFlags::TOP_OF_PIPE }
+                if dst_stages.is_empty() { dst_stages = vk::PipelineStageFlags::BOTTOM_OF_PIPE }
+                device.cmd_pipeline_barrier(command_buffer, src_stages, dst_stages, vk::DependencyFlags::empty(),
+                                            &[], &buffer_barriers, &image_barriers);
+            }
+        }
+    }
+}
+
+impl GraphImage {
+    //imported images are all color ones so far
+    fn aspect(&self) -> vk::ImageAspectFlags {
+        match self.kind {
+            ImageKind::Transient { format, .. } | ImageKind::PingPong { format, .. } => aspect(format),
+            ImageKind::Imported { .. } => vk::ImageAspectFlags::COLOR,
+        }
+    }
+}
+
+//moves a resource on to its next access. returns the barrier in between, if there has to be one:
+//  a layout change, a change of hands, or anything involving a write. reads after reads just pile up, so a later write
+//  waits on all of them.
+fn advance(current: &mut State, next: State) -> Option<(State, State)> {
+    let hazard = current.layout != next.layout || current.family != next.family || current.access.intersects(WRITES) || next.access.intersects(WRITES);
+    if !hazard {
+        current.stages |= next.stages;
+        current.access |= next.access;
+        return None
+    }
+    let src = *current;
+    *current = next;
+    //nothing happened to it yet and the layout's fine, so there's nothing to wait for
+    if src.stages.is_empty() && src.layout == next.layout && src.family == next.family { return None }
+    Some((src, next))
+}
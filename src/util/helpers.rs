@@ -3,60 +3,28 @@ use std::{fs, mem, process, ptr, slice};
 use std::error::Error;
 use std::fs::DirEntry;
 use ash::{khr, vk, Device};
-use ash::vk::{DescriptorPool, DescriptorSetLayout, Pipeline, PipelineLayout, PushConstantRange};
-use log::{debug, error, info};
-use shaderc::{CompilationArtifact, IncludeCallbackResult, IncludeType, ResolvedInclude};
+use ash::vk::{Pipeline, PipelineLayout};
+use log::{debug, error, info, warn};
 #[cfg(target_os = "linux")]
 use winit::platform::wayland::WindowExtWayland;
 use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle, RawDisplayHandle, RawWindowHandle};
 use winit::window::Window;
-use crate::{UniformBufferObject, MAX_FRAMES_IN_FLIGHT, T_ZERO};
+use crate::{PushConstants, INSTANCE, T_ZERO};
 use crate::util::per_window::PerWindow;
 use crate::util::swapchain::PerSwapchain;
-
-//the bare minimum
-fn load_shaders(source: &str, kind: shaderc::ShaderKind) -> shaderc::Result<CompilationArtifact> {
-    let compiler = shaderc::Compiler::new()?;
-    let mut options = shaderc::CompileOptions::new()?;
-    //specify the entry point - here, it's "main"
-    options.add_macro_definition("EP", Some("main"));
-    options.set_include_callback(|file: &str, kind: IncludeType, parent: &str, _depth: usize|{
-        let parent_dir = parent.rsplit_once('/').unwrap_or(("/","")).0;
-        let path: String = match kind {
-            IncludeType::Relative => {
-                format!("{parent_dir}/{file}")
-            }
-            IncludeType::Standard => {
-                format!("src/lib/{file}")
-            }
-        };
-        let file: String = match fs::read_to_string(&path) {
-            Ok(file) => {
-                info!("Loading {path} as dependency of {parent}");
-                file },
-            Err(e) => {
-                error!("Failure building shader: {e}");
-                return Err(format!("{e}"))
-            }
-        };
-        Ok(ResolvedInclude {
-            resolved_name: path,
-            content: file
-        })});
-    compiler.compile_into_spirv(
-        source, kind,
-        "src/shader/SHADER.glsl", "main", Some(&options))
-}
-
-
-
+use crate::util::reflect::{ScalarKind, ShaderInterface};
+use crate::util::chain::{Chain, PassDesc, PassKind};
+use crate::util::config::Section;
+use crate::util::upload::Uploader;
+use crate::util::scale::RenderScale;
+use crate::util::shaders::{compile_glsl, load_shaders, Diagnostic, ShaderError, ShaderSources};
 
 
 
 #[derive(Copy, Clone)]
 pub struct Vertex {
-    pos: [f32;3],
-    tex: [f32;2],
+    pub(crate) pos: [f32;3],
+    pub(crate) tex: [f32;2],
 }
 
 
@@ -68,24 +36,109 @@ pub const VERTICES: [Vertex;4] = [
 ];
 
 
+//the shaders can be swapped out from the config, in any language load_shaders knows:
+//  [pass.effect]
+//  fragment = src/shader/effect.hlsl
+//the code comes back in stage order: vertex and fragment for a graphics pass, just the one for a compute pass
+pub(crate) fn compile_pass(pass: &PassDesc, sources: &mut ShaderSources) -> Result<(Vec<Vec<u32>>, ShaderInterface), ShaderError> {
+    let codes = match &pass.kind {
+        PassKind::Graphics { vertex, fragment, .. } => {
+            //both get compiled even if the first one fails, so both get watched
+            let vertex_shader_code = load_shaders(vertex, shaderc::ShaderKind::Vertex, sources);
+            let fragment_shader_code = load_shaders(fragment, shaderc::ShaderKind::Fragment, sources);
+            match (vertex_shader_code, fragment_shader_code) {
+                (Ok(vertex), Ok(fragment)) => vec![vertex, fragment],
+                (Err(vertex), Err(fragment)) =>
+                    return Err(ShaderError::new(format!("pass \"{}\"", pass.name), [vertex.diagnostics, fragment.diagnostics].concat())),
+                (Err(e), _) | (_, Err(e)) => return Err(e),
+            }
+        }
+        PassKind::Compute { shader, .. } => vec![load_shaders(shader, shaderc::ShaderKind::Compute, sources)?],
+    };
+    let interface = reflect_pass(pass, &codes)?;
+    Ok((codes, interface))
+}
 
+fn reflect_pass(pass: &PassDesc, codes: &[Vec<u32>]) -> Result<ShaderInterface, ShaderError> {
+    let fail = |problems: Vec<String>| ShaderError::new(format!("pass \"{}\"", pass.name),
+        problems.into_iter().map(|message| Diagnostic { file: None, line: None, message }).collect());
+    let interface = codes.iter().try_fold(ShaderInterface::default(), |interface, code| interface.merge(ShaderInterface::reflect(code)?))
+        .map_err(|e| fail(vec![e.to_string()]))?;
+    interface.check(&pass.name, &pass.bindings(), Some(size_of::<PushConstants>() as u32))
+        .map_err(|mismatch| fail(mismatch.problems))?;
+    if let Some(binding) = interface.bindings.iter().find(|binding| binding.set != 0) {
+        return Err(fail(vec![format!("Descriptor set {} ({}) is unsupported, everything has to live in set 0", binding.set, binding.name)]))
+    }
+    Ok(interface)
+}
 
+//shown instead of a pass while its shaders don't build. the diagnostics get baked into the fragment shader
+//  as constants, so it doesn't use anything from the layout and fits whichever one the pass has.
+pub(crate) unsafe fn build_error_pipeline(device: &Device, extent: vk::Extent2D, render_pass: vk::RenderPass, format: vk::Format,
+                                          samples: vk::SampleCountFlags, depth: Option<vk::Format>, layout: PipelineLayout, error: &ShaderError) -> Pipeline {
+    error!("{error}");
+    let (version, body) = ERROR_FRAGMENT.split_once('\n').unwrap();
+    let fragment_source = format!("{version}\n{ERROR_FONT}\n{}\n{body}", error_text(error));
+    let vertex_shader_code = compile_glsl(ERROR_VERTEX, "src/shader/error.vert", shaderc::ShaderKind::Vertex)
+        .unwrap_or_else(|e| { error!("{e}"); panic!() });
+    let fragment_shader_code = compile_glsl(&fragment_source, "src/shader/error.frag", shaderc::ShaderKind::Fragment)
+        .unwrap_or_else(|e| { error!("{e}"); panic!() });
+    build_graphics_pipeline(device, extent, render_pass, format, samples, depth, vk::PrimitiveTopology::TRIANGLE_STRIP, layout,
+        &vertex_shader_code, &fragment_shader_code, &ShaderInterface::default(), None, "error")
+}
 
+//the error pipeline's shaders are part of the binary, so they still work when the shader directory doesn't
+const ERROR_VERTEX: &str = include_str!("../shader/error.vert");
+const ERROR_FRAGMENT: &str = include_str!("../shader/error.frag");
+const ERROR_FONT: &str = include_str!("../shader/font.glsl");
+const ERROR_COLUMNS: usize = 160;
+const ERROR_ROWS: usize = 64;
+
+//the error as GLSL: a grid of ERROR_COLUMNS characters per row, four to a uint. long lines wrap, anything past ERROR_ROWS is cut.
+fn error_text(error: &ShaderError) -> String {
+    let mut rows: Vec<Vec<u8>> = Vec::new();
+    for line in error.to_string().lines() {
+        let line: Vec<u8> = line.chars().map(|c| match c {
+            '\t' => b' ',
+            ' '..='~' => c as u8,
+            _ => b'?' }).collect();
+        if line.is_empty() { rows.push(Vec::new()) }
+        rows.extend(line.chunks(ERROR_COLUMNS).map(<[u8]>::to_vec));
+    }
+    rows.truncate(ERROR_ROWS);
+    let words: Vec<String> = rows.iter_mut().flat_map(|row| {
+        row.resize(ERROR_COLUMNS, b' ');
+        row.chunks(4).map(|chars| format!("{:#010x}u", u32::from_le_bytes(chars.try_into().unwrap()))).collect::<Vec<_>>()
+    }).collect();
+    format!("const int TEXT_COLUMNS = {ERROR_COLUMNS};\nconst int TEXT_ROWS = {};\nconst uint TEXT[{}] = uint[]({});\n",
+        rows.len(), words.len(), words.join(", "))
+}
+
+//a null render pass means dynamic rendering, where all the pipeline gets to know about its target is the format.
+//`depth` is the format of the depth attachment, if there is one. it gets tested against and written.
+//`topology` is TRIANGLE_STRIP for the fullscreen quad, TRIANGLE_LIST for meshes.
+pub(crate) unsafe fn build_graphics_pipeline(device: &Device, extent: vk::Extent2D, render_pass: vk::RenderPass, format: vk::Format,
+                                             samples: vk::SampleCountFlags, depth: Option<vk::Format>, topology: vk::PrimitiveTopology,
+                                             layout: PipelineLayout,
+                                             vertex_shader_code: &[u32], fragment_shader_code: &[u32], interface: &ShaderInterface,
+                                             specialization_section: Option<&Section>, pipeline: &str) -> Pipeline {
+    let (map_entries, data) = specialization(interface, specialization_section, pipeline);
+    let specialization_info = vk::SpecializationInfo {
+        map_entry_count: map_entries.len() as u32,
+        p_map_entries: map_entries.as_ptr(),
+        data_size: data.len(),
+        p_data: data.as_ptr().cast(),
+        ..Default::default()};
 
-//this func was made due to code starting to be annoying to read in other files and a severe lack of proper error managment.
-//ofc there's no real error management here too. crash and burn, shitheap
-pub(crate) unsafe fn create_graphics_pipeline(device: &Device, extent: vk::Extent2D, render_pass: vk::RenderPass) -> (Pipeline, PipelineLayout, PushConstantRange, DescriptorSetLayout, DescriptorPool) {
-    let vertex_shader_code = load_shaders(include_str!("../shader/basic.vert"),shaderc::ShaderKind::Vertex).unwrap();
-    let fragment_shader_code = load_shaders(include_str!("../shader/basic.frag"),shaderc::ShaderKind::Fragment).unwrap();
     let vsm_create_info = vk::ShaderModuleCreateInfo{
         //VERY IMPORTANT: the codesize is measured in BYTES.
         //however, the pointer to the code should be a *const u32 - a raw pointer to a 32bit unsigned integer
-        code_size: vertex_shader_code.len(), //luckily, CompilationArtifact's .len() function returns the size in bytes
-        p_code: vertex_shader_code.as_binary().as_ptr(),
+        code_size: vertex_shader_code.len() * 4, //our code comes as &[u32], so multiply by 4
+        p_code: vertex_shader_code.as_ptr(),
         ..Default::default()};
     let fsm_create_info = vk::ShaderModuleCreateInfo{
-        code_size: fragment_shader_code.len(),
-        p_code: fragment_shader_code.as_binary().as_ptr(),
+        code_size: fragment_shader_code.len() * 4,
+        p_code: fragment_shader_code.as_ptr(),
         ..Default::default()};
     let vertex_shader_module = device.create_shader_module(&vsm_create_info,None).unwrap();
     let fragment_shader_module = device.create_shader_module(&fsm_create_info,None).unwrap();
@@ -96,14 +149,16 @@ pub(crate) unsafe fn create_graphics_pipeline(device: &Device, extent: vk::Exten
         stage: vk::ShaderStageFlags::VERTEX,
         module: vertex_shader_module,
         p_name: c"main".as_ptr(),
-        //p_specialization_info: ,  for setting shader constants at runtime
+        //for setting shader constants at runtime.
         //if functionality changes depending on some const bool, setting the value for this at runtime instead of
         //  passing it as a push constant or uniform, allows some really good compiler optimizations
+        p_specialization_info: &specialization_info,
         ..Default::default()};
     let fss_create_info = vk::PipelineShaderStageCreateInfo {
         stage: vk::ShaderStageFlags::FRAGMENT,
         module: fragment_shader_module,
         p_name: c"main".as_ptr(),
+        p_specialization_info: &specialization_info,
         ..Default::default()};
     let stages = vec![vss_create_info,fss_create_info];
 
@@ -145,8 +200,8 @@ pub(crate) unsafe fn create_graphics_pipeline(device: &Device, extent: vk::Exten
         ..Default::default()};
 
     let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo {
-        //index buffer info, effectively. nothing special needed for a singular tris.
-        topology: vk::PrimitiveTopology::TRIANGLE_STRIP,
+        //index buffer info, effectively
+        topology,
         primitive_restart_enable: vk::FALSE,
         ..Default::default()};
 
@@ -183,7 +238,8 @@ pub(crate) unsafe fn create_graphics_pipeline(device: &Device, extent: vk::Exten
         line_width: 1.0,
         ..Default::default()};
     let multisample_info = vk::PipelineMultisampleStateCreateInfo {
-        rasterization_samples: vk::SampleCountFlags::TYPE_1,
+        //has to match the attachment's
+        rasterization_samples: samples,
         sample_shading_enable: vk::FALSE,
         min_sample_shading: 1.0,
         p_sample_mask: ptr::null(),
@@ -212,80 +268,23 @@ pub(crate) unsafe fn create_graphics_pipeline(device: &Device, extent: vk::Exten
         ..Default::default()};
 
 
-    let pool_size_ubo = vk::DescriptorPoolSize {
-        ty: vk::DescriptorType::UNIFORM_BUFFER,
-        descriptor_count: 3, // todo!
-    };
-    let pool_size_img = vk::DescriptorPoolSize {
-        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-        descriptor_count: 2,
-    };
-
-    let pool_size_mv = vk::DescriptorPoolSize {
-        ty: vk::DescriptorType::STORAGE_BUFFER,
-        descriptor_count: 3,
-    };
-
-    let pool_size: Vec<vk::DescriptorPoolSize> = vec![pool_size_ubo,pool_size_img,pool_size_mv];
-
-    let pool_info = vk::DescriptorPoolCreateInfo {
-        flags: vk::DescriptorPoolCreateFlags::default(),
-        max_sets: MAX_FRAMES_IN_FLIGHT,
-        pool_size_count: pool_size.len() as u32,
-        p_pool_sizes: pool_size.as_ptr(),
-        ..Default::default()};
-    let descriptor_pool = device.create_descriptor_pool(&pool_info, None).unwrap();
-
-
-    let push_constants_range = vk::PushConstantRange {
-            stage_flags: { type Flags = vk::ShaderStageFlags;
-                Flags::FRAGMENT },
-            offset: 0,
-            size: 20 }; // todo!
-
-    let ubo_layout_binding = vk::DescriptorSetLayoutBinding {
-        binding: 0,
-        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-        descriptor_count: 3, // todo!
-        stage_flags: vk::ShaderStageFlags::VERTEX,
-        ..Default::default()};
-
-    let sampler_layout_binding = vk::DescriptorSetLayoutBinding {
-        binding: 1,
-        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-        descriptor_count: 1,
-        stage_flags: vk::ShaderStageFlags::FRAGMENT,
-        p_immutable_samplers: ptr::null(),
-        ..Default::default()};
-
-    let mv_layout_binding = vk::DescriptorSetLayoutBinding {
-        binding: 2,
-        descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
-        descriptor_count: 3,
-        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+    //closer wins, like you'd expect
+    let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo {
+        depth_test_enable: vk::TRUE,
+        depth_write_enable: vk::TRUE,
+        depth_compare_op: vk::CompareOp::LESS,
+        depth_bounds_test_enable: vk::FALSE,
+        stencil_test_enable: vk::FALSE,
+        min_depth_bounds: 0.0,
+        max_depth_bounds: 1.0,
         ..Default::default()};
 
-
-    let bindings: Vec<vk::DescriptorSetLayoutBinding> = vec![ubo_layout_binding, sampler_layout_binding, mv_layout_binding];
-
-    let descriptor_set_layout_info = vk::DescriptorSetLayoutCreateInfo {
-        binding_count: bindings.len() as u32,
-        p_bindings: bindings.as_ptr(),
-        ..Default::default()};
-
-    let descriptor_set_layout = device.create_descriptor_set_layout(&descriptor_set_layout_info, None).unwrap();
-
-
-    let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
-        set_layout_count: 1,
-        p_set_layouts: &descriptor_set_layout,
-        push_constant_range_count: 1,
-        p_push_constant_ranges: ptr::from_ref(&push_constants_range),
+    let mut rendering_info = vk::PipelineRenderingCreateInfo {
+        color_attachment_count: 1,
+        p_color_attachment_formats: &format,
+        depth_attachment_format: depth.unwrap_or(vk::Format::UNDEFINED),
         ..Default::default()};
-    let pipeline_layout = device.create_pipeline_layout(&pipeline_layout_info,None).unwrap(); //error handling lmao
-
-
-    let pipeline_info = vk::GraphicsPipelineCreateInfo {
+    let mut pipeline_info = vk::GraphicsPipelineCreateInfo {
         flags: Default::default(),
         stage_count: 2,
         p_stages: stages.as_ptr(),
@@ -295,15 +294,17 @@ pub(crate) unsafe fn create_graphics_pipeline(device: &Device, extent: vk::Exten
         p_viewport_state: &pipeline_viewport_info,
         p_rasterization_state: &rasterization_info,
         p_multisample_state: &multisample_info,
-        p_depth_stencil_state: ptr::null(),
+        p_depth_stencil_state: if depth.is_some() { &depth_stencil_info } else { ptr::null() },
         p_color_blend_state: &blending_info,
         p_dynamic_state: &dynamic_state_info,
-        layout: pipeline_layout,
+        layout,
         render_pass,
         subpass: 0,
         base_pipeline_handle: vk::Pipeline::null(),
         base_pipeline_index: 0,
         ..Default::default()};
+    if render_pass == vk::RenderPass::null() {
+        pipeline_info = pipeline_info.push_next(&mut rendering_info) }
 
     let pipeline = device.create_graphics_pipelines(vk::PipelineCache::null(),&[pipeline_info],None).unwrap(); //you know the deal
 
@@ -311,23 +312,135 @@ pub(crate) unsafe fn create_graphics_pipeline(device: &Device, extent: vk::Exten
     device.destroy_shader_module(vertex_shader_module,None);
     device.destroy_shader_module(fragment_shader_module,None);
 
-    (*pipeline.first().unwrap(),pipeline_layout,push_constants_range,descriptor_set_layout, descriptor_pool)
+    *pipeline.first().unwrap()
+}
+
+//compute passes only have the one stage, and nothing fixed-function to set up around it
+pub(crate) unsafe fn build_compute_pipeline(device: &Device, layout: PipelineLayout, compute_shader_code: &[u32], interface: &ShaderInterface,
+                                            specialization_section: Option<&Section>, pipeline: &str) -> Pipeline {
+    let (map_entries, data) = specialization(interface, specialization_section, pipeline);
+    let specialization_info = vk::SpecializationInfo {
+        map_entry_count: map_entries.len() as u32,
+        p_map_entries: map_entries.as_ptr(),
+        data_size: data.len(),
+        p_data: data.as_ptr().cast(),
+        ..Default::default()};
+
+    let csm_create_info = vk::ShaderModuleCreateInfo{
+        code_size: compute_shader_code.len() * 4,
+        p_code: compute_shader_code.as_ptr(),
+        ..Default::default()};
+    let compute_shader_module = device.create_shader_module(&csm_create_info,None).unwrap();
+
+    let pipeline_info = vk::ComputePipelineCreateInfo {
+        stage: vk::PipelineShaderStageCreateInfo {
+            stage: vk::ShaderStageFlags::COMPUTE,
+            module: compute_shader_module,
+            p_name: c"main".as_ptr(),
+            p_specialization_info: &specialization_info,
+            ..Default::default()},
+        layout,
+        ..Default::default()};
+    let pipeline = device.create_compute_pipelines(vk::PipelineCache::null(),&[pipeline_info],None).unwrap();
+    device.destroy_shader_module(compute_shader_module,None);
+
+    *pipeline.first().unwrap()
+}
+
+
+//packs the config's values for a pipeline's specialization constants the way VkSpecializationInfo wants them.
+//constants that aren't in the config keep the default from the shader source.
+pub(crate) fn specialization(interface: &ShaderInterface, section: Option<&Section>, pipeline: &str) -> (Vec<vk::SpecializationMapEntry>, Vec<u8>) {
+    let mut map_entries: Vec<vk::SpecializationMapEntry> = Vec::new();
+    let mut data: Vec<u8> = Vec::new();
+    let Some(section) = section else { return (map_entries, data) };
+
+    for (key, value) in &section.entries {
+        let Some(constant) = interface.spec_constants.iter().find(|constant| constant.name == *key) else {
+            warn!("Pipeline \"{pipeline}\" has no specialization constant named {key}, ignoring it");
+            continue };
+        let bytes: Option<Vec<u8>> = match (constant.kind, constant.width) {
+            (ScalarKind::Bool, _) => value.parse::<bool>().ok().map(|value| (value as vk::Bool32).to_ne_bytes().to_vec()),
+            (ScalarKind::Int, 32) => value.parse::<i32>().ok().map(|value| value.to_ne_bytes().to_vec()),
+            (ScalarKind::Int, 64) => value.parse::<i64>().ok().map(|value| value.to_ne_bytes().to_vec()),
+            (ScalarKind::UInt, 32) => value.parse::<u32>().ok().map(|value| value.to_ne_bytes().to_vec()),
+            (ScalarKind::UInt, 64) => value.parse::<u64>().ok().map(|value| value.to_ne_bytes().to_vec()),
+            (ScalarKind::Float, 32) => value.parse::<f32>().ok().map(|value| value.to_ne_bytes().to_vec()),
+            (ScalarKind::Float, 64) => value.parse::<f64>().ok().map(|value| value.to_ne_bytes().to_vec()),
+            _ => None,
+        };
+        let Some(bytes) = bytes else {
+            warn!("Pipeline \"{pipeline}\": {key} = \"{value}\" isn't a valid {}bit {:?}, keeping the shader default", constant.width, constant.kind);
+            continue };
+        map_entries.push(vk::SpecializationMapEntry {
+            constant_id: constant.id,
+            offset: data.len() as u32,
+            size: bytes.len() });
+        data.extend(bytes);
+    }
+    (map_entries, data)
+}
+
+//VK_KHR_dynamic_rendering, or the same thing from core 1.3. the khr entry points only exist when the extension got enabled.
+#[derive(Clone)]
+pub(crate) enum DynamicRendering {
+    Core,
+    Khr(khr::dynamic_rendering::Device),
 }
 
-//render passes tell vulkan what attachments we use as well as any important info regarding those
-pub(crate) unsafe fn create_render_pass(device: &Device, format: vk::Format) -> vk::RenderPass {
+impl DynamicRendering {
+    pub unsafe fn begin(&self, device: &Device, command_buffer: vk::CommandBuffer, rendering_info: &vk::RenderingInfo) {
+        match self {
+            DynamicRendering::Core => device.cmd_begin_rendering(command_buffer, rendering_info),
+            DynamicRendering::Khr(ext) => ext.cmd_begin_rendering(command_buffer, rendering_info),
+        }
+    }
+
+    pub unsafe fn end(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        match self {
+            DynamicRendering::Core => device.cmd_end_rendering(command_buffer),
+            DynamicRendering::Khr(ext) => ext.cmd_end_rendering(command_buffer),
+        }
+    }
+}
+
+//render passes tell vulkan what attachments we use as well as any important info regarding those.
+//layout transitions and synchronization are the render graph's business (see util::graph), so the attachment
+//  comes in and leaves as a color attachment and there are no dependencies to declare.
+//with more than one sample, attachment 0 is the multisampled one and gets resolved into attachment 1.
+//the depth attachment, if any, comes last. same as Pass::attachments.
+pub(crate) unsafe fn create_render_pass(device: &Device, format: vk::Format, samples: vk::SampleCountFlags, depth: Option<vk::Format>) -> vk::RenderPass {
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
     let color_attachment_desc = vk::AttachmentDescription {
         //there's a singular bitflag available here for aliasing attachments to one point in memory
         format,
-        //likely needs to match multisampling sample count
-        samples: vk::SampleCountFlags::TYPE_1,
+        samples,
         load_op: vk::AttachmentLoadOp::CLEAR,
-        store_op: vk::AttachmentStoreOp::STORE,
+        //the samples themselves are of no use to anyone once they're resolved
+        store_op: if multisampled { vk::AttachmentStoreOp::DONT_CARE } else { vk::AttachmentStoreOp::STORE },
         stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
         stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
-        initial_layout: vk::ImageLayout::UNDEFINED, //only really important if we need previous frames information
-        final_layout: vk::ImageLayout::PRESENT_SRC_KHR, //cuz we wanna put the stuff back into the swapchain for presentation
+        initial_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
         ..Default::default()};
+    let resolve_attachment_desc = vk::AttachmentDescription {
+        format,
+        samples: vk::SampleCountFlags::TYPE_1,
+        //every pixel gets overwritten by the resolve
+        load_op: vk::AttachmentLoadOp::DONT_CARE,
+        store_op: vk::AttachmentStoreOp::STORE,
+        ..color_attachment_desc};
+    let mut attachments = vec![color_attachment_desc];
+    if multisampled { attachments.push(resolve_attachment_desc) }
+    let resolve_reference = vk::AttachmentReference { attachment: 1, layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL };
+    //only needed while the pass is running
+    if let Some(format) = depth { attachments.push(vk::AttachmentDescription {
+        format,
+        store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        ..color_attachment_desc}) }
+    let depth_reference = vk::AttachmentReference { attachment: attachments.len() as u32 - 1, layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL };
     //subpasses let us tell vulkan we want to do multiple rendering operations consecutively, where said operations use
     //  the output of previous subpasses as input. defining them as subpasses allows vulkan to make optimizations
     let subpass = vk::SubpassDescription {
@@ -336,42 +449,32 @@ pub(crate) unsafe fn create_render_pass(device: &Device, format: vk::Format) ->
         color_attachment_count: 1,
         p_color_attachments: &vk::AttachmentReference { attachment: 0, layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL },
         //p_input_attachments: , if we read from attachments in shaders
-        //p_resolve_attachments: , stuff for multisampling
-        //p_depth_stencil_attachment: ,
+        p_resolve_attachments: if multisampled { &resolve_reference } else { ptr::null() },
+        p_depth_stencil_attachment: if depth.is_some() { &depth_reference } else { ptr::null() },
         //p_preserve_attachments: , unused attachments that we still want to preserve across subpasses
         ..Default::default()};
 
-    let dependency = vk::SubpassDependency {
-        //dependency_flags: ,
-        src_subpass: vk::SUBPASS_EXTERNAL,
-        dst_subpass: 0,
-        src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-        dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-        src_access_mask: vk::AccessFlags::NONE,
-        dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-        ..Default::default()};
-
     let render_pass_info = vk::RenderPassCreateInfo {
-        attachment_count: 1,
-        p_attachments: &color_attachment_desc,
+        attachment_count: attachments.len() as u32,
+        p_attachments: attachments.as_ptr(),
         subpass_count: 1,
         p_subpasses: &subpass,
-        dependency_count: 1,
-        p_dependencies: &dependency,
         ..Default::default()};
     let render_pass = device.create_render_pass(&render_pass_info,None).unwrap(); //error handlingn't
     render_pass
 }
 
-pub(crate) unsafe fn create_framebuffers(device: &Device, window: &Window, views: &Vec<vk::ImageView>, render_pass: vk::RenderPass) -> Vec<vk::Framebuffer> {
+//with dynamic rendering there's no render pass, and no framebuffers either.
+pub(crate) unsafe fn create_framebuffers(device: &Device, extent: vk::Extent2D, views: &Vec<vk::ImageView>, render_pass: vk::RenderPass) -> Vec<vk::Framebuffer> {
+    if render_pass == vk::RenderPass::null() { return Vec::new() }
     let mut returnee: Vec<vk::Framebuffer> = Vec::with_capacity(views.len());
     for view in views {
         let framebuffer_info = vk::FramebufferCreateInfo {
             render_pass,
             attachment_count: 1,
             p_attachments: ptr::from_ref(&view),
-            width: window.inner_size().width,
-            height: window.inner_size().height,
+            width: extent.width,
+            height: extent.height,
             layers: 1,
             ..Default::default()
         };
@@ -380,62 +483,159 @@ pub(crate) unsafe fn create_framebuffers(device: &Device, window: &Window, views
     returnee
 }
 
-pub(crate) unsafe fn record_into_buffer(device: &Device, window: &Window, pipeline: vk::Pipeline,
-                                        render_pass: vk::RenderPass, framebuffer: vk::Framebuffer, extent: vk::Extent2D,
-                                        command_buffer: vk::CommandBuffer, image_index: usize, vertex_buffer: vk::Buffer, pipeline_layout: vk::PipelineLayout,
-                                        push_constant_range: vk::PushConstantRange, screen_cast: vk::Image, descriptor_sets: Vec<vk::DescriptorSet>, id: i32) {
+//every pass of the chain, in order. all but the last render into their own images, the last one into the swapchain image
+//  (through `framebuffer` on the render pass path), or into the feedback pair which then gets copied over.
+//the render graph puts the barriers in between. `scale` times the frame, see RenderScale::adapt.
+pub(crate) unsafe fn record_into_buffer(device: &Device, window: &Window, chain: &mut Chain, framebuffer: vk::Framebuffer, extent: vk::Extent2D,
+                                        command_buffer: vk::CommandBuffer, image_index: usize, vertex_buffer: vk::Buffer,
+                                        swapchain_image: vk::Image, swapchain_view: vk::ImageView, uploader: &mut Uploader,
+                                        scale: &mut RenderScale, id: i32) {
     let begin_info = vk::CommandBufferBeginInfo {
         //flags: vk::CommandBufferUsageFlags,
         p_inheritance_info: ptr::null(),
         ..Default::default()};
     device.begin_command_buffer(command_buffer, &begin_info).unwrap();
+    uploader.record_images(device, command_buffer);
+    scale.begin(device, command_buffer, image_index);
+
+    let data = PushConstants {
+        rand: glm::vec2(rand::random(), rand::random()),
+        capture: chain.capture().map_or(glm::vec2(0.0, 0.0), |capture| glm::vec2(capture.extent.width as f32, capture.extent.height as f32)),
+        aspect: (window.inner_size().width as f32)/(window.inner_size().height as f32),
+        t: T_ZERO.elapsed().as_secs_f32(),
+        id };
+    let data = slice::from_raw_parts(ptr::from_ref(&data).cast::<u8>(), size_of_val(&data));
+
+    chain.graph.set_image(chain.swapchain, image_index, swapchain_image, swapchain_view);
+    chain.begin(device, image_index);
+
+    for (idx, pass) in chain.passes.iter().enumerate() {
+        chain.graph.barriers(device, command_buffer, image_index, idx);
+        //only the part of the block the shaders actually declare
+        let range = pass.push_constant_range;
+        //the last pass fills the window, the others might render smaller
+        let extent = chain.extent(idx, extent);
+
+        let PassKind::Graphics { .. } = pass.desc.kind else {
+            //a compute shader that doesn't build has nothing to dispatch
+            if pass.pipeline == vk::Pipeline::null() { continue }
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pass.pipeline);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, pass.layout, 0, &[pass.descriptor_sets[image_index]], &[]);
+            if range.size > 0 {
+                device.cmd_push_constants(command_buffer, pass.layout, range.stage_flags, range.offset,
+                                          &data[range.offset as usize..(range.offset + range.size) as usize]);
+            }
+            let [x, y, z] = pass.groups(extent);
+            device.cmd_dispatch(command_buffer, x, y, z);
+            continue
+        };
+        let output = pass.output.unwrap();
+
+        //IMPORTANT: the color to which the screen is cleared
+        let clear_value = vk::ClearValue {color: vk::ClearColorValue {float32:[0.0,0.0,0.0,0.0f32]}};
+        let depth_clear_value = vk::ClearValue {depth_stencil: vk::ClearDepthStencilValue {depth: 1.0, stencil: 0}};
+        match &chain.rendering {
+            Some(rendering) => {
+                //the graph already got the image into COLOR_ATTACHMENT_OPTIMAL
+                let color_attachment = match pass.msaa {
+                    None => vk::RenderingAttachmentInfo {
+                        image_view: chain.graph.view(output, image_index),
+                        image_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                        load_op: vk::AttachmentLoadOp::CLEAR,
+                        store_op: vk::AttachmentStoreOp::STORE,
+                        clear_value,
+                        ..Default::default()},
+                    //the samples get averaged into the output once the pass is done, and aren't needed after that
+                    Some(msaa) => vk::RenderingAttachmentInfo {
+                        image_view: chain.graph.view(msaa, image_index),
+                        image_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                        resolve_mode: vk::ResolveModeFlags::AVERAGE,
+                        resolve_image_view: chain.graph.view(output, image_index),
+                        resolve_image_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                        load_op: vk::AttachmentLoadOp::CLEAR,
+                        store_op: vk::AttachmentStoreOp::DONT_CARE,
+                        clear_value,
+                        ..Default::default()},
+                };
+                let depth_attachment = pass.depth.map(|(depth, _)| vk::RenderingAttachmentInfo {
+                    image_view: chain.graph.view(depth, image_index),
+                    image_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    load_op: vk::AttachmentLoadOp::CLEAR,
+                    store_op: vk::AttachmentStoreOp::DONT_CARE,
+                    clear_value: depth_clear_value,
+                    ..Default::default()});
+                let rendering_info = vk::RenderingInfo {
+                    render_area: vk::Rect2D::from(extent),
+                    layer_count: 1,
+                    color_attachment_count: 1,
+                    p_color_attachments: &color_attachment,
+                    p_depth_attachment: depth_attachment.as_ref().map_or(ptr::null(), ptr::from_ref),
+                    ..Default::default()};
+                rendering.begin(device, command_buffer, &rendering_info);
+            }
+            None => {
+                //one per attachment, the depth buffer's last. the resolve target doesn't get cleared, whatever it has there is ignored.
+                let attachments = pass.attachments(&chain.graph, image_index);
+                let mut clear_values = vec![clear_value; attachments.len()];
+                if pass.depth.is_some() { *clear_values.last_mut().unwrap() = depth_clear_value }
+                let render_pass_info = vk::RenderPassBeginInfo {
+                    render_pass: pass.render_pass,
+                    framebuffer: pass.framebuffers.iter().find(|(views, _)| *views == attachments)
+                        .map_or(framebuffer, |(_, framebuffer)| *framebuffer),
+                    render_area: vk::Rect2D::from(extent),
+                    clear_value_count: clear_values.len() as u32,
+                    p_clear_values: clear_values.as_ptr(),
+                    ..Default::default()};
+                device.cmd_begin_render_pass(command_buffer,&render_pass_info,vk::SubpassContents::INLINE);
+            }
+        }
 
-    //IMPORTANT: the color to which the screen is cleared
-    let clear_value = vk::ClearValue {color: vk::ClearColorValue {float32:[0.0,0.0,0.0,0.0f32]}};
-    let render_pass_info = vk::RenderPassBeginInfo {
-        render_pass, framebuffer,
-        render_area: vk::Rect2D::from(extent),
-        clear_value_count: 1,
-        p_clear_values: &clear_value,
-        ..Default::default()};
-
-    device.cmd_begin_render_pass(command_buffer,&render_pass_info,vk::SubpassContents::INLINE);
-
-    device.cmd_bind_pipeline(command_buffer,vk::PipelineBindPoint::GRAPHICS,pipeline);
-
-    device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer], &[0]);
-
-    //because we set the viewport and scissor as dynamic state previously, we gotta set them again.
-    let viewport = vk::Viewport {
-        x: 0.0, y: 0.0,
-        width: window.inner_size().width as f32,
-        height: window.inner_size().height as f32,
-        min_depth: 0.0,
-        max_depth: 1.0};
-    device.cmd_set_viewport(command_buffer,0,&[viewport]);
-
-    let scissor = vk::Rect2D::from(extent);
-    device.cmd_set_scissor(command_buffer,0,&[scissor]);
+        device.cmd_bind_pipeline(command_buffer,vk::PipelineBindPoint::GRAPHICS,pass.pipeline);
 
-    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline_layout, 0, &[descriptor_sets[image_index]], &[]);
+        //because we set the viewport and scissor as dynamic state previously, we gotta set them again.
+        let viewport = vk::Viewport {
+            x: 0.0, y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0};
+        device.cmd_set_viewport(command_buffer,0,&[viewport]);
 
+        let scissor = vk::Rect2D::from(extent);
+        device.cmd_set_scissor(command_buffer,0,&[scissor]);
 
-    let mut data: (f32,f32,f32,f32,i32) = (
-        0.0, 0.0,
-        (window.inner_size().width as f32)/(window.inner_size().height as f32),
-        0.0,
-        id);
-    [data.0,data.1].fill_with(rand::random);
-    data.3 = T_ZERO.elapsed().as_secs_f32();
+        device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, pass.layout, 0, &[pass.descriptor_sets[image_index]], &[]);
 
-    // todo!
-    device.cmd_push_constants(command_buffer, pipeline_layout,
-                              push_constant_range.stage_flags,
-                              push_constant_range.offset,
-                              slice::from_raw_parts(ptr::from_ref(&data) as *const _, size_of_val(&data)));
+        if range.size > 0 {
+            device.cmd_push_constants(command_buffer, pass.layout, range.stage_flags, range.offset,
+                                      &data[range.offset as usize..(range.offset + range.size) as usize]);
+        }
 
-    device.cmd_draw(command_buffer,VERTICES.len() as u32,1,0,0);
-    device.cmd_end_render_pass(command_buffer);
+        match &pass.mesh {
+            Some(mesh) if pass.draws_mesh => mesh.draw(device, command_buffer),
+            _ => {
+                device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer], &[0]);
+                device.cmd_draw(command_buffer,VERTICES.len() as u32,1,0,0);
+            }
+        }
+        match &chain.rendering {
+            Some(rendering) => rendering.end(device, command_buffer),
+            None => device.cmd_end_render_pass(command_buffer),
+        }
+    }
+    if let Some((current, _)) = chain.feedback {
+        chain.graph.barriers(device, command_buffer, image_index, chain.passes.len());
+        let layers = vk::ImageSubresourceLayers { aspect_mask: vk::ImageAspectFlags::COLOR, mip_level: 0, base_array_layer: 0, layer_count: 1 };
+        let region = vk::ImageCopy {
+            src_subresource: layers,
+            dst_subresource: layers,
+            extent: vk::Extent3D { width: extent.width, height: extent.height, depth: 1 },
+            ..Default::default()};
+        device.cmd_copy_image(command_buffer, chain.graph.image(current, image_index), vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                              swapchain_image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[region]);
+    }
+    chain.graph.finish(device, command_buffer, image_index);
+    scale.end(device, command_buffer, image_index);
     //because there aren't any errors thrown during command recording, everything that can go wrong will go wrong here.
     device.end_command_buffer(command_buffer).unwrap();
 }
@@ -459,14 +659,37 @@ pub(crate) unsafe fn recreate_swapchain(
     let (swapchain,format,extent,sync) = PerSwapchain::create_swapchain(&per_window.window, per_window.surface, device, physical_device, ext_surface, ext_swapchain).unwrap(); // todo!
     let images = ext_swapchain.get_swapchain_images(swapchain).unwrap();    // todo!
     let views = create_views(device,&images,format);
-    let framebuffers: Vec<vk::Framebuffer> = create_framebuffers(device,&per_window.window,&views,per_window.render_pass);
+
+    //the window's render pass and the chain's last pass are made for the format, a new one needs new ones
+    let reformatted = format != per_window.swapchain.format;
+    let old_render_pass = per_window.render_pass;
+    if reformatted && old_render_pass != vk::RenderPass::null() {
+        per_window.render_pass = create_render_pass(device, format, vk::SampleCountFlags::TYPE_1, None) }
+    let framebuffers: Vec<vk::Framebuffer> = create_framebuffers(device,extent,&views,per_window.render_pass);
 
     per_window.swapchain = PerSwapchain {
         handle: swapchain, format, extent, images, views, framebuffers, sync };
+    match reformatted {
+        true => {
+            info!("The surface's format is {format:?} now, rebuilding the render chain");
+            per_window.chain.set_present(device, extent, per_window.render_pass, format, per_window.swapchain.views.clone());
+            if old_render_pass != vk::RenderPass::null() { device.destroy_render_pass(old_render_pass, None) } }
+        false => per_window.chain.resize(device, extent, per_window.swapchain.views.clone()),
+    }
 }
 
 
 
+//what a view or barrier of an image in that format covers
+pub(crate) fn aspect(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
+        vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT =>
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
 pub(crate) unsafe fn create_views(device: &Device, images: &Vec<vk::Image>, format: vk::Format) -> Vec<vk::ImageView> {
     let mut views: Vec<vk::ImageView> = Vec::with_capacity(images.len());
     for image in images {
@@ -481,7 +704,7 @@ pub(crate) unsafe fn create_views(device: &Device, images: &Vec<vk::Image>, form
                 a: vk::ComponentSwizzle::IDENTITY},
             subresource_range: vk::ImageSubresourceRange {
                 //lot of interesting stuff regarding image aspect flags/masks - but nothing important for now.
-                aspect_mask: vk::ImageAspectFlags::COLOR,
+                aspect_mask: aspect(format),
                 base_mip_level: 0,
                 level_count: 1,
                 base_array_layer: 0,
@@ -490,4 +713,57 @@ pub(crate) unsafe fn create_views(device: &Device, images: &Vec<vk::Image>, form
         views.push(device.create_image_view(&view_create_info, None).unwrap()); // todo!    ERROR HANDLING
         }
     views
-}
\ No newline at end of file
+}
+
+
+//records whatever `record` does into a command buffer of its own and waits for it to be done.
+//  only meant for setup and rebuilds, where the device is idle anyway. waiting for the queue covers the visibility
+//  of everything in there to whatever gets submitted after it.
+pub(crate) unsafe fn submit_once(device: &Device, queue: vk::Queue, command_pool: vk::CommandPool, record: impl FnOnce(vk::CommandBuffer)) {
+    let cmd_alloc_info = vk::CommandBufferAllocateInfo {
+        command_pool,
+        level: vk::CommandBufferLevel::PRIMARY,
+        command_buffer_count: 1,
+        ..Default::default()};
+    let command_buffer = device.allocate_command_buffers(&cmd_alloc_info).unwrap()[0];
+    let begin_info = vk::CommandBufferBeginInfo {
+        flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+        ..Default::default()};
+    device.begin_command_buffer(command_buffer, &begin_info).unwrap();
+    record(command_buffer);
+    device.end_command_buffer(command_buffer).unwrap();
+    let submit_info = vk::SubmitInfo {
+        command_buffer_count: 1,
+        p_command_buffers: &command_buffer,
+        ..Default::default()};
+    device.queue_submit(queue, &[submit_info], vk::Fence::null()).unwrap();
+    device.queue_wait_idle(queue).unwrap();
+    device.free_command_buffers(command_pool, &[command_buffer]);
+}
+
+//the first memory type that fits the requirements and has all of `flags`
+pub(crate) unsafe fn memory_type(physical_device: vk::PhysicalDevice, requirements: vk::MemoryRequirements, flags: vk::MemoryPropertyFlags) -> u32 {
+    let mem_properties = INSTANCE.get_physical_device_memory_properties(physical_device);
+    mem_properties.memory_types[..mem_properties.memory_type_count as _]
+        .iter().enumerate().find(|(idx, mem_type)| {
+        (1u32 << idx) & requirements.memory_type_bits != 0
+        && mem_type.property_flags & flags == flags
+    }).map(|(idx, _)| idx as u32).expect("no matching mem type found")
+}
+
+pub(crate) unsafe fn create_buffer(device: &Device, physical_device: vk::PhysicalDevice, size: u64, usage: vk::BufferUsageFlags,
+                                   flags: vk::MemoryPropertyFlags) -> (vk::Buffer, vk::DeviceMemory) {
+    let buffer_info = vk::BufferCreateInfo {
+        size, usage,
+        sharing_mode: vk::SharingMode::EXCLUSIVE,
+        ..Default::default()};
+    let buffer = device.create_buffer(&buffer_info, None).unwrap();
+    let requirements = device.get_buffer_memory_requirements(buffer);
+    let allocate_info = vk::MemoryAllocateInfo {
+        allocation_size: requirements.size,
+        memory_type_index: memory_type(physical_device, requirements, flags),
+        ..Default::default()};
+    let memory = device.allocate_memory(&allocate_info, None).unwrap();
+    device.bind_buffer_memory(buffer, memory, 0).unwrap();
+    (buffer, memory)
+}
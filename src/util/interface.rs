@@ -0,0 +1,147 @@
+use ash::vk;
+use crate::util::reflect::RustBinding;
+
+//one definition for both sides of the shader interface.
+//`shader_interface!` spits out the #[repr(C)] structs for rust, a GLSL include (served as <ember/interface.glsl>)
+//  and compile-time checks that every field sits exactly where std140/std430 says it should.
+//  if a field gets added, removed or reordered, both sides change together. if a type doesn't fit the layout, it won't compile.
+
+pub(crate) trait GlslType {
+    const GLSL: &'static str;
+    const ALIGN_STD140: usize;
+    const SIZE_STD140: usize;
+    const ALIGN_STD430: usize;
+    const SIZE_STD430: usize;
+
+    //arrays need to put their dimensions after the name, hence the function instead of using GLSL directly
+    fn declare(name: &str) -> String { format!("{} {name}", Self::GLSL) }
+}
+
+pub(crate) const fn align_up(value: usize, align: usize) -> usize {
+    value.div_ceil(align) * align
+}
+
+macro_rules! glsl_type {
+    ($($ty:ty => $glsl:literal, $align:literal, $size:literal;)*) => {$(
+        impl GlslType for $ty {
+            const GLSL: &'static str = $glsl;
+            const ALIGN_STD140: usize = $align;
+            const SIZE_STD140: usize = $size;
+            const ALIGN_STD430: usize = $align;
+            const SIZE_STD430: usize = $size;
+        }
+    )*};
+}
+glsl_type! {
+    f32        => "float",    4,  4;
+    i32        => "int",      4,  4;
+    u32        => "uint",     4,  4;
+    u64        => "uint64_t", 8,  8;
+    glm::Vec2  => "vec2",     8,  8;
+    glm::Vec3  => "vec3",     16, 12;
+    glm::Vec4  => "vec4",     16, 16;
+    glm::IVec2 => "ivec2",    8,  8;
+    glm::IVec4 => "ivec4",    16, 16;
+    glm::UVec2 => "uvec2",    8,  8;
+    glm::UVec4 => "uvec4",    16, 16;
+    glm::Mat4  => "mat4",     16, 64;
+}
+
+//std140 rounds array strides (and alignment) up to a vec4, std430 doesn't.
+impl<T: GlslType, const N: usize> GlslType for [T; N] {
+    const GLSL: &'static str = T::GLSL;
+    const ALIGN_STD140: usize = align_up(T::ALIGN_STD140, 16);
+    const SIZE_STD140: usize = N * align_up(T::SIZE_STD140, Self::ALIGN_STD140);
+    const ALIGN_STD430: usize = T::ALIGN_STD430;
+    const SIZE_STD430: usize = N * align_up(T::SIZE_STD430, T::ALIGN_STD430);
+
+    fn declare(name: &str) -> String { T::declare(&format!("{name}[{N}]")) }
+}
+
+pub(crate) trait ShaderBlock {
+    fn glsl() -> String;
+}
+
+macro_rules! glsl_layout {
+    (std140, $ty:ty) => { (<$ty as $crate::util::interface::GlslType>::ALIGN_STD140, <$ty as $crate::util::interface::GlslType>::SIZE_STD140) };
+    (std430, $ty:ty) => { (<$ty as $crate::util::interface::GlslType>::ALIGN_STD430, <$ty as $crate::util::interface::GlslType>::SIZE_STD430) };
+}
+
+macro_rules! glsl_block_header {
+    (uniform, $layout:ident, $set:literal, $binding:literal) => {
+        format!("layout({}, set = {}, binding = {}) uniform", stringify!($layout), $set, $binding) };
+    (buffer, $layout:ident, $set:literal, $binding:literal) => {
+        format!("layout({}, set = {}, binding = {}) buffer", stringify!($layout), $set, $binding) };
+    (push_constant, $layout:ident) => {
+        format!("layout(push_constant, {}) uniform", stringify!($layout)) };
+}
+
+macro_rules! rust_binding {
+    (uniform, $name:ident, $set:literal, $binding:literal) => {
+        $crate::util::interface::binding::<$name>(stringify!($name), $set, $binding, ash::vk::DescriptorType::UNIFORM_BUFFER) };
+    (buffer, $name:ident, $set:literal, $binding:literal) => {
+        $crate::util::interface::binding::<$name>(stringify!($name), $set, $binding, ash::vk::DescriptorType::STORAGE_BUFFER) };
+}
+
+pub(crate) use {glsl_layout, glsl_block_header, rust_binding};
+
+pub(crate) const fn binding<T>(name: &'static str, set: u32, binding: u32, descriptor_type: vk::DescriptorType) -> RustBinding {
+    RustBinding { set, binding, descriptor_type, count: 1, name, size: Some(size_of::<T>() as u64), tail_stride: None }
+}
+
+//  shader_interface! {
+//      uniform(std140, set = 0, binding = 0) ubo: pub struct UniformBufferObject { pub model: glm::Mat4 }
+//      push_constant(std430) pc: pub struct PushConstants { pub t: f32 }
+//  }
+//uniform and buffer blocks also get a `BINDING` const for the reflection check (see PassDesc::bindings).
+macro_rules! shader_interface {
+    ($(
+        $kind:ident ( $layout:ident $(, set = $set:literal, binding = $binding:literal)? ) $instance:ident :
+        $vis:vis struct $name:ident { $( $field_vis:vis $field:ident : $ty:ty ),* $(,)? }
+    )*) => {
+        $(
+            #[repr(C)]
+            $vis struct $name { $( $field_vis $field: $ty ),* }
+
+            impl $crate::util::interface::ShaderBlock for $name {
+                fn glsl() -> String {
+                    let mut glsl = format!("{} {} {{\n", $crate::util::interface::glsl_block_header!($kind, $layout $(, $set, $binding)?), stringify!($name));
+                    $( glsl.push_str(&format!("    {};\n", <$ty as $crate::util::interface::GlslType>::declare(stringify!($field)))); )*
+                    glsl.push_str(&format!("}} {};\n", stringify!($instance)));
+                    glsl
+                }
+            }
+
+            $(
+            impl $name {
+                #[allow(unused)]
+                pub(crate) const BINDING: $crate::util::reflect::RustBinding = $crate::util::interface::rust_binding!($kind, $name, $set, $binding);
+            }
+            )?
+
+            //walk the fields in order, placing each one the way GLSL would, and compare against where rustc put it
+            const _: () = {
+                let mut offset = 0;
+                $(
+                    let (align, size) = $crate::util::interface::glsl_layout!($layout, $ty);
+                    offset = $crate::util::interface::align_up(offset, align);
+                    assert!(std::mem::offset_of!($name, $field) == offset,
+                        concat!(stringify!($name), "::", stringify!($field), " isn't at the offset ", stringify!($layout), " expects, add or remove padding"));
+                    assert!(size_of::<$ty>() == size,
+                        concat!(stringify!($name), "::", stringify!($field), " doesn't have the same size in Rust and ", stringify!($layout)));
+                    offset += size;
+                )*
+                assert!(size_of::<$name>() == $crate::util::interface::align_up(offset, align_of::<$name>()),
+                    concat!(stringify!($name), " has trailing fields GLSL doesn't know about"));
+            };
+        )*
+
+        //the generated <ember/interface.glsl>
+        pub(crate) fn glsl_interface() -> String {
+            let mut glsl = String::from("#extension GL_EXT_shader_explicit_arithmetic_types_int64 : require\n\n");
+            $( glsl.push_str(&<$name as $crate::util::interface::ShaderBlock>::glsl()); glsl.push('\n'); )*
+            glsl
+        }
+    };
+}
+pub(crate) use shader_interface;
@@ -0,0 +1,133 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use ash::{vk, Device};
+use log::{debug, warn};
+use crate::util::helpers::Vertex;
+use crate::util::upload::upload_buffer;
+
+//meshes for passes to draw instead of the fullscreen quad, see `mesh` in [pass.*].
+//  OBJ goes through tobj, glTF (.gltf and .glb) through gltf. whatever's in there ends up as one indexed triangle list
+//  with positions and the first set of texture coordinates. materials, normals and everything else get dropped.
+//  in device-local memory, uploaded once through a staging buffer.
+
+pub(crate) struct Mesh {
+    vertex_buffer: vk::Buffer,
+    vertex_memory: vk::DeviceMemory,
+    index_buffer: vk::Buffer,
+    index_memory: vk::DeviceMemory,
+    pub index_count: u32,
+}
+
+#[derive(Debug)]
+pub(crate) struct MeshError(String);
+impl Display for MeshError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Mesh loading failed: {}", self.0)
+    }
+}
+impl Error for MeshError {}
+
+
+pub(crate) fn load(path: &str) -> Result<(Vec<Vertex>, Vec<u32>), MeshError> {
+    let extension = Path::new(path).extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+    let (vertices, indices) = match extension.as_str() {
+        "obj" => load_obj(path)?,
+        "gltf" | "glb" => load_gltf(path)?,
+        _ => return Err(MeshError(format!("{path}: unknown format, expected .obj, .gltf or .glb"))),
+    };
+    if indices.is_empty() { return Err(MeshError(format!("{path}: no triangles in there"))) }
+    debug!("Mesh {path}: {} vertices, {} triangles", vertices.len(), indices.len() / 3);
+    Ok((vertices, indices))
+}
+
+fn load_obj(path: &str) -> Result<(Vec<Vertex>, Vec<u32>), MeshError> {
+    //single_index, so positions and texture coordinates share the index buffer
+    let (models, _) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS).map_err(|e| MeshError(format!("{path}: {e}")))?;
+    let mut vertices: Vec<Vertex> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    for model in models {
+        let mesh = model.mesh;
+        let base = vertices.len() as u32;
+        vertices.extend(mesh.positions.chunks_exact(3).enumerate().map(|(idx, pos)| Vertex {
+            pos: [pos[0], pos[1], pos[2]],
+            //OBJ has v pointing up, we have it pointing down
+            tex: mesh.texcoords.get(idx * 2..idx * 2 + 2).map_or([0.0; 2], |tex| [tex[0], 1.0 - tex[1]]) }));
+        indices.extend(mesh.indices.iter().map(|idx| base + idx));
+    }
+    Ok((vertices, indices))
+}
+
+//every mesh of the default scene (or the first one), with the node transforms applied
+fn load_gltf(path: &str) -> Result<(Vec<Vertex>, Vec<u32>), MeshError> {
+    let (document, buffers, _) = gltf::import(path).map_err(|e| MeshError(format!("{path}: {e}")))?;
+    let scene = document.default_scene().or(document.scenes().next()).ok_or_else(|| MeshError(format!("{path}: no scene")))?;
+    let mut vertices: Vec<Vertex> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    let mut nodes: Vec<(gltf::Node, [[f32; 4]; 4])> = scene.nodes().map(|node| (node, IDENTITY)).collect();
+    while let Some((node, parent)) = nodes.pop() {
+        let transform = multiply(&parent, &node.transform().matrix());
+        nodes.extend(node.children().map(|child| (child, transform)));
+        let Some(mesh) = node.mesh() else { continue };
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                warn!("Mesh {path}: skipping a primitive of {:?}, only triangles are supported", primitive.mode());
+                continue }
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+            let Some(positions) = reader.read_positions() else { continue };
+            let base = vertices.len() as u32;
+            let mut tex_coords = reader.read_tex_coords(0).map(|tex_coords| tex_coords.into_f32());
+            for [x, y, z] in positions {
+                let pos = [0, 1, 2].map(|row| transform[0][row] * x + transform[1][row] * y + transform[2][row] * z + transform[3][row]);
+                let tex = tex_coords.as_mut().and_then(|tex_coords| tex_coords.next()).unwrap_or([0.0; 2]);
+                vertices.push(Vertex { pos, tex });
+            }
+            match reader.read_indices() {
+                Some(read) => indices.extend(read.into_u32().map(|idx| base + idx)),
+                //non-indexed, every three vertices make a triangle
+                None => indices.extend(base..vertices.len() as u32),
+            }
+        }
+    }
+    Ok((vertices, indices))
+}
+
+const IDENTITY: [[f32; 4]; 4] = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+
+//column major, like glTF has them
+fn multiply(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut product = [[0.0; 4]; 4];
+    for column in 0..4 {
+        for row in 0..4 {
+            product[column][row] = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+    product
+}
+
+
+impl Mesh {
+    //blocks until the upload is done, see upload_buffer
+    pub unsafe fn upload(device: &Device, physical_device: vk::PhysicalDevice, queue: vk::Queue, command_pool: vk::CommandPool,
+                         vertices: &[Vertex], indices: &[u32]) -> Mesh {
+        let vertex_bytes = std::slice::from_raw_parts(vertices.as_ptr().cast::<u8>(), size_of_val(vertices));
+        let index_bytes = std::slice::from_raw_parts(indices.as_ptr().cast::<u8>(), size_of_val(indices));
+        let (vertex_buffer, vertex_memory) = upload_buffer(device, physical_device, queue, command_pool, vk::BufferUsageFlags::VERTEX_BUFFER, vertex_bytes);
+        let (index_buffer, index_memory) = upload_buffer(device, physical_device, queue, command_pool, vk::BufferUsageFlags::INDEX_BUFFER, index_bytes);
+        Mesh { vertex_buffer, vertex_memory, index_buffer, index_memory, index_count: indices.len() as u32 }
+    }
+
+    pub unsafe fn draw(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer], &[0]);
+        device.cmd_bind_index_buffer(command_buffer, self.index_buffer, 0, vk::IndexType::UINT32);
+        device.cmd_draw_indexed(command_buffer, self.index_count, 1, 0, 0, 0);
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        device.destroy_buffer(self.vertex_buffer, None);
+        device.free_memory(self.vertex_memory, None);
+        device.destroy_buffer(self.index_buffer, None);
+        device.free_memory(self.index_memory, None);
+    }
+}
@@ -2,4 +2,15 @@ pub(crate) mod per_window;
 pub(crate) mod logging;
 pub(crate) mod helpers;
 pub(crate) mod swapchain;
-mod extensions;
\ No newline at end of file
+mod extensions;
+pub(crate) mod reflect;
+pub(crate) mod interface;
+pub(crate) mod config;
+pub(crate) mod shaders;
+pub(crate) mod chain;
+pub(crate) mod graph;
+pub(crate) mod mesh;
+pub(crate) mod texture;
+pub(crate) mod upload;
+pub(crate) mod scale;
+pub(crate) mod capture;
\ No newline at end of file
@@ -1,16 +1,18 @@
-use core::ffi;
 use std::error::Error;
-use std::ffi::c_void;
-use std::ptr;
+use std::slice;
 use ash::{vk, Device};
-use ash::util::Align;
 use log::error;
 use winit::event_loop::ActiveEventLoop;
 use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle, RawDisplayHandle, RawWindowHandle};
 use winit::window::{Window, WindowAttributes, WindowId};
-use crate::{platform, ExtensionHolder, MVBufferObject, OSSurface, SCHolder, UniformBufferObject, FAR, FOV, INSTANCE, MAX_FRAMES_IN_FLIGHT, NEAR};
-use crate::util::helpers::{create_framebuffers, create_graphics_pipeline, create_render_pass, create_views, Vertex, VERTICES};
+use crate::{platform, ExtensionHolder, MVBufferObject, OSSurface, UniformBufferObject, FAR, FOV, MAX_FRAMES_IN_FLIGHT, NEAR};
+use crate::util::helpers::{create_framebuffers, create_render_pass, create_views, VERTICES};
 use crate::util::swapchain::PerSwapchain;
+use crate::util::config::Config;
+use crate::util::chain::{describe, Chain, ChainInputs};
+use crate::util::upload::{upload_buffer, StagedBuffer, TransferQueue, Uploader};
+use crate::util::scale::RenderScale;
+use crate::util::capture::{Capture, MAX_SOURCES};
 
 
 type HWND = isize;
@@ -19,24 +21,21 @@ pub struct PerWindow {
     pub surface: vk::SurfaceKHR,
     pub swapchain: PerSwapchain,
 
+    //the one presenting into the swapchain, used by the chain's last pass. null with dynamic rendering.
     pub render_pass: vk::RenderPass,
-    pub pipeline: vk::Pipeline,
-    pub layout: vk::PipelineLayout,
+    pub chain: Chain,
     pub command_buffers: Vec<vk::CommandBuffer>,
     pub vertex_buffer: vk::Buffer,
     pub vertex_buffer_mem: vk::DeviceMemory,
-    pub push_constant_range: vk::PushConstantRange,
-    pub descriptor_set_layout: vk::DescriptorSetLayout,
-    pub ubufs: Vec<vk::Buffer>,
-    pub ubufs_mem: Vec<vk::DeviceMemory>,
-    pub ubufs_map: Vec<*mut c_void>,
-    pub mv_ubufs: Vec<vk::Buffer>,
-    pub mv_ubufs_mem: Vec<vk::DeviceMemory>,
-    pub mv_ubufs_map: Vec<*mut c_void>,
-    pub descriptor_pool: vk::DescriptorPool,
-    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    pub uploader: Uploader,
+    pub ubo: StagedBuffer,
+    pub mv: StagedBuffer,
+    //what fraction of the window the chain renders at, see util::scale
+    pub scale: RenderScale,
 
     pub id: i32,
+    //which of the screencasts it shows as "capture", see CaptureDesc
+    pub source: usize,
 }
 
 
@@ -47,6 +46,8 @@ pub(crate) struct SYN {
     pub(crate) swapchain: vk::Semaphore,
     pub(crate) presentation: vk::Semaphore,
     pub(crate) in_flight: vk::Fence,
+    //the captures' buffers being written, by source. imported anew every frame, see Capture::acquire
+    pub(crate) capture: [vk::Semaphore; MAX_SOURCES],
 }
 impl SYN {
     pub(crate) unsafe fn new(device: &Device) -> Result<SYN, Box<dyn Error>> {
@@ -57,12 +58,17 @@ impl SYN {
         let swapchain    = device.create_semaphore(&semaphore_info,None)?;
         let presentation = device.create_semaphore(&semaphore_info,None)?;
         let in_flight    = device.create_fence(&fence_info,None)?;
-        Ok(Self {swapchain,presentation,in_flight})
+        let mut capture  = [vk::Semaphore::null(); MAX_SOURCES];
+        for semaphore in &mut capture {
+            *semaphore = device.create_semaphore(&semaphore_info,None)?;
+        }
+        Ok(Self {swapchain,presentation,in_flight,capture})
     }
     pub(crate) unsafe fn destroy(&self, device: &Device) {
         device.destroy_semaphore(self.swapchain,None);
         device.destroy_semaphore(self.presentation,None);
         device.destroy_fence(self.in_flight,None);
+        self.capture.iter().for_each(|semaphore| device.destroy_semaphore(*semaphore,None));
     }
 }
 
@@ -70,7 +76,10 @@ pub struct WindowBuilder<'a> {
     ext: &'a ExtensionHolder,
     device: &'a Device,
     physical_device: vk::PhysicalDevice,
+    queue: vk::Queue,
+    transfer: TransferQueue,
     command_pool: vk::CommandPool,
+    config: &'a Config,
 
     pub attributes: WindowAttributes,
 }
@@ -81,13 +90,17 @@ impl<'a> WindowBuilder<'a> {
         ext: &'a ExtensionHolder,
         device: &'a Device,
         physical_device: vk::PhysicalDevice,
-        command_pool: vk::CommandPool
+        queue: vk::Queue,
+        transfer: TransferQueue,
+        command_pool: vk::CommandPool,
+        config: &'a Config,
     ) -> Self {
         WindowBuilder {
-            ext, device, physical_device, command_pool,
+            ext, device, physical_device, queue, transfer, command_pool, config,
             attributes: WindowAttributes::default()}
     }
-    pub fn build(&self, event_loop: &'a ActiveEventLoop, screencast: Option<&SCHolder>) -> (WindowId, PerWindow) {
+    //`source` is the screencast among `screencasts` it shows, see CaptureDesc
+    pub fn build(&self, event_loop: &'a ActiveEventLoop, screencasts: &[Capture], source: usize) -> (WindowId, PerWindow) {
         let window = event_loop.create_window(self.attributes.clone()).unwrap();
 
         let surface = unsafe {
@@ -145,169 +158,40 @@ impl<'a> WindowBuilder<'a> {
         let images = unsafe { self.ext.swapchain.get_swapchain_images(swapchain).unwrap() };
         let views = unsafe { create_views(self.device,&images,format) };
 
-        let render_pass = unsafe { create_render_pass(self.device,format) };
-        let (pipeline,layout,push_constant_range,descriptor_set_layout,descriptor_pool) = unsafe { create_graphics_pipeline(self.device,extent,render_pass) };
-        let framebuffers: Vec<vk::Framebuffer> = unsafe { create_framebuffers(self.device,&window,&views,render_pass) };
+        let render_pass = match self.ext.dynamic_rendering {
+            Some(_) => vk::RenderPass::null(),
+            None => unsafe { create_render_pass(self.device,format,vk::SampleCountFlags::TYPE_1,None) }};
+        let framebuffers: Vec<vk::Framebuffer> = unsafe { create_framebuffers(self.device,extent,&views,render_pass) };
 
 
 
-        let vertex_buffer_info = vk::BufferCreateInfo {
-            size: (VERTICES.len() * size_of::<Vertex>()) as _,
-            usage: { type Flags = vk::BufferUsageFlags;
-                Flags::VERTEX_BUFFER },
-            sharing_mode: vk::SharingMode::EXCLUSIVE,
-            ..Default::default()};
-
-        let vertex_buffer = unsafe { self.device.create_buffer(&vertex_buffer_info, None).unwrap() };
-
-        let vb_mem_req = unsafe { self.device.get_buffer_memory_requirements(vertex_buffer) };
-
-        let mem_properties = unsafe { INSTANCE.get_physical_device_memory_properties(self.physical_device) };
-
-        let req_flags = { type Flags = vk::MemoryPropertyFlags;
-            Flags::HOST_VISIBLE | Flags::HOST_COHERENT };
-        let mem_idx = mem_properties.memory_types[..mem_properties.memory_type_count as _]
-            .iter().enumerate().find(|(idx,mem_type)|{
-            (1u32 << idx) & vb_mem_req.memory_type_bits != 0
-            && mem_type.property_flags &  req_flags == req_flags
-        }).map(|(idx,mem_type)| idx as _ ).expect("no matching mem type found");
-
-        let vb_allocate_info = vk::MemoryAllocateInfo {
-            allocation_size: vb_mem_req.size,
-            memory_type_index: mem_idx,
-            ..Default::default()};
-
-        let vertex_buffer_mem = unsafe { self.device.allocate_memory( &vb_allocate_info, None).unwrap() };
-
-        let vert_ptr = unsafe { self.device.map_memory(vertex_buffer_mem, 0, vb_mem_req.size, vk::MemoryMapFlags::empty()).unwrap() };
-        let mut vert_align = unsafe { Align::new(
-            vert_ptr,
-            align_of::<Vertex>() as u64,
-            vb_mem_req.size,
-        ) };
-        vert_align.copy_from_slice(&VERTICES);
-        unsafe { self.device.unmap_memory(vertex_buffer_mem) };
-
-        unsafe { self.device.bind_buffer_memory(vertex_buffer, vertex_buffer_mem, 0).unwrap() };
-
-        let mut ubufs: Vec<vk::Buffer> = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT as usize);
-        let mut ubufs_mem: Vec<vk::DeviceMemory> = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT as usize);
-        let mut ubufs_map: Vec<*mut ffi::c_void> = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT as usize);
+        //the fullscreen quad never changes, the blocks the shaders read do every frame. see util::upload
+        let vertices = unsafe { slice::from_raw_parts(VERTICES.as_ptr().cast::<u8>(), size_of_val(&VERTICES)) };
+        let (vertex_buffer, vertex_buffer_mem) = unsafe {
+            upload_buffer(self.device, self.physical_device, self.queue, self.command_pool, vk::BufferUsageFlags::VERTEX_BUFFER, vertices) };
+        let uploader = unsafe { Uploader::new(self.device, self.physical_device, self.transfer, MAX_FRAMES_IN_FLIGHT as usize) };
+        let ubo = unsafe { StagedBuffer::new(self.device, self.physical_device, &self.transfer, size_of::<UniformBufferObject>() as u64,
+                                             vk::BufferUsageFlags::UNIFORM_BUFFER, MAX_FRAMES_IN_FLIGHT as usize) };
+        let mv = unsafe { StagedBuffer::new(self.device, self.physical_device, &self.transfer, size_of::<MVBufferObject>() as u64,
+                                            vk::BufferUsageFlags::STORAGE_BUFFER, MAX_FRAMES_IN_FLIGHT as usize) };
 
-        let mut mv_ubufs: Vec<vk::Buffer> = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT as usize);
-        let mut mv_ubufs_mem: Vec<vk::DeviceMemory> = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT as usize);
-        let mut mv_ubufs_map: Vec<*mut ffi::c_void> = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT as usize);
 
+        let scale = unsafe { RenderScale::new(self.device, self.physical_device, self.transfer.graphics_family,
+                                              crate::util::scale::describe(self.config), MAX_FRAMES_IN_FLIGHT as usize) };
 
-        let buf_info = vk::BufferCreateInfo {
-            flags: vk::BufferCreateFlags::default(),
-            size: size_of::<UniformBufferObject>() as u64,
-            usage: vk::BufferUsageFlags::UNIFORM_BUFFER,
-            sharing_mode: vk::SharingMode::EXCLUSIVE,
-            ..Default::default()};
-        let buf_mem_info = vk::MemoryAllocateInfo {
-            allocation_size: buf_info.size,
-            memory_type_index: 0, // todo! actually check memtype
-            ..Default::default()};
-
-        let mv_buf_info = vk::BufferCreateInfo {
-            flags: vk::BufferCreateFlags::default(),
-            size: size_of::<MVBufferObject>() as u64,
-            usage: vk::BufferUsageFlags::STORAGE_BUFFER,
-            sharing_mode: vk::SharingMode::EXCLUSIVE,
-            ..Default::default()};
-        let mv_buf_mem_info = vk::MemoryAllocateInfo {
-            allocation_size: mv_buf_info.size,
-            memory_type_index: 0, // todo! actually check memtype
-            ..Default::default()};
-
-        let mut buf: vk::Buffer = vk::Buffer::null();
-        let mut mem: vk::DeviceMemory = vk::DeviceMemory::null();
-        let mut map: *mut c_void = ptr::null_mut();
-        (0..MAX_FRAMES_IN_FLIGHT).for_each(|_|{
-            buf = unsafe { self.device.create_buffer(&buf_info, None).unwrap() };
-            mem = unsafe { self.device.allocate_memory(&buf_mem_info, None).unwrap() };
-            unsafe { self.device.bind_buffer_memory(buf,mem,0).unwrap() };
-            map = unsafe { self.device.map_memory(mem, 0, buf_info.size, vk::MemoryMapFlags::default()).unwrap() };
-            ubufs.push(buf);
-            ubufs_mem.push(mem);
-            ubufs_map.push(map);
-
-            buf = unsafe { self.device.create_buffer(&mv_buf_info, None).unwrap() };
-            mem = unsafe { self.device.allocate_memory(&mv_buf_mem_info, None).unwrap() };
-            unsafe { self.device.bind_buffer_memory(buf,mem,0).unwrap() };
-            map = unsafe { self.device.map_memory(mem, 0, mv_buf_info.size, vk::MemoryMapFlags::default()).unwrap() };
-            mv_ubufs.push(buf);
-            mv_ubufs_mem.push(mem);
-            mv_ubufs_map.push(map);
-        });
-
-
-        let sets = vec![descriptor_set_layout,descriptor_set_layout];
-
-        let descriptor_set_info = vk::DescriptorSetAllocateInfo {
-            descriptor_pool,
-            descriptor_set_count: MAX_FRAMES_IN_FLIGHT,
-            p_set_layouts: sets.as_ptr(),
-            ..Default::default()};
-
-
-        let descriptor_sets = unsafe { self.device.allocate_descriptor_sets(&descriptor_set_info).unwrap() };
-        println!("desc set alloc done");
-
-        (0..MAX_FRAMES_IN_FLIGHT).for_each(|idx|{
-            let buf_info = vk::DescriptorBufferInfo {
-                buffer: ubufs[idx as usize],
-                offset: 0,
-                range: size_of::<UniformBufferObject>() as u64,
-            };
-
-            let img_info = vk::DescriptorImageInfo {
-                sampler: screencast.unwrap().sampler,
-                image_view: screencast.unwrap().view,
-                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            };
-
-            let mv_buf_info = vk::DescriptorBufferInfo {
-                buffer: mv_ubufs[idx as usize],
-                offset: 0,
-                range: size_of::<MVBufferObject>() as u64,
-            };
-
-            let descriptor_write_ubo = vk::WriteDescriptorSet {
-                dst_set: descriptor_sets[idx as usize],
-                dst_binding: 0,
-                dst_array_element: 0,
-                descriptor_count: 1,
-                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                p_image_info: ptr::null(),
-                p_buffer_info: &buf_info,
-                p_texel_buffer_view: ptr::null(),
-                ..Default::default()};
-
-            let descriptor_write_img = vk::WriteDescriptorSet {
-                dst_set: descriptor_sets[idx as usize],
-                dst_binding: 1,
-                dst_array_element: 0,
-                descriptor_count: 1,
-                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                p_image_info: &img_info,
-                ..Default::default()};
-
-            let descriptor_write_mv = vk::WriteDescriptorSet {
-                dst_set: descriptor_sets[idx as usize],
-                dst_binding: 2,
-                dst_array_element: 0,
-                descriptor_count: 1,
-                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
-                p_image_info: ptr::null(),
-                p_buffer_info: &mv_buf_info,
-                p_texel_buffer_view: ptr::null(),
-                ..Default::default()};
-
-            let descriptor_writes: Vec<vk::WriteDescriptorSet> = vec![descriptor_write_ubo,descriptor_write_img,descriptor_write_mv];
-            unsafe { self.device.update_descriptor_sets(descriptor_writes.as_slice(), &[]) };
-        });
+        //the passes and everything they bind, see util::chain
+        let inputs = ChainInputs {
+            ubufs: ubo.buffers.clone(),
+            mv_ubufs: mv.buffers.clone(),
+            captures: screencasts.iter().map(Capture::sampled).collect(),
+            source,
+            queue: self.queue,
+            family: self.transfer.graphics_family,
+            command_pool: self.command_pool,
+            copyable: unsafe { self.ext.surface.get_physical_device_surface_capabilities(self.physical_device, surface) }
+                .is_ok_and(|capabilities| capabilities.supported_usage_flags.contains(vk::ImageUsageFlags::TRANSFER_DST)) };
+        let chain = unsafe { Chain::new(self.device, self.physical_device, extent, scale.factor, render_pass, format, views.clone(), describe(self.config), inputs,
+                                        self.ext.synchronization2.clone(), self.ext.dynamic_rendering.clone()) };
 
 
         let cmd_alloc_info = vk::CommandBufferAllocateInfo {
@@ -322,21 +206,16 @@ impl<'a> WindowBuilder<'a> {
             swapchain: PerSwapchain {
                 handle: swapchain, format, extent, images, views, framebuffers, sync },
             render_pass,
-            pipeline, layout,
+            chain,
             command_buffers,
             vertex_buffer,
             vertex_buffer_mem,
-            push_constant_range,
-            descriptor_set_layout,
-            ubufs,
-            ubufs_mem,
-            ubufs_map,
-            mv_ubufs,
-            mv_ubufs_mem,
-            mv_ubufs_map,
-            descriptor_pool,
-            descriptor_sets,
+            uploader,
+            ubo,
+            mv,
+            scale,
             id: 0,
+            source,
         })
     }
 }
@@ -0,0 +1,668 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use ash::vk;
+
+//just enough of a SPIR-V parser to figure out what a shader module expects from us.
+//  spec: https://registry.khronos.org/SPIR-V/specs/unified1/SPIRV.html
+//  we only care about descriptor bindings, push constant blocks and specialization constants, everything else gets skipped.
+
+const MAGIC: u32 = 0x07230203;
+
+mod op {
+    pub const NAME: u16 = 5;
+    pub const MEMBER_NAME: u16 = 6;
+    pub const ENTRY_POINT: u16 = 15;
+    pub const EXECUTION_MODE: u16 = 16;
+    pub const TYPE_BOOL: u16 = 20;
+    pub const TYPE_INT: u16 = 21;
+    pub const TYPE_FLOAT: u16 = 22;
+    pub const TYPE_VECTOR: u16 = 23;
+    pub const TYPE_MATRIX: u16 = 24;
+    pub const TYPE_IMAGE: u16 = 25;
+    pub const TYPE_SAMPLER: u16 = 26;
+    pub const TYPE_SAMPLED_IMAGE: u16 = 27;
+    pub const TYPE_ARRAY: u16 = 28;
+    pub const TYPE_RUNTIME_ARRAY: u16 = 29;
+    pub const TYPE_STRUCT: u16 = 30;
+    pub const TYPE_POINTER: u16 = 32;
+    pub const CONSTANT: u16 = 43;
+    pub const SPEC_CONSTANT_TRUE: u16 = 48;
+    pub const SPEC_CONSTANT_FALSE: u16 = 49;
+    pub const SPEC_CONSTANT: u16 = 50;
+    pub const VARIABLE: u16 = 59;
+    pub const DECORATE: u16 = 71;
+    pub const MEMBER_DECORATE: u16 = 72;
+}
+mod execution_mode {
+    pub const LOCAL_SIZE: u32 = 17;
+}
+mod decoration {
+    pub const SPEC_ID: u32 = 1;
+    pub const BLOCK: u32 = 2;
+    pub const BUFFER_BLOCK: u32 = 3;
+    pub const ARRAY_STRIDE: u32 = 6;
+    pub const MATRIX_STRIDE: u32 = 7;
+    pub const BINDING: u32 = 33;
+    pub const DESCRIPTOR_SET: u32 = 34;
+    pub const OFFSET: u32 = 35;
+}
+mod storage {
+    pub const UNIFORM_CONSTANT: u32 = 0;
+    pub const UNIFORM: u32 = 2;
+    pub const PUSH_CONSTANT: u32 = 9;
+    pub const STORAGE_BUFFER: u32 = 12;
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Scalar { width: u32, kind: ScalarKind },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { storage: u32, pointee: u32 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum ScalarKind { Bool, Int, UInt, Float }
+
+#[derive(Clone, Debug)]
+pub(crate) struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub count: u32,
+    pub stages: vk::ShaderStageFlags,
+    pub name: String,
+    //only for buffers. size of the block, not counting a trailing runtime array.
+    pub size: Option<u64>,
+    //only for buffers ending in a runtime array.
+    pub tail_stride: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PushConstantBlock {
+    pub offset: u32,
+    pub size: u32,
+    pub stages: vk::ShaderStageFlags,
+    pub name: String,
+}
+
+//`layout(constant_id = N) const T NAME = default;` - the name is what the config refers to.
+#[derive(Clone, Debug)]
+pub(crate) struct SpecConstant {
+    pub id: u32,
+    pub name: String,
+    pub kind: ScalarKind,
+    pub width: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ShaderInterface {
+    pub bindings: Vec<DescriptorBinding>,
+    pub push_constants: Option<PushConstantBlock>,
+    pub spec_constants: Vec<SpecConstant>,
+    //local_size of a compute shader, for working out how many groups cover an image
+    pub workgroup_size: Option<[u32;3]>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ReflectError(String);
+impl Display for ReflectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "SPIR-V reflection failed: {}", self.0)
+    }
+}
+impl Error for ReflectError {}
+
+
+impl ShaderInterface {
+    pub fn reflect(code: &[u32]) -> Result<ShaderInterface, ReflectError> {
+        if code.len() < 5 || code[0] != MAGIC {
+            return Err(ReflectError("not a SPIR-V module".to_owned())) }
+
+        let mut stages = vk::ShaderStageFlags::empty();
+        let mut names: HashMap<u32, String> = HashMap::new();
+        let mut types: HashMap<u32, Type> = HashMap::new();
+        let mut constants: HashMap<u32, u32> = HashMap::new();
+        let mut variables: Vec<(u32, u32, u32)> = Vec::new(); //(id, pointer type, storage class)
+        let mut spec_constants: Vec<(u32, u32)> = Vec::new(); //(id, result type)
+        let mut decorations: HashMap<(u32, u32), u32> = HashMap::new(); //(id, decoration) -> first literal
+        let mut member_offsets: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut member_matrix_strides: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut workgroup_size: Option<[u32;3]> = None;
+
+        let mut idx = 5;
+        while idx < code.len() {
+            let word_count = (code[idx] >> 16) as usize;
+            let opcode = (code[idx] & 0xFFFF) as u16;
+            if word_count == 0 || idx + word_count > code.len() {
+                return Err(ReflectError(format!("malformed instruction at word {idx}"))) }
+            let args = &code[idx+1..idx+word_count];
+            idx += word_count;
+
+            match opcode {
+                op::ENTRY_POINT => stages |= match args[0] {
+                    0 => vk::ShaderStageFlags::VERTEX,
+                    1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+                    2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+                    3 => vk::ShaderStageFlags::GEOMETRY,
+                    4 => vk::ShaderStageFlags::FRAGMENT,
+                    5 => vk::ShaderStageFlags::COMPUTE,
+                    model => return Err(ReflectError(format!("unsupported execution model {model}")))},
+                //local_size_x_id and friends would be LocalSizeId, those stay at whatever the config groups say
+                op::EXECUTION_MODE if args.len() >= 5 && args[1] == execution_mode::LOCAL_SIZE =>
+                    workgroup_size = Some([args[2], args[3], args[4]]),
+                op::NAME => { names.insert(args[0], string(&args[1..])); }
+                op::MEMBER_NAME => {}
+                op::DECORATE if args.len() >= 2 => {
+                    decorations.insert((args[0], args[1]), args.get(2).copied().unwrap_or(0)); }
+                op::MEMBER_DECORATE => match args[2] {
+                    decoration::OFFSET => { member_offsets.insert((args[0], args[1]), args[3]); }
+                    decoration::MATRIX_STRIDE => { member_matrix_strides.insert((args[0], args[1]), args[3]); }
+                    _ => {}},
+                op::TYPE_BOOL => { types.insert(args[0], Type::Scalar { width: 32, kind: ScalarKind::Bool }); }
+                op::TYPE_INT => { types.insert(args[0], Type::Scalar { width: args[1],
+                    kind: if args[2] == 1 { ScalarKind::Int } else { ScalarKind::UInt } }); }
+                op::TYPE_FLOAT => { types.insert(args[0], Type::Scalar { width: args[1], kind: ScalarKind::Float }); }
+                op::TYPE_VECTOR => { types.insert(args[0], Type::Vector { component: args[1], count: args[2] }); }
+                op::TYPE_MATRIX => { types.insert(args[0], Type::Matrix { column: args[1], count: args[2] }); }
+                op::TYPE_IMAGE => { types.insert(args[0], Type::Image { dim: args[2], sampled: args[6] }); }
+                op::TYPE_SAMPLER => { types.insert(args[0], Type::Sampler); }
+                op::TYPE_SAMPLED_IMAGE => { types.insert(args[0], Type::SampledImage); }
+                op::TYPE_ARRAY => { types.insert(args[0], Type::Array { element: args[1], length: args[2] }); }
+                op::TYPE_RUNTIME_ARRAY => { types.insert(args[0], Type::RuntimeArray { element: args[1] }); }
+                op::TYPE_STRUCT => { types.insert(args[0], Type::Struct { members: args[1..].to_vec() }); }
+                op::TYPE_POINTER => { types.insert(args[0], Type::Pointer { storage: args[1], pointee: args[2] }); }
+                //only the low word matters, nobody declares 2^32 descriptors
+                op::CONSTANT => { constants.insert(args[1], args[2]); }
+                op::SPEC_CONSTANT_TRUE | op::SPEC_CONSTANT_FALSE | op::SPEC_CONSTANT => spec_constants.push((args[1], args[0])),
+                op::VARIABLE => variables.push((args[1], args[0], args[2])),
+                _ => {}
+            }
+        }
+
+        let layout = Layout { types: &types, constants: &constants, decorations: &decorations,
+            member_offsets: &member_offsets, member_matrix_strides: &member_matrix_strides };
+        let mut interface = ShaderInterface { workgroup_size, ..Default::default() };
+
+        for (id, pointer, storage_class) in variables {
+            if !matches!(storage_class,
+                storage::UNIFORM_CONSTANT | storage::UNIFORM | storage::STORAGE_BUFFER | storage::PUSH_CONSTANT) { continue }
+            let Some(Type::Pointer { pointee, .. }) = types.get(&pointer) else {
+                return Err(ReflectError(format!("variable %{id} isn't a pointer"))) };
+
+            if storage_class == storage::PUSH_CONSTANT {
+                let (offset, size) = layout.block_extent(*pointee)?;
+                interface.push_constants = Some(PushConstantBlock {
+                    offset, size, stages,
+                    name: names.get(&id).filter(|name| !name.is_empty())
+                        .or(names.get(pointee)).cloned().unwrap_or_default() });
+                continue
+            }
+
+            //arrays of descriptors; strip them off and remember the count
+            let (mut count, mut inner) = (1u32, *pointee);
+            match types.get(&inner) {
+                Some(Type::Array { element, length }) => {
+                    count = *constants.get(length).unwrap_or(&1);
+                    inner = *element }
+                Some(Type::RuntimeArray { .. }) => {
+                    return Err(ReflectError(format!("unbounded descriptor array \"{}\" is unsupported",
+                        names.get(&id).cloned().unwrap_or_default()))) }
+                _ => {}
+            }
+
+            let descriptor_type = match (storage_class, types.get(&inner)) {
+                (storage::UNIFORM_CONSTANT, Some(Type::SampledImage)) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                (storage::UNIFORM_CONSTANT, Some(Type::Sampler)) => vk::DescriptorType::SAMPLER,
+                (storage::UNIFORM_CONSTANT, Some(Type::Image { dim, sampled })) => match (dim, sampled) {
+                    (5, 1) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                    (5, _) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                    (6, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                    (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                    _ => vk::DescriptorType::SAMPLED_IMAGE },
+                (storage::UNIFORM, Some(Type::Struct { .. })) => {
+                    if decorations.contains_key(&(inner, decoration::BUFFER_BLOCK)) { vk::DescriptorType::STORAGE_BUFFER }
+                    else { vk::DescriptorType::UNIFORM_BUFFER }}
+                (storage::STORAGE_BUFFER, Some(Type::Struct { .. })) => vk::DescriptorType::STORAGE_BUFFER,
+                (_, ty) => return Err(ReflectError(format!("unsupported resource type {ty:?} for \"{}\"",
+                    names.get(&id).cloned().unwrap_or_default()))),
+            };
+
+            let (size, tail_stride) = match descriptor_type {
+                vk::DescriptorType::UNIFORM_BUFFER | vk::DescriptorType::STORAGE_BUFFER => {
+                    let (size, tail_stride) = layout.block_size(inner)?;
+                    (Some(size as u64), tail_stride.map(|stride| stride as u64)) }
+                _ => (None, None)
+            };
+
+            interface.bindings.push(DescriptorBinding {
+                set: *decorations.get(&(id, decoration::DESCRIPTOR_SET)).unwrap_or(&0),
+                binding: *decorations.get(&(id, decoration::BINDING)).unwrap_or(&0),
+                descriptor_type, count, stages,
+                name: names.get(&id).cloned().unwrap_or_default(),
+                size, tail_stride });
+        }
+        interface.bindings.sort_by_key(|binding| (binding.set, binding.binding));
+
+        //composites and spec-constant-ops don't carry a SpecId, so they fall out here on their own
+        for (id, result_type) in spec_constants {
+            let Some(spec_id) = decorations.get(&(id, decoration::SPEC_ID)) else { continue };
+            let Some(Type::Scalar { width, kind }) = types.get(&result_type) else {
+                return Err(ReflectError(format!("specialization constant %{id} isn't a scalar"))) };
+            interface.spec_constants.push(SpecConstant {
+                id: *spec_id,
+                name: names.get(&id).cloned().unwrap_or_default(),
+                kind: *kind, width: *width });
+        }
+        interface.spec_constants.sort_by_key(|constant| constant.id);
+        Ok(interface)
+    }
+
+    //merges the interfaces of all stages of one pipeline.
+    pub fn merge(mut self, other: ShaderInterface) -> Result<ShaderInterface, ReflectError> {
+        for binding in other.bindings {
+            match self.bindings.iter_mut().find(|b| b.set == binding.set && b.binding == binding.binding) {
+                Some(existing) => {
+                    if existing.descriptor_type != binding.descriptor_type || existing.count != binding.count {
+                        return Err(ReflectError(format!(
+                            "set {} binding {} is declared as {:?}[{}] in one stage and {:?}[{}] in another",
+                            binding.set, binding.binding, existing.descriptor_type, existing.count,
+                            binding.descriptor_type, binding.count))) }
+                    existing.stages |= binding.stages;
+                    existing.size = existing.size.max(binding.size);
+                }
+                None => self.bindings.push(binding),
+            }
+        }
+        self.push_constants = match (self.push_constants, other.push_constants) {
+            (Some(a), Some(b)) => {
+                let start = a.offset.min(b.offset);
+                let end = (a.offset + a.size).max(b.offset + b.size);
+                Some(PushConstantBlock { offset: start, size: end - start, stages: a.stages | b.stages, name: a.name }) }
+            (a, b) => a.or(b),
+        };
+        self.bindings.sort_by_key(|binding| (binding.set, binding.binding));
+
+        for constant in other.spec_constants {
+            match self.spec_constants.iter().find(|existing| existing.id == constant.id) {
+                Some(existing) if existing.kind != constant.kind || existing.width != constant.width => {
+                    return Err(ReflectError(format!(
+                        "constant_id {} is {:?} \"{}\" in one stage and {:?} \"{}\" in another",
+                        constant.id, existing.kind, existing.name, constant.kind, constant.name))) }
+                Some(_) => {}
+                None => self.spec_constants.push(constant),
+            }
+        }
+        self.spec_constants.sort_by_key(|constant| constant.id);
+        self.workgroup_size = self.workgroup_size.or(other.workgroup_size);
+        Ok(self)
+    }
+
+    //what the interface would be if the shaders used exactly what the rust side binds, in every graphics stage.
+    //for when there's nothing to reflect (shaders that don't build), but a layout is needed anyway.
+    pub fn assumed(rust_bindings: &[RustBinding], rust_push_constants: Option<u32>) -> ShaderInterface {
+        ShaderInterface {
+            bindings: rust_bindings.iter().map(|rust| DescriptorBinding {
+                set: rust.set,
+                binding: rust.binding,
+                descriptor_type: rust.descriptor_type,
+                count: rust.count,
+                stages: vk::ShaderStageFlags::ALL_GRAPHICS,
+                name: rust.name.to_owned(),
+                size: rust.size,
+                tail_stride: rust.tail_stride }).collect(),
+            push_constants: rust_push_constants.map(|size| PushConstantBlock {
+                offset: 0, size, stages: vk::ShaderStageFlags::ALL_GRAPHICS, name: "assumed".to_owned() }),
+            spec_constants: Vec::new(),
+            workgroup_size: None,
+        }
+    }
+
+    pub fn set_layout_bindings(&self, set: u32) -> Vec<vk::DescriptorSetLayoutBinding<'static>> {
+        self.bindings.iter().filter(|binding| binding.set == set).map(|binding| vk::DescriptorSetLayoutBinding {
+            binding: binding.binding,
+            descriptor_type: binding.descriptor_type,
+            descriptor_count: binding.count,
+            stage_flags: binding.stages,
+            ..Default::default()}).collect()
+    }
+
+    //one pool size per descriptor type, enough for `sets` copies of every set
+    pub fn pool_sizes(&self, sets: u32) -> Vec<vk::DescriptorPoolSize> {
+        let mut pool_sizes: Vec<vk::DescriptorPoolSize> = Vec::new();
+        for binding in &self.bindings {
+            match pool_sizes.iter_mut().find(|size| size.ty == binding.descriptor_type) {
+                Some(size) => size.descriptor_count += binding.count * sets,
+                None => pool_sizes.push(vk::DescriptorPoolSize { ty: binding.descriptor_type, descriptor_count: binding.count * sets }),
+            }
+        }
+        pool_sizes
+    }
+
+    pub fn push_constant_range(&self) -> Option<vk::PushConstantRange> {
+        self.push_constants.as_ref().map(|block| vk::PushConstantRange {
+            stage_flags: block.stages,
+            offset: block.offset,
+            size: block.size })
+    }
+
+    //compares the reflected interface against what the rust side is going to bind.
+    //every problem gets collected, so a single failed build tells you everything that's wrong at once.
+    pub fn check(&self, pipeline: &str, rust_bindings: &[RustBinding], rust_push_constants: Option<u32>) -> Result<(), InterfaceMismatch> {
+        let mut problems: Vec<String> = Vec::new();
+
+        for binding in &self.bindings {
+            let Some(rust) = rust_bindings.iter().find(|rust| rust.set == binding.set && rust.binding == binding.binding) else {
+                problems.push(format!("set {} binding {} ({}): shader declares {:?}, Rust side binds nothing there",
+                    binding.set, binding.binding, binding.name, binding.descriptor_type));
+                continue };
+            if rust.descriptor_type != binding.descriptor_type {
+                problems.push(format!("set {} binding {} ({}): shader declares {:?}, Rust side binds {:?} ({})",
+                    binding.set, binding.binding, binding.name, binding.descriptor_type, rust.descriptor_type, rust.name)) }
+            if rust.count != binding.count {
+                problems.push(format!("set {} binding {} ({}): shader declares {} descriptor(s), Rust side binds {}",
+                    binding.set, binding.binding, binding.name, binding.count, rust.count)) }
+            if let (Some(size), Some(rust_size)) = (binding.size, rust.size) {
+                match (binding.tail_stride, rust.tail_stride) {
+                    (None, _) if size != rust_size => problems.push(format!(
+                        "set {} binding {} ({}): shader block is {} bytes, Rust type {} is {} bytes",
+                        binding.set, binding.binding, binding.name, size, rust.name, rust_size)),
+                    (Some(stride), rust_stride) if rust_stride != Some(stride) || rust_size < size || (rust_size - size) % stride != 0 =>
+                        problems.push(format!(
+                        "set {} binding {} ({}): shader block is {} bytes followed by a runtime array of stride {}, Rust type {} is {} bytes with element stride {}",
+                        binding.set, binding.binding, binding.name, size, stride, rust.name, rust_size,
+                        rust_stride.map(|stride| stride.to_string()).unwrap_or("<none>".to_owned()))),
+                    _ => {}
+                }
+            }
+        }
+        for rust in rust_bindings {
+            if !self.bindings.iter().any(|binding| binding.set == rust.set && binding.binding == rust.binding) {
+                log::warn!("Pipeline \"{pipeline}\": set {} binding {} ({}) is bound on the Rust side but unused by the shaders",
+                    rust.set, rust.binding, rust.name) }
+        }
+
+        match (&self.push_constants, rust_push_constants) {
+            (Some(block), Some(size)) if block.offset + block.size != size => problems.push(format!(
+                "push constants ({}): shader block ends at byte {}, Rust side pushes {} bytes",
+                block.name, block.offset + block.size, size)),
+            (Some(block), None) => problems.push(format!(
+                "push constants ({}): shader declares {} bytes, Rust side pushes nothing", block.name, block.size)),
+            _ => {}
+        }
+
+        if problems.is_empty() { Ok(()) }
+        else { Err(InterfaceMismatch { pipeline: pipeline.to_owned(), problems }) }
+    }
+}
+
+//the rust-side counterpart of a DescriptorBinding, i.e. whatever we write into the descriptor set.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct RustBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub count: u32,
+    pub name: &'static str,
+    pub size: Option<u64>,
+    pub tail_stride: Option<u64>,
+}
+
+#[derive(Debug)]
+pub(crate) struct InterfaceMismatch {
+    pub pipeline: String,
+    pub problems: Vec<String>,
+}
+impl Display for InterfaceMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Shader interface mismatch in pipeline \"{}\":", self.pipeline)?;
+        for problem in &self.problems {
+            writeln!(f, "    - {problem}")?;
+        }
+        Ok(())
+    }
+}
+impl Error for InterfaceMismatch {}
+
+
+struct Layout<'a> {
+    types: &'a HashMap<u32, Type>,
+    constants: &'a HashMap<u32, u32>,
+    decorations: &'a HashMap<(u32, u32), u32>,
+    member_offsets: &'a HashMap<(u32, u32), u32>,
+    member_matrix_strides: &'a HashMap<(u32, u32), u32>,
+}
+impl Layout<'_> {
+    fn ty(&self, id: u32) -> Result<&Type, ReflectError> {
+        self.types.get(&id).ok_or_else(|| ReflectError(format!("unknown type %{id}")))
+    }
+
+    //size of a (non-runtime) type, as laid out by the explicit offsets/strides.
+    //`matrix_stride` comes from the member decoration, since it's not a property of the matrix type itself.
+    fn size(&self, id: u32, matrix_stride: Option<u32>) -> Result<u32, ReflectError> {
+        Ok(match self.ty(id)? {
+            Type::Scalar { width, .. } => width / 8,
+            Type::Vector { component, count } => self.size(*component, None)? * count,
+            Type::Matrix { column, count } => match matrix_stride {
+                Some(stride) => stride * count,
+                None => self.size(*column, None)? * count },
+            Type::Array { element, length } => {
+                let length = *self.constants.get(length).unwrap_or(&1);
+                match self.decorations.get(&(id, decoration::ARRAY_STRIDE)) {
+                    Some(stride) => stride * length,
+                    None => self.size(*element, matrix_stride)? * length }}
+            Type::Struct { .. } => {
+                let (size, tail) = self.block_size(id)?;
+                if tail.is_some() { return Err(ReflectError(format!("nested runtime array in %{id}"))) }
+                size }
+            ty => return Err(ReflectError(format!("type {ty:?} has no size"))),
+        })
+    }
+
+    //(size without a trailing runtime array, stride of that runtime array)
+    fn block_size(&self, id: u32) -> Result<(u32, Option<u32>), ReflectError> {
+        let Type::Struct { members } = self.ty(id)? else {
+            return Err(ReflectError(format!("%{id} is not a block"))) };
+        let mut end = 0;
+        let mut tail = None;
+        for (idx, member) in members.iter().enumerate() {
+            let offset = *self.member_offsets.get(&(id, idx as u32)).unwrap_or(&end);
+            if let Type::RuntimeArray { .. } = self.ty(*member)? {
+                tail = Some(*self.decorations.get(&(*member, decoration::ARRAY_STRIDE)).unwrap_or(&0));
+                end = end.max(offset);
+                continue }
+            end = end.max(offset + self.size(*member, self.member_matrix_strides.get(&(id, idx as u32)).copied())?);
+        }
+        Ok((end, tail))
+    }
+
+    //(offset of the first member, size from there on). push constant ranges don't need to start at 0.
+    fn block_extent(&self, id: u32) -> Result<(u32, u32), ReflectError> {
+        let Type::Struct { members } = self.ty(id)? else {
+            return Err(ReflectError(format!("%{id} is not a block"))) };
+        let start = (0..members.len() as u32)
+            .filter_map(|idx| self.member_offsets.get(&(id, idx)).copied())
+            .min().unwrap_or(0);
+        let (end, _) = self.block_size(id)?;
+        Ok((start, end - start))
+    }
+}
+
+//SPIR-V literal strings are nul-terminated UTF-8, packed little-endian into words
+fn string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).take_while(|byte| *byte != 0).collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reflect(source: &str, kind: shaderc::ShaderKind) -> ShaderInterface {
+        let compiler = shaderc::Compiler::new().unwrap();
+        let artifact = compiler.compile_into_spirv(source, kind, "test.glsl", "main", None).unwrap();
+        ShaderInterface::reflect(artifact.as_binary()).unwrap()
+    }
+
+    const FRAGMENT: &str = "#version 450
+        layout(set = 0, binding = 0) uniform Ubo { mat4 model; vec4 tint; } ubo;
+        layout(set = 0, binding = 1) uniform sampler2D capture;
+        layout(set = 0, binding = 2) uniform sampler2D layers[3];
+        layout(set = 1, binding = 0) buffer Histogram { uint total; uint bins[]; } histogram;
+        layout(push_constant) uniform PushConstants { vec2 capture; float time; } pc;
+        layout(location = 0) out vec4 color;
+        void main() {
+            color = (texture(capture, pc.capture) + texture(layers[1], pc.capture)) * ubo.tint * pc.time * ubo.model[0].x
+                + float(histogram.total + histogram.bins[0]);
+        }";
+
+    const VERTEX: &str = "#version 450
+        layout(set = 0, binding = 0) uniform Ubo { mat4 model; vec4 tint; } ubo;
+        layout(push_constant) uniform PushConstants { layout(offset = 16) mat4 mvp; } pc;
+        void main() { gl_Position = pc.mvp * ubo.model * vec4(0.0, 0.0, 0.0, 1.0); }";
+
+    fn binding(binding: u32, descriptor_type: vk::DescriptorType, count: u32, name: &'static str, size: Option<u64>) -> RustBinding {
+        RustBinding { set: 0, binding, descriptor_type, count, name, size, tail_stride: None }
+    }
+
+    //what FRAGMENT's set 0 wants, and pushes
+    fn fragment_bindings() -> Vec<RustBinding> {
+        vec![binding(0, vk::DescriptorType::UNIFORM_BUFFER, 1, "ubo", Some(80)),
+             binding(1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1, "capture", None),
+             binding(2, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 3, "layers", None),
+             RustBinding { set: 1, binding: 0, descriptor_type: vk::DescriptorType::STORAGE_BUFFER, count: 1, name: "histogram",
+                           size: Some(4), tail_stride: Some(4) }]
+    }
+
+    #[test]
+    fn reflects_bindings() {
+        let interface = reflect(FRAGMENT, shaderc::ShaderKind::Fragment);
+        let bindings = interface.bindings.iter()
+            .map(|binding| (binding.set, binding.binding, binding.descriptor_type, binding.count, binding.name.as_str(), binding.size, binding.tail_stride))
+            .collect::<Vec<_>>();
+        assert_eq!(bindings, [
+            (0, 0, vk::DescriptorType::UNIFORM_BUFFER, 1, "ubo", Some(80), None),
+            (0, 1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1, "capture", None, None),
+            (0, 2, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 3, "layers", None, None),
+            (1, 0, vk::DescriptorType::STORAGE_BUFFER, 1, "histogram", Some(4), Some(4)),
+        ]);
+        assert!(interface.bindings.iter().all(|binding| binding.stages == vk::ShaderStageFlags::FRAGMENT));
+
+        let layout = interface.set_layout_bindings(0);
+        assert_eq!(layout.iter().map(|binding| (binding.binding, binding.descriptor_count)).collect::<Vec<_>>(), [(0, 1), (1, 1), (2, 3)]);
+        assert_eq!(interface.set_layout_bindings(1).len(), 1);
+        assert!(interface.set_layout_bindings(2).is_empty());
+    }
+
+    #[test]
+    fn sizes_pools() {
+        let interface = reflect(FRAGMENT, shaderc::ShaderKind::Fragment);
+        let sizes = interface.pool_sizes(2).iter().map(|size| (size.ty, size.descriptor_count)).collect::<Vec<_>>();
+        //the array counts as three
+        assert_eq!(sizes, [
+            (vk::DescriptorType::UNIFORM_BUFFER, 2),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 8),
+            (vk::DescriptorType::STORAGE_BUFFER, 2),
+        ]);
+        assert!(ShaderInterface::default().pool_sizes(2).is_empty());
+    }
+
+    #[test]
+    fn reflects_push_constants() {
+        let range = reflect(FRAGMENT, shaderc::ShaderKind::Fragment).push_constant_range().unwrap();
+        assert_eq!((range.stage_flags, range.offset, range.size), (vk::ShaderStageFlags::FRAGMENT, 0, 12));
+        //a block that doesn't start at 0
+        let range = reflect(VERTEX, shaderc::ShaderKind::Vertex).push_constant_range().unwrap();
+        assert_eq!((range.stage_flags, range.offset, range.size), (vk::ShaderStageFlags::VERTEX, 16, 64));
+        let none = reflect("#version 450\nvoid main() { gl_Position = vec4(0.0); }", shaderc::ShaderKind::Vertex);
+        assert!(none.push_constant_range().is_none());
+    }
+
+    #[test]
+    fn merges_stages() {
+        let merged = reflect(VERTEX, shaderc::ShaderKind::Vertex).merge(reflect(FRAGMENT, shaderc::ShaderKind::Fragment)).unwrap();
+        assert_eq!(merged.bindings.len(), 4);
+        assert_eq!(merged.bindings[0].stages, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT);
+        assert_eq!(merged.bindings[1].stages, vk::ShaderStageFlags::FRAGMENT);
+        //both blocks, from the start of one to the end of the other
+        let range = merged.push_constant_range().unwrap();
+        assert_eq!((range.stage_flags, range.offset, range.size), (vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0, 80));
+
+        let disagreeing = "#version 450
+            layout(set = 0, binding = 1) uniform sampler2D capture[2];
+            void main() { gl_Position = texture(capture[1], vec2(0.0)); }";
+        assert!(reflect(disagreeing, shaderc::ShaderKind::Vertex).merge(reflect(FRAGMENT, shaderc::ShaderKind::Fragment)).is_err());
+    }
+
+    #[test]
+    fn reflects_compute() {
+        let interface = reflect("#version 450
+            layout(local_size_x = 8, local_size_y = 4) in;
+            layout(binding = 8, rgba16f) uniform writeonly image2D target;
+            layout(constant_id = 3) const int MARGIN = 16;
+            layout(constant_id = 0) const float GAIN = 1.0;
+            layout(constant_id = 1) const bool ENABLED = true;
+            void main() {
+                vec4 value = ENABLED ? vec4(GAIN) : vec4(0.0);
+                imageStore(target, ivec2(gl_GlobalInvocationID.xy) + MARGIN, value);
+            }", shaderc::ShaderKind::Compute);
+        assert_eq!(interface.workgroup_size, Some([8, 4, 1]));
+        assert_eq!(interface.bindings.iter().map(|binding| (binding.binding, binding.descriptor_type, binding.stages)).collect::<Vec<_>>(),
+                   [(8, vk::DescriptorType::STORAGE_IMAGE, vk::ShaderStageFlags::COMPUTE)]);
+        let constants = interface.spec_constants.iter()
+            .map(|constant| (constant.id, constant.name.as_str(), constant.kind, constant.width)).collect::<Vec<_>>();
+        assert_eq!(constants, [(0, "GAIN", ScalarKind::Float, 32), (1, "ENABLED", ScalarKind::Bool, 32), (3, "MARGIN", ScalarKind::Int, 32)]);
+    }
+
+    #[test]
+    fn checks() {
+        let interface = reflect(FRAGMENT, shaderc::ShaderKind::Fragment);
+        assert!(interface.check("test", &fragment_bindings(), Some(12)).is_ok());
+        //bound but unused is only worth a warning
+        let mut more = fragment_bindings();
+        more.push(binding(5, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1, "extra", None));
+        assert!(interface.check("test", &more, Some(12)).is_ok());
+        //a longer runtime array is fine, one that doesn't fit its stride isn't
+        let mut longer = fragment_bindings();
+        longer[3].size = Some(4 + 4 * 256);
+        assert!(interface.check("test", &longer, Some(12)).is_ok());
+        longer[3].size = Some(6);
+        assert!(interface.check("test", &longer, Some(12)).is_err());
+    }
+
+    #[test]
+    fn reports_mismatches() {
+        let interface = reflect(FRAGMENT, shaderc::ShaderKind::Fragment);
+        let mut bindings = fragment_bindings();
+        //wrong type, wrong count, wrong size, and nothing at all for the histogram
+        bindings[1].descriptor_type = vk::DescriptorType::SAMPLED_IMAGE;
+        bindings[2].count = 2;
+        bindings[0].size = Some(64);
+        bindings.pop();
+        let Err(mismatch) = interface.check("test", &bindings, Some(8)) else { panic!("expected a mismatch") };
+        assert_eq!(mismatch.pipeline, "test");
+        assert_eq!(mismatch.problems.len(), 5, "{mismatch}");
+        assert!(mismatch.problems.iter().any(|problem| problem.starts_with("set 1 binding 0 (histogram)")), "{mismatch}");
+        assert!(mismatch.problems.iter().any(|problem| problem.starts_with("push constants")), "{mismatch}");
+        //and pushing nothing to a shader that wants push constants
+        let Err(mismatch) = interface.check("test", &fragment_bindings(), None) else { panic!("expected a mismatch") };
+        assert_eq!(mismatch.problems.len(), 1, "{mismatch}");
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(ShaderInterface::reflect(&[]).is_err());
+        assert!(ShaderInterface::reflect(&[0xDEADBEEF, 0, 0, 0, 0]).is_err());
+        //an instruction running past the end
+        assert!(ShaderInterface::reflect(&[MAGIC, 0x10000, 0, 1, 0, 0xFF << 16 | op::NAME as u32]).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use ash::{vk, Device};
+use log::{debug, warn};
+use crate::INSTANCE;
+use crate::util::config::{Config, Section};
+
+
+//rendering at less than the window's resolution, for shaders too heavy to run at full size:
+//
+//  [scale]
+//  factor = 0.5
+//  filter = sharpened
+//  target = 8
+//
+//every pass but the last one renders at `factor` times the size of the window. the last one is the upscale pass then
+//  (see chain::describe), which stretches what the pass before it drew over the whole window.
+//with a target, in milliseconds of GPU time per frame, the factor adapts on its own: anywhere between `min` and `factor`.
+const SCALE_SECTION: &str = "scale";
+//below that there's not much left to look at
+const MIN_FACTOR: f32 = 0.1;
+//the factor moves in steps this large, so the chain doesn't get reallocated over every little change
+const STEP: f32 = 0.05;
+//how far off the target the frame time can be before the factor changes
+const TOLERANCE: f32 = 0.1;
+//how much of every new frame time goes into the average
+const SMOOTHING: f32 = 0.1;
+//frames to average over before the factor changes again
+const SETTLE: u32 = 30;
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum UpscaleFilter {
+    Nearest,
+    Bilinear,
+    //bilinear, with an unsharp mask on top against the blur
+    Sharpened,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct ScaleDesc {
+    pub factor: f32,
+    pub filter: UpscaleFilter,
+    //of the sharpened filter, 0 to 1
+    pub sharpness: f32,
+    //milliseconds of GPU time per frame, if the factor adapts
+    pub target: Option<f32>,
+    //as low as an adapting factor goes
+    pub min: f32,
+}
+
+impl ScaleDesc {
+    //the upscale pass's specialization constants, see upscale.frag
+    pub fn specialization(&self) -> Section {
+        let filter = match self.filter {
+            UpscaleFilter::Nearest => 0,
+            UpscaleFilter::Bilinear => 1,
+            UpscaleFilter::Sharpened => 2,
+        };
+        Section { name: format!("{SCALE_SECTION}.specialization"),
+                  entries: vec![("FILTER".to_owned(), filter.to_string()), ("SHARPNESS".to_owned(), self.sharpness.to_string())] }
+    }
+}
+
+//the [scale] section, if there is one. broken values get warned about and replaced with the defaults.
+pub(crate) fn describe(config: &Config) -> Option<ScaleDesc> {
+    let section = config.section(SCALE_SECTION)?;
+    let factor = section.get_or("factor", 1.0f32);
+    if !(MIN_FACTOR..=1.0).contains(&factor) {
+        warn!("Config [{}]: factor {factor} is out of range, clamping it to {MIN_FACTOR}..1", section.name) }
+    let factor = factor.clamp(MIN_FACTOR, 1.0);
+    let filter = match section.get::<String>("filter").as_deref() {
+        Some("nearest") => UpscaleFilter::Nearest,
+        None | Some("bilinear") => UpscaleFilter::Bilinear,
+        Some("sharpened") => UpscaleFilter::Sharpened,
+        Some(filter) => {
+            warn!("Config [{}]: unknown filter {filter}, expected nearest, bilinear or sharpened", section.name);
+            UpscaleFilter::Bilinear }
+    };
+    let target = section.get::<f32>("target").filter(|target| {
+        if *target <= 0.0 { warn!("Config [{}]: target should be a positive number of milliseconds, not adapting", section.name) }
+        *target > 0.0 });
+    Some(ScaleDesc {
+        factor, filter, target,
+        sharpness: section.get_or("sharpness", 0.5f32).clamp(0.0, 1.0),
+        min: section.get_or("min", 0.25f32).clamp(MIN_FACTOR, factor) })
+}
+
+//how large the scaled images are, never less than a pixel
+pub(crate) fn scaled(extent: vk::Extent2D, factor: f32) -> vk::Extent2D {
+    vk::Extent2D {
+        width: ((extent.width as f32 * factor).round() as u32).max(1),
+        height: ((extent.height as f32 * factor).round() as u32).max(1) }
+}
+
+
+//a window's render scale, and what it takes to adapt it: a timestamp at the start and the end of every frame.
+pub(crate) struct RenderScale {
+    pub desc: Option<ScaleDesc>,
+    //what the chain renders at right now
+    pub factor: f32,
+    //two queries per frame in flight. null if the graphics queue can't do timestamps, the factor doesn't adapt then.
+    queries: vk::QueryPool,
+    //nanoseconds per tick
+    period: f32,
+    //the bits of a timestamp that count. the counter wraps around at the top of them.
+    mask: u64,
+    //whether a frame's timestamps got written since they were last read
+    written: Vec<bool>,
+    //of the frame times so far, in milliseconds
+    average: Option<f32>,
+    //frames since the factor last changed
+    settled: u32,
+}
+
+impl RenderScale {
+    pub unsafe fn new(device: &Device, physical_device: vk::PhysicalDevice, family: u32, desc: Option<ScaleDesc>, frames: usize) -> RenderScale {
+        let bits = INSTANCE.get_physical_device_queue_family_properties(physical_device)[family as usize].timestamp_valid_bits;
+        let queries = match bits > 0 {
+            true => {
+                let query_pool_info = vk::QueryPoolCreateInfo {
+                    query_type: vk::QueryType::TIMESTAMP,
+                    query_count: 2 * frames as u32,
+                    ..Default::default()};
+                device.create_query_pool(&query_pool_info, None).unwrap() }
+            false => {
+                if desc.is_some_and(|desc| desc.target.is_some()) { warn!("No timestamps on the graphics queue, the render scale stays put") }
+                vk::QueryPool::null() }
+        };
+        RenderScale {
+            desc, queries,
+            factor: desc.map_or(1.0, |desc| desc.factor),
+            period: INSTANCE.get_physical_device_properties(physical_device).limits.timestamp_period,
+            mask: u64::MAX >> (64 - bits.clamp(1, 64)),
+            written: vec![false; frames],
+            average: None,
+            settled: 0 }
+    }
+
+    //for when the config changed. returns the factor to render at from now on.
+    pub fn configure(&mut self, desc: Option<ScaleDesc>) -> f32 {
+        self.desc = desc;
+        self.factor = desc.map_or(1.0, |desc| desc.factor);
+        self.average = None;
+        self.settled = 0;
+        self.factor
+    }
+
+    //right after the command buffer begins, and before it ends
+    pub unsafe fn begin(&self, device: &Device, command_buffer: vk::CommandBuffer, frame: usize) {
+        if self.queries == vk::QueryPool::null() { return }
+        device.cmd_reset_query_pool(command_buffer, self.queries, 2 * frame as u32, 2);
+        device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::TOP_OF_PIPE, self.queries, 2 * frame as u32);
+    }
+
+    pub unsafe fn end(&mut self, device: &Device, command_buffer: vk::CommandBuffer, frame: usize) {
+        if self.queries == vk::QueryPool::null() { return }
+        device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::BOTTOM_OF_PIPE, self.queries, 2 * frame as u32 + 1);
+        self.written[frame] = true;
+    }
+
+    //once the frame's fence got waited on. returns the factor to render at from now on, if it should change.
+    pub unsafe fn adapt(&mut self, device: &Device, frame: usize) -> Option<f32> {
+        if !self.written[frame] { return None }
+        self.written[frame] = false;
+        let mut timestamps = [0u64; 2];
+        device.get_query_pool_results(self.queries, 2 * frame as u32, &mut timestamps, vk::QueryResultFlags::TYPE_64).ok()?;
+        //whatever's above the valid bits is undefined, and a counter that wrapped in between still got this far
+        let ticks = (timestamps[1] & self.mask).wrapping_sub(timestamps[0] & self.mask) & self.mask;
+        let time = ticks as f32 * self.period / 1e6;
+        let average = self.average.map_or(time, |average| average + (time - average) * SMOOTHING);
+        self.average = Some(average);
+        self.settled += 1;
+
+        let desc = self.desc?;
+        let target = desc.target?;
+        if self.settled < SETTLE || (average - target).abs() < target * TOLERANCE { return None }
+        //the time goes with the number of pixels, which goes with the square of the factor
+        let factor = ((self.factor * (target / average).sqrt() / STEP).round() * STEP).clamp(desc.min, desc.factor);
+        if factor == self.factor { return None }
+        debug!("Render scale: {average:.2}ms per frame against a target of {target}ms, going from {:.2} to {factor:.2}", self.factor);
+        self.factor = factor;
+        //the frames so far took as long as they did at the old factor
+        self.average = None;
+        self.settled = 0;
+        Some(factor)
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        device.destroy_query_pool(self.queries, None);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desc(factor: f32, filter: UpscaleFilter, sharpness: f32, target: Option<f32>, min: f32) -> Option<ScaleDesc> {
+        Some(ScaleDesc { factor, filter, sharpness, target, min })
+    }
+
+    #[test]
+    fn describes() {
+        use UpscaleFilter::*;
+        let cases = [
+            ("", None),
+            ("[other]\nfactor = 0.5", None),
+            ("[scale]", desc(1.0, Bilinear, 0.5, None, 0.25)),
+            ("[scale]\nfactor = 0.5\nfilter = sharpened\nsharpness = 0.8\ntarget = 8\nmin = 0.3", desc(0.5, Sharpened, 0.8, Some(8.0), 0.3)),
+            ("[scale]\nfilter = nearest", desc(1.0, Nearest, 0.5, None, 0.25)),
+            //out of range factors get clamped
+            ("[scale]\nfactor = 2", desc(1.0, Bilinear, 0.5, None, 0.25)),
+            ("[scale]\nfactor = 0.01", desc(MIN_FACTOR, Bilinear, 0.5, None, MIN_FACTOR)),
+            ("[scale]\nfactor = -1", desc(MIN_FACTOR, Bilinear, 0.5, None, MIN_FACTOR)),
+            //unparseable ones are the default
+            ("[scale]\nfactor = half", desc(1.0, Bilinear, 0.5, None, 0.25)),
+            ("[scale]\nfilter = lanczos", desc(1.0, Bilinear, 0.5, None, 0.25)),
+            //targets that aren't positive don't adapt
+            ("[scale]\ntarget = 0", desc(1.0, Bilinear, 0.5, None, 0.25)),
+            ("[scale]\ntarget = -5", desc(1.0, Bilinear, 0.5, None, 0.25)),
+            ("[scale]\ntarget = soon", desc(1.0, Bilinear, 0.5, None, 0.25)),
+            ("[scale]\nsharpness = 3", desc(1.0, Bilinear, 1.0, None, 0.25)),
+            ("[scale]\nsharpness = -1", desc(1.0, Bilinear, 0.0, None, 0.25)),
+            //min stays between MIN_FACTOR and the factor
+            ("[scale]\nfactor = 0.2\nmin = 0.5", desc(0.2, Bilinear, 0.5, None, 0.2)),
+            ("[scale]\nmin = 0", desc(1.0, Bilinear, 0.5, None, MIN_FACTOR)),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(describe(&Config::from_text(text)), expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn specializes() {
+        let cases = [(UpscaleFilter::Nearest, "0"), (UpscaleFilter::Bilinear, "1"), (UpscaleFilter::Sharpened, "2")];
+        for (filter, expected) in cases {
+            let section = desc(0.5, filter, 0.75, None, 0.25).unwrap().specialization();
+            assert_eq!(section.get::<String>("FILTER").as_deref(), Some(expected));
+            assert_eq!(section.get::<f32>("SHARPNESS"), Some(0.75));
+        }
+    }
+
+    #[test]
+    fn scales() {
+        let cases = [((1920, 1080), 0.5, (960, 540)), ((1920, 1080), 1.0, (1920, 1080)), ((3, 3), 0.5, (2, 2)), ((1, 1), 0.1, (1, 1))];
+        for ((width, height), factor, expected) in cases {
+            let extent = scaled(vk::Extent2D { width, height }, factor);
+            assert_eq!((extent.width, extent.height), expected, "{width}x{height} * {factor}");
+        }
+    }
+}
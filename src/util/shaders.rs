@@ -0,0 +1,277 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use log::{debug, info};
+use shaderc::{IncludeType, ResolvedInclude};
+use crate::glsl_interface;
+
+//every shader goes through here, whatever it's written in. which front-end gets used depends on the extension:
+//  .hlsl -> shaderc in HLSL mode
+//  .wgsl -> naga
+//  anything else (.vert, .frag, .comp, .glsl) -> shaderc, as GLSL
+//the stage can't be told from .hlsl or .wgsl, so it always has to be passed in.
+//either way the entry point ends up being called "main" in the SPIR-V.
+//every file that gets read, includes too, is added to `sources`. that's what hot reloading watches.
+pub(crate) fn load_shaders(path: &str, kind: shaderc::ShaderKind, sources: &mut ShaderSources) -> Result<Vec<u32>, ShaderError> {
+    sources.track(path);
+    let source = fs::read_to_string(path)
+        .map_err(|e| ShaderError::new(path, vec![Diagnostic { file: Some(path.to_owned()), line: None, message: e.to_string() }]))?;
+    info!("Compiling {path}");
+    match Dialect::of(path) {
+        Dialect::Wgsl => compile_wgsl(&source, path, kind, sources),
+        dialect => compile_shaderc(&source, path, kind, dialect, sources),
+    }
+}
+
+//for shaders that are baked into the binary rather than loaded, like the error pipeline's
+pub(crate) fn compile_glsl(source: &str, name: &str, kind: shaderc::ShaderKind) -> Result<Vec<u32>, ShaderError> {
+    compile_shaderc(source, name, kind, Dialect::Glsl, &mut ShaderSources::default())
+}
+
+//one line of compiler output. file and line are wherever the compiler pointed, which might be an include.
+#[derive(Clone, Debug)]
+pub(crate) struct Diagnostic {
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub message: String,
+}
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{file}:{line}: {}", self.message),
+            (Some(file), None) => write!(f, "{file}: {}", self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ShaderError {
+    pub name: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+impl ShaderError {
+    pub fn new(name: impl Into<String>, diagnostics: Vec<Diagnostic>) -> ShaderError {
+        ShaderError { name: name.into(), diagnostics }
+    }
+}
+impl Display for ShaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Failure building {}:", self.name)?;
+        for diagnostic in &self.diagnostics {
+            writeln!(f, "    {diagnostic}")?;
+        }
+        Ok(())
+    }
+}
+impl Error for ShaderError {}
+
+//the files that went into some set of shaders, and when they were last modified at the time they got read
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ShaderSources {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+impl ShaderSources {
+    fn track(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        if self.files.iter().any(|(tracked, _)| *tracked == path) { return }
+        let modified = modified(&path);
+        self.files.push((path, modified));
+    }
+
+    pub fn changed(&self) -> bool {
+        self.files.iter().any(|(path, when)| modified(path) != *when)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Dialect { Glsl, Hlsl, Wgsl }
+
+impl Dialect {
+    pub fn of(path: &str) -> Dialect {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("hlsl") => Dialect::Hlsl,
+            Some("wgsl") => Dialect::Wgsl,
+            _ => Dialect::Glsl,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Dialect::Glsl => "glsl",
+            Dialect::Hlsl => "hlsl",
+            Dialect::Wgsl => "wgsl",
+        }
+    }
+}
+
+//lygia has every function in every language, same path, different extension.
+//so whatever gets included is swapped over to the dialect of the shader including it,
+//  which means `#include <lygia/color/hueShift.glsl>` works from HLSL and WGSL too. (lygia's own wgsl files rely on that)
+const LYGIA_EXTENSIONS: [&str; 5] = ["glsl", "hlsl", "wgsl", "msl", "cuh"];
+
+fn resolve_include(file: &str, standard: bool, parent: &str, dialect: Dialect) -> Result<ResolvedInclude, String> {
+    //generated from the shader_interface! in main.rs, never touches the disk. GLSL only.
+    if standard && file == "ember/interface.glsl" {
+        return match dialect {
+            Dialect::Glsl => Ok(ResolvedInclude { resolved_name: file.to_owned(), content: glsl_interface() }),
+            _ => Err(format!("<{file}> is GLSL, it can't be included from {parent}")),
+        }
+    }
+    let mut path = match standard {
+        false => Path::new(parent).parent().unwrap_or(Path::new("/")).join(file),
+        true => Path::new("src/lib").join(file),
+    };
+    if path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| LYGIA_EXTENSIONS.contains(&ext)) {
+        path.set_extension(dialect.extension());
+    }
+    let path = path.to_string_lossy().into_owned();
+    match fs::read_to_string(&path) {
+        Ok(content) => {
+            info!("Loading {path} as dependency of {parent}");
+            Ok(ResolvedInclude { resolved_name: path, content }) }
+        Err(e) => Err(format!("{path}: {e}")),
+    }
+}
+
+fn compile_shaderc(source: &str, path: &str, kind: shaderc::ShaderKind, dialect: Dialect, sources: &mut ShaderSources) -> Result<Vec<u32>, ShaderError> {
+    //declared before the options, since their include callback borrows it
+    let includes: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    let compiler = shaderc::Compiler::new().map_err(|e| shaderc_error(e, path))?;
+    let mut options = shaderc::CompileOptions::new().map_err(|e| shaderc_error(e, path))?;
+    if dialect == Dialect::Hlsl {
+        options.set_source_language(shaderc::SourceLanguage::HLSL);
+    }
+    //specify the entry point - here, it's "main"
+    options.add_macro_definition("EP", Some("main"));
+    options.set_include_callback(|file: &str, kind: IncludeType, parent: &str, _depth: usize| {
+        let include = resolve_include(file, kind == IncludeType::Standard, parent, dialect)?;
+        includes.borrow_mut().push(include.resolved_name.clone());
+        Ok(include)
+    });
+    let artifact = compiler.compile_into_spirv(source, kind, path, "main", Some(&options));
+    includes.borrow().iter().for_each(|include| sources.track(include));
+    Ok(artifact.map_err(|e| shaderc_error(e, path))?.as_binary().to_vec())
+}
+
+//glslang's log is one "file:line: error: message" per line, with a summary at the end
+fn shaderc_error(error: shaderc::Error, path: &str) -> ShaderError {
+    let shaderc::Error::CompilationError(_, log) = &error else {
+        return ShaderError::new(path, vec![Diagnostic { file: Some(path.to_owned()), line: None, message: error.to_string() }]) };
+    let diagnostics = log.lines()
+        .filter(|line| !line.trim().is_empty() && !line.ends_with(" generated."))
+        .map(|line| {
+            let mut parts = line.splitn(3, ':');
+            match (parts.next(), parts.next().and_then(|line| line.trim().parse().ok()), parts.next()) {
+                (Some(file), Some(line), Some(message)) =>
+                    Diagnostic { file: Some(file.to_owned()), line: Some(line), message: message.trim().to_owned() },
+                _ => Diagnostic { file: None, line: None, message: line.trim().to_owned() },
+            }
+        }).collect();
+    ShaderError::new(path, diagnostics)
+}
+
+fn compile_wgsl(source: &str, path: &str, kind: shaderc::ShaderKind, sources: &mut ShaderSources) -> Result<Vec<u32>, ShaderError> {
+    let fail = |line: Option<(String, u32)>, message: String| {
+        let (file, line) = match line {
+            Some((file, line)) => (file, Some(line)),
+            None => (path.to_owned(), None) };
+        ShaderError::new(path, vec![Diagnostic { file: Some(file), line, message }])
+    };
+    let stage = match kind {
+        shaderc::ShaderKind::Vertex => naga::ShaderStage::Vertex,
+        shaderc::ShaderKind::Fragment => naga::ShaderStage::Fragment,
+        shaderc::ShaderKind::Compute => naga::ShaderStage::Compute,
+        _ => return Err(fail(None, format!("{kind:?} shaders can't be written in WGSL"))),
+    };
+    let mut origins = Vec::new();
+    let mut included = HashSet::new();
+    let expanded = expand_wgsl(source, path, &mut included, &mut origins);
+    included.iter().for_each(|include| sources.track(include));
+    let source = expanded?;
+    //naga reports lines of the expanded source, this maps them back to where they came from
+    let origin = |location: Option<naga::SourceLocation>| location
+        .and_then(|location| origins.get(location.line_number as usize - 1).cloned());
+
+    let mut module = naga::front::wgsl::parse_str(&source)
+        .map_err(|e| fail(origin(e.location(&source)), e.message().to_owned()))?;
+
+    //a WGSL file can hold several entry points. we take the only one for this stage, or the one called main.
+    let candidates: Vec<usize> = module.entry_points.iter().enumerate()
+        .filter(|(_, entry_point)| entry_point.stage == stage)
+        .map(|(index, _)| index).collect();
+    let index = match candidates[..] {
+        [index] => index,
+        [] => return Err(fail(None, format!("no {stage:?} entry point"))),
+        _ => candidates.iter().copied().find(|&index| module.entry_points[index].name == "main")
+            .ok_or_else(|| fail(None, format!("several {stage:?} entry points, call the one to use \"main\"")))?,
+    };
+    module.entry_points[index].name = "main".to_owned();
+
+    let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+        .validate(&module)
+        .map_err(|e| fail(origin(e.location(&source)), chain(e.as_inner())))?;
+
+    //naga flips y by default to match wgpu's clip space. we're on plain vulkan, same as the GLSL shaders.
+    let mut options = naga::back::spv::Options::default();
+    options.flags.remove(naga::back::spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+    let pipeline_options = naga::back::spv::PipelineOptions { shader_stage: stage, entry_point: "main".to_owned() };
+    naga::back::spv::write_vec(&module, &info, &options, Some(&pipeline_options))
+        .map_err(|e| fail(None, e.to_string()))
+}
+
+//naga's validation errors nest, the useful part tends to be at the bottom
+fn chain(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(&format!(": {error}"));
+        source = error.source();
+    }
+    message
+}
+
+//WGSL has no preprocessor, but lygia's wgsl files still #include each other.
+//includes get pasted in textually, each file at most once (lygia has no include guards in WGSL).
+//any other directive is dropped, there's nothing they could mean here.
+//`origins` gets the file and line every line of the result came from.
+fn expand_wgsl(source: &str, path: &str, included: &mut HashSet<PathBuf>, origins: &mut Vec<(String, u32)>) -> Result<String, ShaderError> {
+    let mut expanded = String::with_capacity(source.len());
+    for (line_idx, line) in source.lines().enumerate() {
+        let origin = (path.to_owned(), line_idx as u32 + 1);
+        let trimmed = line.trim();
+        if let Some(target) = trimmed.strip_prefix("#include") {
+            let fail = |message: String| ShaderError::new(path,
+                vec![Diagnostic { file: Some(origin.0.clone()), line: Some(origin.1), message }]);
+            let target = target.trim();
+            let (file, standard) = if let Some(file) = target.strip_prefix('<').and_then(|file| file.strip_suffix('>')) {
+                (file, true)
+            } else if let Some(file) = target.strip_prefix('"').and_then(|file| file.strip_suffix('"')) {
+                (file, false)
+            } else {
+                return Err(fail(format!("malformed include \"{trimmed}\"")))
+            };
+            let include = resolve_include(file, standard, path, Dialect::Wgsl).map_err(fail)?;
+            let canonical = fs::canonicalize(&include.resolved_name).unwrap_or(PathBuf::from(&include.resolved_name));
+            if included.insert(canonical) {
+                expanded.push_str(&expand_wgsl(&include.content, &include.resolved_name, included, origins)?);
+            }
+        } else if trimmed.starts_with('#') {
+            debug!("{path}: ignoring \"{trimmed}\"");
+        } else {
+            expanded.push_str(line);
+        }
+        expanded.push('\n');
+        origins.push(origin);
+    }
+    Ok(expanded)
+}
@@ -70,8 +70,8 @@ impl PerSwapchain {
             image_color_space: color_space,
             image_extent: extent,
             image_array_layers: 1,
-            //for now, we'll only use the swapchain as a framebuffer color attachment
-            image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            //a color attachment, and the target of the feedback copy (see util::chain) if that's supported. without it there's no feedback.
+            image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | (capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_DST),
             //exclusive sharing between queue families has the best performance, but forces you to deal with ownership in between families if you use multiple ones.
             // we don't. we use one family without checking for presentation support. yay
             image_sharing_mode: vk::SharingMode::EXCLUSIVE,
@@ -0,0 +1,266 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use ash::{vk, Device};
+use log::{debug, warn};
+use crate::INSTANCE;
+use crate::util::helpers::{create_buffer, memory_type, submit_once};
+
+//images for passes to sample next to the capture, see [texture.*] in ember.conf.
+//  PNG and JPEG go through image, decoded to 8 bits per channel (16 for 16 bit PNGs, which LUTs tend to be).
+//  KTX2 (with the ktx2 feature) gets uploaded as is, in whatever format and with whatever mip levels it brings.
+//  everything else gets its mip chain blitted together on the GPU, if the format allows for it.
+//in device-local memory, uploaded once through a staging buffer. they're only ever sampled after that,
+//  so the render graph doesn't need to know about them.
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TextureDesc {
+    pub name: String,
+    pub path: String,
+    pub binding: u32,
+    //whether 8 bit images hold sRGB colors, rather than data like masks or noise
+    pub srgb: bool,
+    pub mipmaps: bool,
+    pub filter: vk::Filter,
+    pub wrap: vk::SamplerAddressMode,
+}
+
+pub(crate) struct Texture {
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub sampler: vk::Sampler,
+}
+
+#[derive(Debug)]
+pub(crate) struct TextureError(String);
+impl Display for TextureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Texture loading failed: {}", self.0)
+    }
+}
+impl Error for TextureError {}
+
+//what's in the file, tightly packed, largest mip level first
+pub(crate) struct Pixels {
+    pub format: vk::Format,
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl Pixels {
+    //for textures that don't load, so their passes still have something to sample
+    pub fn black() -> Pixels {
+        Pixels { format: vk::Format::R8G8B8A8_UNORM, width: 1, height: 1, levels: vec![vec![0, 0, 0, 255]] }
+    }
+}
+
+
+pub(crate) fn load(desc: &TextureDesc) -> Result<Pixels, TextureError> {
+    let path = desc.path.as_str();
+    let extension = Path::new(path).extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+    let pixels = match extension.as_str() {
+        "ktx2" => load_ktx2(path)?,
+        _ => load_image(path, desc.srgb)?,
+    };
+    debug!("Texture {path}: {}x{} {:?}, {} mip levels", pixels.width, pixels.height, pixels.format, pixels.levels.len());
+    Ok(pixels)
+}
+
+fn load_image(path: &str, srgb: bool) -> Result<Pixels, TextureError> {
+    let image = image::open(path).map_err(|e| TextureError(format!("{path}: {e}")))?;
+    let (width, height) = (image.width(), image.height());
+    let (format, data) = match image.color().bytes_per_pixel() / image.color().channel_count() {
+        1 => (if srgb { vk::Format::R8G8B8A8_SRGB } else { vk::Format::R8G8B8A8_UNORM }, image.into_rgba8().into_raw()),
+        _ => {
+            if srgb { warn!("Texture {path}: there's no sRGB format with 16 bits per channel, reading it as linear") }
+            let data = image.into_rgba16().into_raw();
+            (vk::Format::R16G16B16A16_UNORM, data.iter().flat_map(|channel| channel.to_ne_bytes()).collect()) }
+    };
+    Ok(Pixels { format, width, height, levels: vec![data] })
+}
+
+#[cfg(feature = "ktx2")]
+fn load_ktx2(path: &str) -> Result<Pixels, TextureError> {
+    let data = std::fs::read(path).map_err(|e| TextureError(format!("{path}: {e}")))?;
+    let reader = ktx2::Reader::new(&data[..]).map_err(|e| TextureError(format!("{path}: {e}")))?;
+    let header = reader.header();
+    if header.supercompression_scheme.is_some() {
+        return Err(TextureError(format!("{path}: supercompressed files aren't supported, only plain ones"))) }
+    if header.layer_count > 1 || header.face_count > 1 || header.pixel_depth > 1 {
+        return Err(TextureError(format!("{path}: only single 2D images are supported, no arrays, cubemaps or 3D textures"))) }
+    //the format is a plain VkFormat, unless it's left to the data format descriptor
+    let format = header.format.map(|format| vk::Format::from_raw(format.value() as i32))
+        .ok_or_else(|| TextureError(format!("{path}: no Vulkan format given")))?;
+    Ok(Pixels {
+        format,
+        width: header.pixel_width,
+        height: header.pixel_height.max(1),
+        levels: reader.levels().map(|level| level.data.to_vec()).collect() })
+}
+
+#[cfg(not(feature = "ktx2"))]
+fn load_ktx2(path: &str) -> Result<Pixels, TextureError> {
+    Err(TextureError(format!("{path}: built without KTX2 support, see the ktx2 feature")))
+}
+
+
+impl Texture {
+    //blocks until the upload is done, see submit_once. formats the device can't sample from are an error,
+    //  ones it can't blit between just don't get mipmaps.
+    pub unsafe fn upload(device: &Device, physical_device: vk::PhysicalDevice, queue: vk::Queue, command_pool: vk::CommandPool,
+                         desc: &TextureDesc, pixels: &Pixels) -> Result<Texture, TextureError> {
+        let features = INSTANCE.get_physical_device_format_properties(physical_device, pixels.format).optimal_tiling_features;
+        if !features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE) {
+            return Err(TextureError(format!("{}: the device can't sample from {:?}", desc.path, pixels.format))) }
+        let blittable = features.contains(vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR);
+        let full_chain = 32 - pixels.width.max(pixels.height).leading_zeros();
+        let generate = desc.mipmaps && pixels.levels.len() == 1 && full_chain > 1 && blittable;
+        if desc.mipmaps && pixels.levels.len() == 1 && !blittable {
+            warn!("Texture {}: can't generate mipmaps for {:?}, it gets sampled without", desc.path, pixels.format) }
+        let mip_levels = match (desc.mipmaps, generate) {
+            (_, true) => full_chain,
+            (true, false) => pixels.levels.len() as u32,
+            (false, false) => 1,
+        };
+
+        //every level at an offset that fits any texel or block size
+        let mut offsets: Vec<u64> = Vec::with_capacity(mip_levels as usize);
+        let mut size = 0;
+        for level in &pixels.levels[..mip_levels.min(pixels.levels.len() as u32) as usize] {
+            offsets.push(size);
+            size = (size + level.len() as u64).next_multiple_of(16);
+        }
+        let (staging, staging_memory) = create_buffer(device, physical_device, size, vk::BufferUsageFlags::TRANSFER_SRC,
+                                                      vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT);
+        let map = device.map_memory(staging_memory, 0, size, vk::MemoryMapFlags::empty()).unwrap().cast::<u8>();
+        for (level, offset) in pixels.levels.iter().zip(&offsets) {
+            std::ptr::copy_nonoverlapping(level.as_ptr(), map.add(*offset as usize), level.len());
+        }
+        device.unmap_memory(staging_memory);
+
+        let image_info = vk::ImageCreateInfo {
+            image_type: vk::ImageType::TYPE_2D,
+            format: pixels.format,
+            extent: vk::Extent3D { width: pixels.width, height: pixels.height, depth: 1 },
+            mip_levels,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST
+                | if generate { vk::ImageUsageFlags::TRANSFER_SRC } else { vk::ImageUsageFlags::empty() },
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            ..Default::default()};
+        let image = device.create_image(&image_info, None).unwrap();
+        let requirements = device.get_image_memory_requirements(image);
+        let allocate_info = vk::MemoryAllocateInfo {
+            allocation_size: requirements.size,
+            memory_type_index: memory_type(physical_device, requirements, vk::MemoryPropertyFlags::DEVICE_LOCAL),
+            ..Default::default()};
+        let memory = device.allocate_memory(&allocate_info, None).unwrap();
+        device.bind_image_memory(image, memory, 0).unwrap();
+
+        let extent = |level: u32| [(pixels.width >> level).max(1) as i32, (pixels.height >> level).max(1) as i32];
+        submit_once(device, queue, command_pool, |command_buffer| {
+            barrier(device, command_buffer, image, 0..mip_levels, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+            let regions: Vec<vk::BufferImageCopy> = offsets.iter().enumerate().map(|(level, offset)| {
+                let [width, height] = extent(level as u32);
+                vk::BufferImageCopy {
+                    buffer_offset: *offset,
+                    image_subresource: layers(level as u32),
+                    image_extent: vk::Extent3D { width: width as u32, height: height as u32, depth: 1 },
+                    ..Default::default()}
+            }).collect();
+            device.cmd_copy_buffer_to_image(command_buffer, staging, image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &regions);
+            if !generate {
+                barrier(device, command_buffer, image, 0..mip_levels, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+                return }
+            //every level gets blitted down from the one before it, which is done being written to by then
+            for level in 1..mip_levels {
+                barrier(device, command_buffer, image, level - 1..level, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+                let ([src_x, src_y], [dst_x, dst_y]) = (extent(level - 1), extent(level));
+                let blit = vk::ImageBlit {
+                    src_subresource: layers(level - 1),
+                    src_offsets: [vk::Offset3D::default(), vk::Offset3D { x: src_x, y: src_y, z: 1 }],
+                    dst_subresource: layers(level),
+                    dst_offsets: [vk::Offset3D::default(), vk::Offset3D { x: dst_x, y: dst_y, z: 1 }] };
+                device.cmd_blit_image(command_buffer, image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, image, vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                                      &[blit], vk::Filter::LINEAR);
+            }
+            barrier(device, command_buffer, image, 0..mip_levels - 1, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            barrier(device, command_buffer, image, mip_levels - 1..mip_levels, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        });
+        device.destroy_buffer(staging, None);
+        device.free_memory(staging_memory, None);
+
+        let view_info = vk::ImageViewCreateInfo {
+            image,
+            view_type: vk::ImageViewType::TYPE_2D,
+            format: pixels.format,
+            components: vk::ComponentMapping::default(),
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: mip_levels,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            ..Default::default()};
+        let view = device.create_image_view(&view_info, None).unwrap();
+
+        let sampler_info = vk::SamplerCreateInfo {
+            mag_filter: desc.filter,
+            min_filter: desc.filter,
+            mipmap_mode: match desc.filter {
+                vk::Filter::NEAREST => vk::SamplerMipmapMode::NEAREST,
+                _ => vk::SamplerMipmapMode::LINEAR,
+            },
+            address_mode_u: desc.wrap,
+            address_mode_v: desc.wrap,
+            address_mode_w: desc.wrap,
+            max_lod: vk::LOD_CLAMP_NONE,
+            border_color: vk::BorderColor::FLOAT_OPAQUE_BLACK,
+            ..Default::default()};
+        let sampler = device.create_sampler(&sampler_info, None).unwrap();
+
+        Ok(Texture { image, memory, view, sampler })
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        device.destroy_sampler(self.sampler, None);
+        device.destroy_image_view(self.view, None);
+        device.destroy_image(self.image, None);
+        device.free_memory(self.memory, None);
+    }
+}
+
+fn layers(mip_level: u32) -> vk::ImageSubresourceLayers {
+    vk::ImageSubresourceLayers { aspect_mask: vk::ImageAspectFlags::COLOR, mip_level, base_array_layer: 0, layer_count: 1 }
+}
+
+//the whole upload is in one command buffer that's waited on, so everything in there can just wait on all transfers
+unsafe fn barrier(device: &Device, command_buffer: vk::CommandBuffer, image: vk::Image, levels: std::ops::Range<u32>,
+                  old_layout: vk::ImageLayout, new_layout: vk::ImageLayout) {
+    if levels.is_empty() { return }
+    let image_barrier = vk::ImageMemoryBarrier {
+        src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+        dst_access_mask: vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::TRANSFER_WRITE | vk::AccessFlags::SHADER_READ,
+        old_layout, new_layout,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        image,
+        subresource_range: vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: levels.start,
+            level_count: levels.len() as u32,
+            base_array_layer: 0,
+            layer_count: 1,
+        },
+        ..Default::default()};
+    device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TRANSFER,
+                                vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                                vk::DependencyFlags::empty(), &[], &[], &[image_barrier]);
+}
@@ -0,0 +1,462 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::{mem, ptr, slice};
+use ash::{vk, Device};
+use log::warn;
+use crate::INSTANCE;
+use crate::util::helpers::{create_buffer, memory_type, submit_once};
+
+//getting data from the host into device-local memory.
+//  what's written once (meshes, textures, the quad) goes through upload_buffer, with a staging buffer of its own.
+//  what changes every frame lives in StagedBuffers: a device-local buffer per frame in flight, and a copy on the host.
+//  writes go to the host copy and mark what changed as dirty for every frame. Uploader::flush then copies the dirty ranges
+//  of the frame about to be rendered through a staging ring, on the transfer queue. the frame's submission waits for that.
+//images can be written through the ring as well. they're shared between frames (and windows), so those copies get recorded
+//  into the frame's own command buffer instead (see Uploader::record_images), where the graphics queue keeps them in order
+//  with whatever read the image before.
+
+const RING_SIZE: u64 = 4 << 20;
+//how finely writes get compared against what's there, see StagedBuffer::write
+const DIRTY_GRANULARITY: usize = 256;
+
+#[derive(Copy, Clone)]
+pub(crate) struct TransferQueue {
+    pub queue: vk::Queue,
+    pub family: u32,
+    //the graphics queue's, which reads whatever got uploaded
+    pub graphics_family: u32,
+}
+
+impl TransferQueue {
+    //a family that can only transfer is usually a copy engine of its own, which doesn't hold up rendering
+    pub unsafe fn dedicated_family(physical_device: vk::PhysicalDevice) -> Option<u32> {
+        INSTANCE.get_physical_device_queue_family_properties(physical_device).iter().position(|properties|
+            properties.queue_flags.contains(vk::QueueFlags::TRANSFER)
+            && !properties.queue_flags.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)).map(|family| family as u32)
+    }
+
+    fn families(&self) -> Vec<u32> {
+        if self.family == self.graphics_family { vec![self.family] } else { vec![self.family, self.graphics_family] }
+    }
+}
+
+
+//a device-local buffer with `data` in it, for data that's written once. blocks until it's there, see submit_once.
+pub(crate) unsafe fn upload_buffer(device: &Device, physical_device: vk::PhysicalDevice, queue: vk::Queue, command_pool: vk::CommandPool,
+                                   usage: vk::BufferUsageFlags, data: &[u8]) -> (vk::Buffer, vk::DeviceMemory) {
+    let (staging, staging_memory) = create_buffer(device, physical_device, data.len() as u64, vk::BufferUsageFlags::TRANSFER_SRC,
+                                                  vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT);
+    let map = device.map_memory(staging_memory, 0, data.len() as u64, vk::MemoryMapFlags::empty()).unwrap();
+    ptr::copy_nonoverlapping(data.as_ptr(), map.cast::<u8>(), data.len());
+    device.unmap_memory(staging_memory);
+
+    let (buffer, memory) = create_buffer(device, physical_device, data.len() as u64, usage | vk::BufferUsageFlags::TRANSFER_DST,
+                                         vk::MemoryPropertyFlags::DEVICE_LOCAL);
+    submit_once(device, queue, command_pool, |command_buffer|
+        device.cmd_copy_buffer(command_buffer, staging, buffer, &[vk::BufferCopy { src_offset: 0, dst_offset: 0, size: data.len() as u64 }]));
+
+    device.destroy_buffer(staging, None);
+    device.free_memory(staging_memory, None);
+    (buffer, memory)
+}
+
+
+pub(crate) struct StagedBuffer {
+    //one per frame in flight
+    pub buffers: Vec<vk::Buffer>,
+    memories: Vec<vk::DeviceMemory>,
+    host: Vec<u8>,
+    //per frame in flight, the ranges of `host` its buffer doesn't have yet. sorted, never overlapping.
+    dirty: Vec<Vec<Range<u64>>>,
+}
+
+impl StagedBuffer {
+    //zeroed. the first flush of every frame copies all of it, device-local memory starts out as whatever was there.
+    pub unsafe fn new(device: &Device, physical_device: vk::PhysicalDevice, transfer: &TransferQueue, size: u64, usage: vk::BufferUsageFlags,
+                      frames: usize) -> StagedBuffer {
+        let families = transfer.families();
+        let buffer_info = vk::BufferCreateInfo {
+            size,
+            usage: usage | vk::BufferUsageFlags::TRANSFER_DST,
+            //both queues touch it, without handing it back and forth
+            sharing_mode: if families.len() > 1 { vk::SharingMode::CONCURRENT } else { vk::SharingMode::EXCLUSIVE },
+            queue_family_index_count: families.len() as u32,
+            p_queue_family_indices: families.as_ptr(),
+            ..Default::default()};
+        let (mut buffers, mut memories) = (Vec::with_capacity(frames), Vec::with_capacity(frames));
+        for _ in 0..frames {
+            let buffer = device.create_buffer(&buffer_info, None).unwrap();
+            let requirements = device.get_buffer_memory_requirements(buffer);
+            let allocate_info = vk::MemoryAllocateInfo {
+                allocation_size: requirements.size,
+                memory_type_index: memory_type(physical_device, requirements, vk::MemoryPropertyFlags::DEVICE_LOCAL),
+                ..Default::default()};
+            let memory = device.allocate_memory(&allocate_info, None).unwrap();
+            device.bind_buffer_memory(buffer, memory, 0).unwrap();
+            buffers.push(buffer);
+            memories.push(memory);
+        }
+        StagedBuffer { buffers, memories, host: vec![0; size as usize], dirty: vec![vec![0..size]; frames] }
+    }
+
+    //only what actually changed gets marked dirty, compared in chunks of DIRTY_GRANULARITY.
+    //  rewriting a large array every frame costs a memcmp then, and only the parts that differ cross the bus.
+    pub fn write(&mut self, offset: u64, data: &[u8]) {
+        let start = offset as usize;
+        for (idx, chunk) in data.chunks(DIRTY_GRANULARITY).enumerate() {
+            let at = start + idx * DIRTY_GRANULARITY;
+            let host = &mut self.host[at..at + chunk.len()];
+            if host == chunk { continue }
+            host.copy_from_slice(chunk);
+            let range = at as u64..(at + chunk.len()) as u64;
+            self.dirty.iter_mut().for_each(|dirty| mark(dirty, range.clone()));
+        }
+    }
+
+    //a whole block, or a field of one at its offset_of
+    pub unsafe fn write_value<T>(&mut self, offset: u64, value: &T) {
+        self.write(offset, slice::from_raw_parts(ptr::from_ref(value).cast::<u8>(), size_of::<T>()));
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        self.buffers.iter().for_each(|buffer| device.destroy_buffer(*buffer, None));
+        self.memories.iter().for_each(|memory| device.free_memory(*memory, None));
+    }
+}
+
+//adds `range`, merging it with whatever it touches
+fn mark(dirty: &mut Vec<Range<u64>>, mut range: Range<u64>) {
+    let first = dirty.partition_point(|other| other.end < range.start);
+    let last = dirty.partition_point(|other| other.start <= range.end);
+    if first < last {
+        range.start = range.start.min(dirty[first].start);
+        range.end = range.end.max(dirty[last - 1].end);
+    }
+    dirty.splice(first..last, [range]);
+}
+
+
+struct ImageWrite {
+    image: vk::Image,
+    //what it's in before and after
+    layout: vk::ImageLayout,
+    copy: vk::BufferImageCopy,
+}
+
+pub(crate) struct Uploader {
+    transfer: TransferQueue,
+    command_pool: vk::CommandPool,
+    //per frame in flight
+    command_buffers: Vec<vk::CommandBuffer>,
+    semaphores: Vec<vk::Semaphore>,
+
+    ring: vk::Buffer,
+    ring_memory: vk::DeviceMemory,
+    ring_map: *mut u8,
+    ring_size: u64,
+    head: u64,
+    //the parts of the ring that are still being read from (or are about to be): (start, end, frame)
+    in_flight: VecDeque<(u64, u64, usize)>,
+    images: Vec<ImageWrite>,
+}
+
+impl Uploader {
+    pub unsafe fn new(device: &Device, physical_device: vk::PhysicalDevice, transfer: TransferQueue, frames: usize) -> Uploader {
+        let command_pool_info = vk::CommandPoolCreateInfo {
+            flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            queue_family_index: transfer.family,
+            ..Default::default()};
+        let command_pool = device.create_command_pool(&command_pool_info, None).unwrap();
+        let cmd_alloc_info = vk::CommandBufferAllocateInfo {
+            command_pool,
+            level: vk::CommandBufferLevel::PRIMARY,
+            command_buffer_count: frames as u32,
+            ..Default::default()};
+        let command_buffers = device.allocate_command_buffers(&cmd_alloc_info).unwrap();
+        let semaphores = (0..frames).map(|_| device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None).unwrap()).collect();
+        let (ring, ring_memory, ring_map) = create_ring(device, physical_device, RING_SIZE);
+        Uploader { transfer, command_pool, command_buffers, semaphores, ring, ring_memory, ring_map, ring_size: RING_SIZE,
+                   head: 0, in_flight: VecDeque::new(), images: Vec::new() }
+    }
+
+    //once the frame's fence got waited on. whatever it staged is done being read.
+    pub fn begin(&mut self, frame: usize) {
+        self.in_flight.retain(|(_, _, staged_by)| *staged_by != frame);
+    }
+
+    //room for `size` bytes in the ring, for `frame` to read from. when there's none, we wait for the device to finish
+    //  everything else, and if that's still not enough the ring grows.
+    unsafe fn stage(&mut self, device: &Device, physical_device: vk::PhysicalDevice, frame: usize, size: u64) -> u64 {
+        let start = match self.fit(size) {
+            Some(start) => start,
+            None => {
+                warn!("Staging ring of {} KiB is full, waiting for the device", self.ring_size >> 10);
+                device.device_wait_idle().unwrap();
+                //what this frame staged hasn't been submitted yet, so it has to stay
+                self.in_flight.retain(|(_, _, staged_by)| *staged_by == frame);
+                match self.fit(size) {
+                    Some(start) => start,
+                    None => {
+                        self.grow(device, physical_device, size);
+                        self.fit(size).unwrap() }
+                }
+            }
+        };
+        self.claim(start, size, frame);
+        start
+    }
+
+    //`size` bytes at `start` are `frame`'s to read from now
+    fn claim(&mut self, start: u64, size: u64, frame: usize) {
+        self.head = (start + size).next_multiple_of(16);
+        self.in_flight.push_back((start, start + size, frame));
+    }
+
+    //where `size` bytes would go, right after the head or wrapped around to the start. what's free goes from the head up
+    //  to whichever pending part comes next going around, wherever that is in in_flight: begin drops the parts of one
+    //  frame and keeps the others', and after grow the order's gone anyway.
+    fn fit(&self, size: u64) -> Option<u64> {
+        let tail = self.in_flight.iter().map(|&(start, _, _)| start).min_by_key(|&start| (start + self.ring_size - self.head) % self.ring_size);
+        let Some(tail) = tail else {
+            return (size <= self.ring_size).then_some(if self.head + size <= self.ring_size { self.head } else { 0 }) };
+        match tail < self.head {
+            true if self.head + size <= self.ring_size => Some(self.head),
+            true if size <= tail => Some(0),
+            false if self.head + size <= tail => Some(self.head),
+            _ => None,
+        }
+    }
+
+    //keeps whatever's in there at the same offsets. only after a device_wait_idle, nobody may be reading the old ring.
+    unsafe fn grow(&mut self, device: &Device, physical_device: vk::PhysicalDevice, size: u64) {
+        let ring_size = (self.ring_size + size).next_power_of_two();
+        warn!("Growing the staging ring to {} KiB", ring_size >> 10);
+        let (ring, ring_memory, ring_map) = create_ring(device, physical_device, ring_size);
+        ptr::copy_nonoverlapping(self.ring_map, ring_map, self.ring_size as usize);
+        device.destroy_buffer(self.ring, None);
+        device.free_memory(self.ring_memory, None);
+        (self.ring, self.ring_memory, self.ring_map) = (ring, ring_memory, ring_map);
+        self.regrown(ring_size);
+    }
+
+    //the old contents are at the start of a ring that's `ring_size` large now, anything new goes after them
+    fn regrown(&mut self, ring_size: u64) {
+        self.ring_size = ring_size;
+        self.head = self.in_flight.iter().map(|(_, end, _)| *end).max().unwrap_or(0).next_multiple_of(16);
+    }
+
+    //copies the dirty ranges of `frame`'s buffers on the transfer queue. the frame's submission has to wait on the
+    //  semaphore that comes back, at whichever stages read the buffers. None if there was nothing to copy.
+    pub unsafe fn flush(&mut self, device: &Device, physical_device: vk::PhysicalDevice, frame: usize, buffers: &mut [&mut StagedBuffer]) -> Option<vk::Semaphore> {
+        let size: u64 = buffers.iter().flat_map(|buffer| &buffer.dirty[frame]).map(|range| range.end - range.start).sum();
+        if size == 0 { return None }
+        let mut offset = self.stage(device, physical_device, frame, size);
+
+        let command_buffer = self.command_buffers[frame];
+        device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty()).unwrap();
+        let begin_info = vk::CommandBufferBeginInfo {
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            ..Default::default()};
+        device.begin_command_buffer(command_buffer, &begin_info).unwrap();
+        for buffer in buffers.iter_mut() {
+            let dirty = mem::take(&mut buffer.dirty[frame]);
+            if dirty.is_empty() { continue }
+            let copies: Vec<vk::BufferCopy> = dirty.into_iter().map(|range| {
+                let size = range.end - range.start;
+                ptr::copy_nonoverlapping(buffer.host[range.start as usize..].as_ptr(), self.ring_map.add(offset as usize), size as usize);
+                let copy = vk::BufferCopy { src_offset: offset, dst_offset: range.start, size };
+                offset += size;
+                copy
+            }).collect();
+            device.cmd_copy_buffer(command_buffer, self.ring, buffer.buffers[frame], &copies);
+        }
+        device.end_command_buffer(command_buffer).unwrap();
+
+        //the semaphore wait on the other end makes the copies visible, the ring is coherent
+        let submit_info = vk::SubmitInfo {
+            command_buffer_count: 1,
+            p_command_buffers: &command_buffer,
+            signal_semaphore_count: 1,
+            p_signal_semaphores: &self.semaphores[frame],
+            ..Default::default()};
+        device.queue_submit(self.transfer.queue, &[submit_info], vk::Fence::null()).unwrap();
+        Some(self.semaphores[frame])
+    }
+
+    //stages a write into part of an image that's in `layout`, and stays there. `copy` says where, its buffer offset is ours.
+    //  lands once record_images got called with the frame's command buffer.
+    pub unsafe fn write_image(&mut self, device: &Device, physical_device: vk::PhysicalDevice, frame: usize, image: vk::Image,
+                              layout: vk::ImageLayout, copy: vk::BufferImageCopy, data: &[u8]) {
+        let offset = self.stage(device, physical_device, frame, data.len() as u64);
+        ptr::copy_nonoverlapping(data.as_ptr(), self.ring_map.add(offset as usize), data.len());
+        self.images.push(ImageWrite { image, layout, copy: vk::BufferImageCopy { buffer_offset: offset, ..copy } });
+    }
+
+    //before anything else in the frame. whatever sampled or wrote the images before gets waited on, whatever comes after waits on the copies.
+    pub unsafe fn record_images(&mut self, device: &Device, command_buffer: vk::CommandBuffer) {
+        let shaders = vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER;
+        for write in self.images.drain(..) {
+            let barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| vk::ImageMemoryBarrier {
+                src_access_mask, dst_access_mask, old_layout, new_layout,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image: write.image,
+                subresource_range: vk::ImageSubresourceRange {
+                    aspect_mask: write.copy.image_subresource.aspect_mask,
+                    base_mip_level: write.copy.image_subresource.mip_level,
+                    level_count: 1,
+                    base_array_layer: write.copy.image_subresource.base_array_layer,
+                    layer_count: write.copy.image_subresource.layer_count,
+                },
+                ..Default::default()};
+            device.cmd_pipeline_barrier(command_buffer, shaders | vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::TRANSFER,
+                                        vk::DependencyFlags::empty(), &[], &[], &[barrier(write.layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                                        vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::TRANSFER_WRITE)]);
+            device.cmd_copy_buffer_to_image(command_buffer, self.ring, write.image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[write.copy]);
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TRANSFER, shaders,
+                                        vk::DependencyFlags::empty(), &[], &[], &[barrier(vk::ImageLayout::TRANSFER_DST_OPTIMAL, write.layout,
+                                        vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::SHADER_READ)]);
+        }
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        self.semaphores.iter().for_each(|semaphore| device.destroy_semaphore(*semaphore, None));
+        //takes the command buffers with it
+        device.destroy_command_pool(self.command_pool, None);
+        device.destroy_buffer(self.ring, None);
+        device.free_memory(self.ring_memory, None);
+    }
+}
+
+//persistently mapped, and coherent so there's nothing to flush
+unsafe fn create_ring(device: &Device, physical_device: vk::PhysicalDevice, size: u64) -> (vk::Buffer, vk::DeviceMemory, *mut u8) {
+    let (ring, ring_memory) = create_buffer(device, physical_device, size, vk::BufferUsageFlags::TRANSFER_SRC,
+                                            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT);
+    let ring_map = device.map_memory(ring_memory, 0, size, vk::MemoryMapFlags::empty()).unwrap().cast::<u8>();
+    (ring, ring_memory, ring_map)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //just the bookkeeping, there's no device behind any of it
+    fn uploader(ring_size: u64) -> Uploader {
+        Uploader {
+            transfer: TransferQueue { queue: vk::Queue::null(), family: 0, graphics_family: 0 },
+            command_pool: vk::CommandPool::null(),
+            command_buffers: Vec::new(),
+            semaphores: Vec::new(),
+            ring: vk::Buffer::null(),
+            ring_memory: vk::DeviceMemory::null(),
+            ring_map: ptr::null_mut(),
+            ring_size,
+            head: 0,
+            in_flight: VecDeque::new(),
+            images: Vec::new() }
+    }
+
+    //ranges as (start, end), what's dirty before and after marking one
+    type MarkCase = (&'static [(u64, u64)], (u64, u64), &'static [(u64, u64)]);
+
+    #[test]
+    fn mark_merges() {
+        let cases: [MarkCase; 9] = [
+            (&[], (0, 10), &[(0, 10)]),
+            //adjacent on either side
+            (&[(0, 10)], (10, 20), &[(0, 20)]),
+            (&[(10, 20)], (0, 10), &[(0, 20)]),
+            //overlapping, and swallowed whole
+            (&[(0, 10)], (5, 15), &[(0, 15)]),
+            (&[(0, 100)], (20, 30), &[(0, 100)]),
+            //bridging the gap between two
+            (&[(0, 10), (20, 30), (40, 50)], (10, 20), &[(0, 30), (40, 50)]),
+            (&[(0, 10), (20, 30), (40, 50)], (5, 45), &[(0, 50)]),
+            //or not touching anything, in order
+            (&[(0, 10), (30, 40)], (15, 20), &[(0, 10), (15, 20), (30, 40)]),
+            (&[(10, 20)], (0, 5), &[(0, 5), (10, 20)]),
+        ];
+        for (dirty, (start, end), expected) in cases {
+            let mut marked = dirty.iter().map(|&(start, end)| start..end).collect();
+            mark(&mut marked, start..end);
+            assert_eq!(marked, expected.iter().map(|&(start, end)| start..end).collect::<Vec<_>>(), "{dirty:?} + {start}..{end}");
+        }
+    }
+
+    #[test]
+    fn fit_empty() {
+        let mut uploader = uploader(1024);
+        assert_eq!(uploader.fit(1024), Some(0));
+        assert_eq!(uploader.fit(1025), None);
+        //nothing pending, so there's room at the start even if there's none after the head
+        uploader.head = 1000;
+        assert_eq!(uploader.fit(100), Some(0));
+        assert_eq!(uploader.fit(24), Some(1000));
+    }
+
+    #[test]
+    fn fit_wraps_around() {
+        let mut uploader = uploader(1024);
+        uploader.claim(0, 400, 0);
+        uploader.claim(400, 400, 1);
+        assert_eq!(uploader.head, 800);
+        //frame 0's part is still being read
+        assert_eq!(uploader.fit(300), None);
+        assert_eq!(uploader.fit(224), Some(800));
+
+        uploader.begin(0);
+        //doesn't fit after the head, but does before frame 1's part
+        assert_eq!(uploader.fit(300), Some(0));
+        assert_eq!(uploader.fit(400), Some(0));
+        assert_eq!(uploader.fit(401), None);
+        uploader.claim(0, 300, 0);
+        //between the head and frame 1's part, and nowhere else
+        assert_eq!(uploader.head, 304);
+        assert_eq!(uploader.fit(96), Some(304));
+        assert_eq!(uploader.fit(97), None);
+    }
+
+    #[test]
+    fn fit_out_of_order() {
+        let mut uploader = uploader(1024);
+        uploader.claim(0, 300, 0);
+        uploader.claim(304, 300, 1);
+        uploader.claim(608, 300, 2);
+        uploader.begin(0);
+        uploader.claim(0, 200, 0);
+        //frame 1's part goes, the ones before and after it in the queue stay. the queue's [608, 0] now,
+        //  and what's next after the head going around is 608, not the smallest start
+        uploader.begin(1);
+        assert_eq!(uploader.in_flight.iter().map(|&(start, _, _)| start).collect::<Vec<_>>(), [608, 0]);
+        assert_eq!(uploader.head, 208);
+        assert_eq!(uploader.fit(400), Some(208));
+        assert_eq!(uploader.fit(401), None);
+        //with frame 2's gone, it's the one at 0 that comes next, all the way around
+        uploader.begin(2);
+        assert_eq!(uploader.fit(816), Some(208));
+        assert_eq!(uploader.fit(817), None);
+    }
+
+    #[test]
+    fn fit_after_grow() {
+        let mut uploader = uploader(1024);
+        uploader.claim(0, 300, 0);
+        uploader.claim(304, 300, 1);
+        uploader.claim(608, 400, 2);
+        uploader.begin(0);
+        uploader.claim(0, 200, 0);
+        //the queue's [304, 608, 0], the head right after the part at 0. growing puts it after the last part in the ring
+        //  instead, which leaves the one at 0 as the next going around, last in the queue.
+        uploader.regrown(2048);
+        assert_eq!(uploader.head, 1008);
+        assert_eq!(uploader.fit(1040), Some(1008));
+        assert_eq!(uploader.fit(1041), None);
+        //no room before the part at 0 either
+        uploader.begin(1);
+        uploader.begin(2);
+        assert_eq!(uploader.fit(1041), None);
+        uploader.begin(0);
+        assert_eq!(uploader.fit(1041), Some(0));
+    }
+}